@@ -0,0 +1,199 @@
+use crate::progress::Monitor;
+use crate::registry;
+use crate::types::*;
+use anyhow::Result;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Audit log used when read-only mode is enabled without an explicit path
+pub const DEFAULT_AUDIT_LOG: &str = "regfix_audit.jsonl";
+
+const TOOL_NAME: &str = env!("CARGO_PKG_NAME");
+const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Serializes appends from threads that share a log, such as the scan workers
+static APPEND_LOCK: Mutex<()> = Mutex::new(());
+
+/// SHA-256 digests of a file taken before and after an operation. There is
+/// no after digest when the operation failed before writing its output.
+#[derive(Debug, Clone)]
+pub struct FileHashes {
+    pub before: String,
+    pub after: Option<String>,
+}
+
+impl FileHashes {
+    pub fn unchanged(&self) -> bool {
+        self.after.as_ref() == Some(&self.before)
+    }
+}
+
+/// Returns the lowercase hex SHA-256 digest of a file
+pub fn hash_file(file_path: &str) -> Result<String> {
+    let mut file = File::open(file_path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Append-only JSON lines log of every analysis and fix action
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, action: &str, mut entry: Value) -> Result<()> {
+        if let Value::Object(fields) = &mut entry {
+            fields.insert("timestamp".to_string(), json!(chrono::Utc::now().to_rfc3339()));
+            fields.insert("tool".to_string(), json!(TOOL_NAME));
+            fields.insert("tool_version".to_string(), json!(TOOL_VERSION));
+            fields.insert("action".to_string(), json!(action));
+            fields.insert("read_only".to_string(), json!(registry::is_read_only()));
+        }
+
+        // The log is only ever opened in append mode so earlier records are
+        // never rewritten, and each record goes out in a single write so
+        // records cannot interleave
+        let line = format!("{}\n", entry);
+        let _guard = APPEND_LOCK.lock().unwrap();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    pub fn record_analysis(
+        &self,
+        file_path: &str,
+        hashes: &FileHashes,
+        result: &Result<AnalysisResult>,
+    ) -> Result<()> {
+        let outcome = match result {
+            Ok(analysis) => json!({
+                "status": "ok",
+                "issues": analysis.issues.iter().map(|issue| json!({
+                    "code": issue.code.as_str(),
+                    "severity": issue.severity.to_string(),
+                    "message": issue.message,
+                    "details": issue.details,
+                    "location": issue.location.as_ref().map(|l| l.to_json()),
+                })).collect::<Vec<_>>(),
+            }),
+            Err(e) => json!({ "status": "error", "error": e.to_string() }),
+        };
+
+        self.append("analysis", json!({
+            "file": file_path,
+            "sha256_before": hashes.before,
+            "sha256_after": hashes.after,
+            "unchanged": hashes.unchanged(),
+            "outcome": outcome,
+        }))
+    }
+
+    pub fn record_fix(
+        &self,
+        file_path: &str,
+        output_path: Option<&str>,
+        fixes: &[FixType],
+        hashes: &FileHashes,
+        outcome: &str,
+    ) -> Result<()> {
+        self.append("fix", json!({
+            "file": file_path,
+            "output": output_path,
+            "fixes": fixes.iter().map(|f| format!("{:?}", f)).collect::<Vec<_>>(),
+            "sha256_before": hashes.before,
+            "sha256_after": hashes.after,
+            "outcome": outcome,
+        }))
+    }
+
+    /// Records an operation that changes a hive in place or into `output_path`
+    pub fn record_change(
+        &self,
+        action: &str,
+        file_path: &str,
+        output_path: Option<&str>,
+        hashes: &FileHashes,
+        outcome: &str,
+    ) -> Result<()> {
+        self.append(action, json!({
+            "file": file_path,
+            "output": output_path,
+            "sha256_before": hashes.before,
+            "sha256_after": hashes.after,
+            "outcome": outcome,
+        }))
+    }
+
+    /// Records an operation that reads one or more hives and may produce a new
+    /// file, hashing every input and the output
+    pub fn record_operation(
+        &self,
+        operation: &str,
+        inputs: &[String],
+        output: Option<&str>,
+        outcome: &str,
+    ) -> Result<()> {
+        let inputs: Vec<Value> = inputs.iter()
+            .map(|path| json!({ "file": path, "sha256": hash_file(path).ok() }))
+            .collect();
+        let output = output.map(|path| json!({ "file": path, "sha256": hash_file(path).ok() }));
+
+        self.append(operation, json!({
+            "inputs": inputs,
+            "output": output,
+            "outcome": outcome,
+        }))
+    }
+}
+
+/// Runs `check_registry_file`, hashing the input before and after when an
+/// audit log is supplied and recording the analysis in it.
+pub fn analyze(file_path: &str, log: Option<&AuditLog>) -> Result<(AnalysisResult, Option<FileHashes>)> {
+    analyze_with(file_path, log, &Monitor::default())
+}
+
+/// `analyze` reporting its progress to `monitor`. A cancelled analysis is
+/// logged as failed.
+pub fn analyze_with(file_path: &str, log: Option<&AuditLog>, monitor: &Monitor) -> Result<(AnalysisResult, Option<FileHashes>)> {
+    let Some(log) = log else {
+        return Ok((registry::check_registry_file_with(file_path, monitor)?, None));
+    };
+
+    let before = hash_file(file_path)?;
+    let result = registry::check_registry_file_with(file_path, monitor);
+    let hashes = FileHashes {
+        before,
+        after: Some(hash_file(file_path)?),
+    };
+
+    log.record_analysis(file_path, &hashes, &result)?;
+    Ok((result?, Some(hashes)))
+}
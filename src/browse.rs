@@ -0,0 +1,254 @@
+use crate::hive::{self, Hive, KeyNode, NO_CELL};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// A key as shown in the tree, before its contents are read
+#[derive(Debug, Clone)]
+pub struct KeyEntry {
+    pub offset: u32,
+    pub name: String,
+    /// Path relative to the root
+    pub path: String,
+    pub has_subkeys: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ValueRow {
+    pub name: String,
+    pub data_type: u32,
+    /// Size recorded in the value record
+    pub size: u32,
+    /// None when the data cannot be read
+    pub data: Option<Vec<u8>>,
+    pub problem: Option<String>,
+}
+
+impl ValueRow {
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() { "(Default)" } else { &self.name }
+    }
+
+    pub fn display_data(&self) -> String {
+        match &self.data {
+            Some(data) => hive::format_value_data(self.data_type, data),
+            None => "<unreadable>".to_string(),
+        }
+    }
+}
+
+/// Everything shown for a selected or expanded key
+#[derive(Debug, Clone, Default)]
+pub struct KeyContents {
+    pub last_written: u64,
+    pub class_name: Option<String>,
+    pub subkeys: Vec<KeyEntry>,
+    pub values: Vec<ValueRow>,
+    /// Problems with the key itself: its lists, security cell or subkeys
+    pub problems: Vec<String>,
+}
+
+/// Reads a hive's key tree on demand, tolerating damaged cells. Which keys
+/// are damaged is worked out once up front so problems can be flagged on
+/// collapsed branches; everything else is read when a key is expanded.
+#[derive(Debug)]
+pub struct HiveBrowser {
+    hive: Hive,
+    pub path: String,
+    pub root: KeyEntry,
+    /// Keys with problems of their own
+    damaged: HashSet<u32>,
+    /// Keys with a damaged key somewhere below them
+    damaged_below: HashSet<u32>,
+    contents: HashMap<u32, Arc<KeyContents>>,
+}
+
+/// How a key is flagged in the tree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyHealth {
+    Healthy,
+    Damaged,
+    DamagedBelow,
+}
+
+// Problems with a key's own structures, without reading its subkeys
+fn key_problems(hive: &Hive, key: &KeyNode) -> Vec<String> {
+    let mut problems = Vec::new();
+    match hive.cell_data(key.security) {
+        Ok(data) if data.get(0..2) == Some(b"sk") => {}
+        Ok(_) => problems.push(format!("Security cell 0x{:X} is not an sk record", key.security)),
+        Err(e) => problems.push(format!("Security cell: {}", e)),
+    }
+    if let Err(e) = hive.class_name(key) {
+        problems.push(format!("Class name: {}", e));
+    }
+    match hive.value_offsets(key) {
+        Ok(offsets) => {
+            for offset in offsets {
+                let problem = hive.value(offset).and_then(|value| hive.value_data(&value).map(|_| ()));
+                if let Err(e) = problem {
+                    problems.push(format!("Value at 0x{:X}: {}", offset, e));
+                }
+            }
+        }
+        Err(e) => problems.push(format!("Value list: {}", e)),
+    }
+    problems
+}
+
+// Offsets of a key's subkeys, with a description of what is wrong with the list
+fn subkey_offsets(hive: &Hive, key: &KeyNode) -> (Vec<u32>, Option<String>) {
+    if key.subkey_count == 0 || key.subkey_list == NO_CELL {
+        return (Vec::new(), None);
+    }
+    match hive.subkey_offsets(key.subkey_list) {
+        Ok(offsets) if offsets.len() != key.subkey_count as usize => {
+            let problem = format!("Declares {} subkeys but its list holds {}", key.subkey_count, offsets.len());
+            (offsets, Some(problem))
+        }
+        Ok(offsets) => (offsets, None),
+        Err(e) => (Vec::new(), Some(format!("Subkey list: {}", e))),
+    }
+}
+
+impl HiveBrowser {
+    pub fn open(file_path: &str) -> Result<Self> {
+        let hive = Hive::open(file_path)?;
+        let root_offset = hive.root_cell_offset();
+        let root = KeyEntry {
+            offset: root_offset,
+            name: hive.key(root_offset).map(|k| k.name).unwrap_or_default(),
+            path: String::new(),
+            has_subkeys: true,
+        };
+        let mut browser = Self {
+            hive,
+            path: file_path.to_string(),
+            root,
+            damaged: HashSet::new(),
+            damaged_below: HashSet::new(),
+            contents: HashMap::new(),
+        };
+        let mut seen = HashSet::new();
+        browser.find_damage(root_offset, 0, &mut seen);
+        Ok(browser)
+    }
+
+    // Returns whether the key or anything below it is damaged
+    fn find_damage(&mut self, offset: u32, depth: usize, seen: &mut HashSet<u32>) -> bool {
+        if depth > hive::MAX_DEPTH || !seen.insert(offset) {
+            self.damaged.insert(offset);
+            return true;
+        }
+        let Ok(key) = self.hive.key(offset) else {
+            self.damaged.insert(offset);
+            return true;
+        };
+        let (children, list_problem) = subkey_offsets(&self.hive, &key);
+        let mut damaged = list_problem.is_some() || !key_problems(&self.hive, &key).is_empty();
+        let mut below = false;
+        for child in children {
+            if self.hive.key(child).is_err() {
+                damaged = true;
+            } else if self.find_damage(child, depth + 1, seen) {
+                below = true;
+            }
+        }
+        if damaged {
+            self.damaged.insert(offset);
+        }
+        if below {
+            self.damaged_below.insert(offset);
+        }
+        damaged || below
+    }
+
+    pub fn health(&self, offset: u32) -> KeyHealth {
+        if self.damaged.contains(&offset) {
+            KeyHealth::Damaged
+        } else if self.damaged_below.contains(&offset) {
+            KeyHealth::DamagedBelow
+        } else {
+            KeyHealth::Healthy
+        }
+    }
+
+    /// The entry for the key at `path`, if it can still be reached
+    pub fn find(&self, path: &str) -> Option<KeyEntry> {
+        let key = self.hive.find_key(path).ok()?;
+        Some(KeyEntry {
+            offset: key.offset,
+            path: path.to_string(),
+            has_subkeys: key.subkey_count > 0 && key.subkey_list != NO_CELL,
+            name: key.name,
+        })
+    }
+
+    pub fn damaged_count(&self) -> usize {
+        self.damaged.len()
+    }
+
+    /// Subkeys, values and problems of a key, read the first time they are asked for
+    pub fn contents(&mut self, entry: &KeyEntry) -> Arc<KeyContents> {
+        if let Some(contents) = self.contents.get(&entry.offset) {
+            return contents.clone();
+        }
+        let contents = Arc::new(self.read_contents(entry));
+        self.contents.insert(entry.offset, contents.clone());
+        contents
+    }
+
+    fn read_contents(&self, entry: &KeyEntry) -> KeyContents {
+        let key = match self.hive.key(entry.offset) {
+            Ok(key) => key,
+            Err(e) => return KeyContents { problems: vec![e.to_string()], ..Default::default() },
+        };
+        let mut problems = key_problems(&self.hive, &key);
+        let (children, list_problem) = subkey_offsets(&self.hive, &key);
+        problems.extend(list_problem);
+
+        let mut subkeys = Vec::new();
+        for offset in children {
+            match self.hive.key(offset) {
+                Ok(child) => subkeys.push(KeyEntry {
+                    offset,
+                    path: hive::join_path(&entry.path, &child.name),
+                    has_subkeys: child.subkey_count > 0 && child.subkey_list != NO_CELL,
+                    name: child.name,
+                }),
+                Err(e) => problems.push(format!("Subkey at 0x{:X}: {}", offset, e)),
+            }
+        }
+        subkeys.sort_by(|a, b| hive::compare_names(&a.name, &b.name));
+
+        let values = self.hive.value_offsets(&key).unwrap_or_default().into_iter()
+            .map(|offset| match self.hive.value(offset) {
+                Ok(value) => {
+                    let data = self.hive.value_data(&value);
+                    ValueRow {
+                        size: value.data_size(),
+                        problem: data.as_ref().err().map(|e| e.to_string()),
+                        data: data.ok(),
+                        data_type: value.data_type,
+                        name: value.name,
+                    }
+                }
+                Err(e) => ValueRow {
+                    name: format!("<value at 0x{:X}>", offset),
+                    data_type: hive::REG_NONE,
+                    size: 0,
+                    data: None,
+                    problem: Some(e.to_string()),
+                },
+            })
+            .collect();
+
+        KeyContents {
+            last_written: key.last_written,
+            class_name: self.hive.class_name(&key).ok().flatten(),
+            subkeys,
+            values,
+            problems,
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use crate::hive::{self, Hive, ValueKey, BASE_BLOCK_SIZE, NO_CELL, REG_QWORD};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Record headers, without names or descriptors
+const KEY_HEADER: usize = 76;
+const VALUE_HEADER: usize = 20;
+const SECURITY_HEADER: usize = 20;
+const BIG_DATA_HEADER: usize = 8;
+
+// FILETIMEs of 1990-01-01 and 2100-01-01; anything outside is implausible
+const EARLIEST_FILETIME: u64 = 0x01B4_1E2A_18D6_4000;
+const LATEST_FILETIME: u64 = 0x022F_7163_7764_0000;
+
+/// Where in the hive a carved record was found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    /// Inside a free cell
+    FreeCell,
+    /// In the unused tail of an allocated cell
+    Slack,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Region::FreeCell => write!(f, "free"),
+            Region::Slack => write!(f, "slack"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Confidence::Low => write!(f, "low"),
+            Confidence::Medium => write!(f, "medium"),
+            Confidence::High => write!(f, "high"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CarvedItem {
+    Key {
+        name: String,
+        last_written: u64,
+        parent: u32,
+        subkey_count: u32,
+        value_count: u32,
+        value_list: u32,
+    },
+    Value {
+        value: ValueKey,
+        /// Data when its cells could still be read
+        data: Option<Vec<u8>>,
+    },
+    Security {
+        reference_count: u32,
+        descriptor_size: u32,
+        owner: Option<String>,
+    },
+    BigData {
+        segment_count: u16,
+        segment_list: u32,
+    },
+}
+
+/// A deleted record recovered from free space
+#[derive(Debug, Clone)]
+pub struct CarvedRecord {
+    /// Offset of the record's signature, relative to the hive bins data
+    pub offset: u32,
+    /// Cell the record was found in
+    pub cell: u32,
+    pub region: Region,
+    pub item: CarvedItem,
+    pub confidence: Confidence,
+    /// Reconstructed key path (keys) or owning key path (values)
+    pub path: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl CarvedRecord {
+    pub fn kind(&self) -> &'static str {
+        match self.item {
+            CarvedItem::Key { .. } => "key",
+            CarvedItem::Value { .. } => "value",
+            CarvedItem::Security { .. } => "security",
+            CarvedItem::BigData { .. } => "big data",
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        match &self.item {
+            CarvedItem::Key { name, last_written, subkey_count, value_count, .. } => format!(
+                "{} (last written {}, {} subkey(s), {} value(s))",
+                self.path.as_deref().unwrap_or(name), hive::format_filetime(*last_written), subkey_count, value_count
+            ),
+            CarvedItem::Value { value, data } => {
+                let name = if value.name.is_empty() { "(Default)" } else { &value.name };
+                let data = match data {
+                    Some(data) => hive::format_value_data(value.data_type, data),
+                    None => "<data not recoverable>".to_string(),
+                };
+                let owner = self.path.as_ref().map(|p| format!(" in {}", display_path(p))).unwrap_or_default();
+                format!("{}{} {} = {}", name, owner, hive::value_type_name(value.data_type), data)
+            }
+            CarvedItem::Security { reference_count, descriptor_size, owner } => format!(
+                "owner {}, {} reference(s), {} byte descriptor",
+                owner.as_deref().unwrap_or("?"), reference_count, descriptor_size
+            ),
+            CarvedItem::BigData { segment_count, segment_list } => format!(
+                "{} segment(s), segment list at 0x{:X}", segment_count, segment_list
+            ),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "offset": self.offset,
+            "file_offset": self.offset as usize + BASE_BLOCK_SIZE,
+            "cell": self.cell,
+            "region": self.region.to_string(),
+            "type": self.kind(),
+            "confidence": self.confidence.to_string(),
+            "path": self.path,
+            "notes": self.notes,
+        });
+        let fields = match &self.item {
+            CarvedItem::Key { name, last_written, parent, subkey_count, value_count, .. } => json!({
+                "name": name,
+                "last_written": hive::format_filetime(*last_written),
+                "parent": parent,
+                "subkey_count": subkey_count,
+                "value_count": value_count,
+            }),
+            CarvedItem::Value { value, data } => json!({
+                "name": value.name,
+                "data_type": hive::value_type_name(value.data_type),
+                "data_size": value.data_size(),
+                "data": data.as_ref().map(|d| hive::format_value_data(value.data_type, d)),
+                "data_hex": data.as_ref().map(|d| d.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+            }),
+            CarvedItem::Security { reference_count, descriptor_size, owner } => json!({
+                "reference_count": reference_count,
+                "descriptor_size": descriptor_size,
+                "owner": owner,
+            }),
+            CarvedItem::BigData { segment_count, segment_list } => json!({
+                "segment_count": segment_count,
+                "segment_list": segment_list,
+            }),
+        };
+        if let (Value::Object(record), Value::Object(fields)) = (&mut record, fields) {
+            record.extend(fields);
+        }
+        record
+    }
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() { "(root)" } else { path }
+}
+
+fn plausible_time(filetime: u64) -> bool {
+    (EARLIEST_FILETIME..=LATEST_FILETIME).contains(&filetime)
+}
+
+fn plausible_offset(hive: &Hive, offset: u32) -> bool {
+    offset.is_multiple_of(8) && offset < hive.bins_len()
+}
+
+fn plausible_name(name: &str) -> bool {
+    !name.chars().any(|c| c.is_control() || c == '\u{FFFD}')
+}
+
+fn is_signature(hive: &Hive, offset: u32, signature: &[u8]) -> bool {
+    hive.cell_payload(offset).map(|d| d.get(0..2) == Some(signature)).unwrap_or(false)
+}
+
+/// Textual form (S-1-5-...) of the owner SID of a self-relative security descriptor
+fn owner_sid(descriptor: &[u8]) -> Option<String> {
+    let owner = hive::read_u32(descriptor.get(0..8)?, 4) as usize;
+    if owner == 0 {
+        return None;
+    }
+    let sid = descriptor.get(owner..owner + 8)?;
+    let count = sid[1] as usize;
+    let sid = descriptor.get(owner..owner + 8 + count * 4)?;
+    let authority = sid[2..8].iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+    let mut text = format!("S-{}-{}", sid[0], authority);
+    for i in 0..count {
+        text.push_str(&format!("-{}", hive::read_u32(sid, 8 + i * 4)));
+    }
+    Some(text)
+}
+
+// Decoders return the record and its length, or None when the bytes are not a
+// plausible record of that type
+
+fn carve_key(hive: &Hive, bytes: &[u8]) -> Option<(CarvedItem, Confidence, Vec<String>, usize)> {
+    if bytes.len() < KEY_HEADER {
+        return None;
+    }
+    let flags = hive::read_u16(bytes, 2);
+    let name_length = hive::read_u16(bytes, 72) as usize;
+    if name_length == 0 || KEY_HEADER + name_length > bytes.len() {
+        return None;
+    }
+    let name = hive::decode_name(&bytes[KEY_HEADER..KEY_HEADER + name_length], flags & hive::KEY_COMP_NAME != 0);
+    let parent = hive::read_u32(bytes, 16);
+    if !plausible_name(&name) || !plausible_offset(hive, parent) {
+        return None;
+    }
+
+    let last_written = hive::read_u64(bytes, 4);
+    let mut notes = Vec::new();
+    let mut confidence = Confidence::High;
+    if !plausible_time(last_written) {
+        notes.push("Last written time is implausible".to_string());
+        confidence = Confidence::Low;
+    }
+    if !is_signature(hive, parent, b"nk") {
+        notes.push(format!("Parent cell 0x{:X} is no longer a key", parent));
+        confidence = confidence.min(Confidence::Medium);
+    }
+    let security = hive::read_u32(bytes, 44);
+    if !is_signature(hive, security, b"sk") {
+        notes.push(format!("Security cell 0x{:X} is no longer a security descriptor", security));
+        confidence = confidence.min(Confidence::Medium);
+    }
+
+    let item = CarvedItem::Key {
+        name,
+        last_written,
+        parent,
+        subkey_count: hive::read_u32(bytes, 20),
+        value_count: hive::read_u32(bytes, 36),
+        value_list: hive::read_u32(bytes, 40),
+    };
+    Some((item, confidence, notes, KEY_HEADER + name_length))
+}
+
+fn carve_value(hive: &Hive, offset: u32, bytes: &[u8]) -> Option<(CarvedItem, Confidence, Vec<String>, usize)> {
+    if bytes.len() < VALUE_HEADER {
+        return None;
+    }
+    let name_length = hive::read_u16(bytes, 2) as usize;
+    if VALUE_HEADER + name_length > bytes.len() {
+        return None;
+    }
+    let value = Hive::parse_value(offset, &bytes[..VALUE_HEADER + name_length]).ok()?;
+    if !plausible_name(&value.name) || (!value.is_inline() && value.data_size() > 0 && !plausible_offset(hive, value.data_offset)) {
+        return None;
+    }
+
+    let mut notes = Vec::new();
+    let mut confidence = Confidence::High;
+    if value.data_type > REG_QWORD {
+        notes.push(format!("Unknown data type 0x{:X}", value.data_type));
+        confidence = Confidence::Low;
+    }
+    if value.is_inline() && value.data_size() > 4 {
+        notes.push("Inline data is longer than 4 bytes".to_string());
+        return Some((CarvedItem::Value { value, data: None }, Confidence::Low, notes, VALUE_HEADER + name_length));
+    }
+
+    let data = match hive.deleted_value_data(&value) {
+        Ok(data) => {
+            let reused = !value.is_inline() && value.data_size() > 0
+                && hive.cell(value.data_offset).map(|c| c.allocated).unwrap_or(false);
+            if reused {
+                notes.push(format!("Data cell 0x{:X} is allocated again; data may belong to another value", value.data_offset));
+                confidence = confidence.min(Confidence::Medium);
+            }
+            Some(data)
+        }
+        Err(e) => {
+            notes.push(format!("Data not recoverable: {}", e));
+            confidence = confidence.min(Confidence::Medium);
+            None
+        }
+    };
+    Some((CarvedItem::Value { value, data }, confidence, notes, VALUE_HEADER + name_length))
+}
+
+fn carve_security(hive: &Hive, bytes: &[u8]) -> Option<(CarvedItem, Confidence, Vec<String>, usize)> {
+    if bytes.len() < SECURITY_HEADER {
+        return None;
+    }
+    let flink = hive::read_u32(bytes, 4);
+    let blink = hive::read_u32(bytes, 8);
+    let descriptor_size = hive::read_u32(bytes, 16);
+    let end = SECURITY_HEADER.checked_add(descriptor_size as usize)?;
+    if descriptor_size < 20 || end > bytes.len() || bytes[SECURITY_HEADER] != 1 {
+        return None;
+    }
+    if !plausible_offset(hive, flink) || !plausible_offset(hive, blink) {
+        return None;
+    }
+
+    let mut notes = Vec::new();
+    let mut confidence = Confidence::High;
+    if !is_signature(hive, flink, b"sk") || !is_signature(hive, blink, b"sk") {
+        notes.push("Neighbours in the security chain are no longer security descriptors".to_string());
+        confidence = Confidence::Medium;
+    }
+    let owner = owner_sid(&bytes[SECURITY_HEADER..end]);
+    if owner.is_none() {
+        notes.push("Owner SID could not be decoded".to_string());
+        confidence = Confidence::Low;
+    }
+
+    let item = CarvedItem::Security { reference_count: hive::read_u32(bytes, 12), descriptor_size, owner };
+    Some((item, confidence, notes, end))
+}
+
+fn carve_big_data(hive: &Hive, bytes: &[u8]) -> Option<(CarvedItem, Confidence, Vec<String>, usize)> {
+    if bytes.len() < BIG_DATA_HEADER {
+        return None;
+    }
+    let segment_count = hive::read_u16(bytes, 2);
+    let segment_list = hive::read_u32(bytes, 4);
+    if segment_count < 2 || !plausible_offset(hive, segment_list) {
+        return None;
+    }
+
+    let mut notes = Vec::new();
+    let confidence = match hive.cell_payload(segment_list) {
+        Ok(list) if list.len() >= segment_count as usize * 4 => {
+            let missing = (0..segment_count as usize)
+                .filter(|&i| hive.cell_payload(hive::read_u32(list, i * 4)).is_err())
+                .count();
+            if missing == 0 {
+                Confidence::High
+            } else {
+                notes.push(format!("{} segment(s) cannot be read", missing));
+                Confidence::Medium
+            }
+        }
+        _ => {
+            notes.push("Segment list cannot be read".to_string());
+            Confidence::Low
+        }
+    };
+
+    Some((CarvedItem::BigData { segment_count, segment_list }, confidence, notes, BIG_DATA_HEADER))
+}
+
+/// Scans free cells and the slack of allocated cells for deleted nk, vk, sk
+/// and db records. Only reads the hive.
+pub fn carve(hive: &Hive) -> Vec<CarvedRecord> {
+    let mut records = Vec::new();
+
+    for bin in &hive.walk_bins().bins {
+        let Ok(cells) = hive.cells_in_bin(bin) else {
+            continue;
+        };
+        for cell in cells {
+            let Ok(payload) = hive.cell_payload(cell.offset) else {
+                continue;
+            };
+            let (region, start) = if cell.allocated {
+                match used_length(payload) {
+                    // Records always start 4 bytes into an 8-aligned cell
+                    Some(used) => (Region::Slack, (used + 4).next_multiple_of(8) - 4),
+                    None => continue,
+                }
+            } else {
+                (Region::FreeCell, 0)
+            };
+
+            let mut position = start;
+            while position + 2 <= payload.len() {
+                let bytes = &payload[position..];
+                let offset = cell.offset + 4 + position as u32;
+                let carved = match &bytes[0..2] {
+                    b"nk" => carve_key(hive, bytes),
+                    b"vk" => carve_value(hive, offset, bytes),
+                    b"sk" => carve_security(hive, bytes),
+                    b"db" => carve_big_data(hive, bytes),
+                    _ => None,
+                };
+                let step = match carved {
+                    Some((item, confidence, notes, length)) => {
+                        records.push(CarvedRecord {
+                            offset,
+                            cell: cell.offset,
+                            region,
+                            item,
+                            confidence,
+                            path: None,
+                            notes,
+                        });
+                        (length + 4).next_multiple_of(8) - 4
+                    }
+                    None => 8,
+                };
+                position += step.max(8);
+            }
+        }
+    }
+
+    resolve_paths(hive, &mut records);
+    records
+}
+
+/// Length of the record in an allocated cell, for the record types whose size is known
+fn used_length(payload: &[u8]) -> Option<usize> {
+    let length = match payload.get(0..2)? {
+        b"nk" if payload.len() >= KEY_HEADER => KEY_HEADER + hive::read_u16(payload, 72) as usize,
+        b"vk" if payload.len() >= VALUE_HEADER => VALUE_HEADER + hive::read_u16(payload, 2) as usize,
+        b"sk" if payload.len() >= SECURITY_HEADER => SECURITY_HEADER + hive::read_u32(payload, 16) as usize,
+        b"lf" | b"lh" if payload.len() >= 4 => 4 + hive::read_u16(payload, 2) as usize * 8,
+        b"li" | b"ri" if payload.len() >= 4 => 4 + hive::read_u16(payload, 2) as usize * 4,
+        b"db" => BIG_DATA_HEADER,
+        _ => return None,
+    };
+    (length <= payload.len()).then_some(length)
+}
+
+/// Rebuilds paths of carved keys from their parent pointers, through live
+/// keys and other carved keys, and assigns carved values to the carved key
+/// whose value list still names them
+fn resolve_paths(hive: &Hive, records: &mut [CarvedRecord]) {
+    let live = hive.key_paths();
+    let carved_keys: HashMap<u32, (String, u32)> = records.iter()
+        .filter_map(|r| match &r.item {
+            CarvedItem::Key { name, parent, .. } => Some((r.offset - 4, (name.clone(), *parent))),
+            _ => None,
+        })
+        .collect();
+
+    let path_of = |cell: u32| -> String {
+        let mut names = Vec::new();
+        let mut current = cell;
+        loop {
+            if let Some(path) = live.get(&current) {
+                names.push(path.clone());
+                break;
+            }
+            match carved_keys.get(&current) {
+                Some((name, parent)) if names.len() < 64 => {
+                    names.push(name.clone());
+                    current = *parent;
+                }
+                _ => {
+                    names.push(format!("<unknown 0x{:X}>", current));
+                    break;
+                }
+            }
+        }
+        names.into_iter().rev().filter(|n| !n.is_empty()).collect::<Vec<_>>().join("\\")
+    };
+
+    let mut value_owners: HashMap<u32, u32> = HashMap::new();
+    for record in records.iter() {
+        if let CarvedItem::Key { value_count, value_list, .. } = record.item {
+            if value_count == 0 || value_list == NO_CELL {
+                continue;
+            }
+            if let Ok(list) = hive.cell_payload(value_list) {
+                for i in 0..(value_count as usize).min(list.len() / 4) {
+                    value_owners.insert(hive::read_u32(list, i * 4), record.offset - 4);
+                }
+            }
+        }
+    }
+
+    for record in records.iter_mut() {
+        match &record.item {
+            CarvedItem::Key { .. } => record.path = Some(path_of(record.offset - 4)),
+            CarvedItem::Value { .. } => {
+                record.path = value_owners.get(&(record.offset - 4)).map(|&owner| path_of(owner));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Writes one JSON object per carved record
+pub fn export_json_lines(records: &[CarvedRecord], output_path: &str) -> Result<()> {
+    let mut file = BufWriter::new(File::create(output_path)?);
+    for record in records {
+        writeln!(file, "{}", record.to_json())?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+pub fn print_records(records: &[CarvedRecord]) {
+    if records.is_empty() {
+        println!("No deleted records found in free space.");
+        return;
+    }
+    println!("{:<10}  {:<8}  {:<6}  {:<10}  Record", "Offset", "Type", "Region", "Confidence");
+    for record in records {
+        println!(
+            "0x{:08X}  {:<8}  {:<6}  {:<10}  {}",
+            record.offset, record.kind(), record.region.to_string(), record.confidence.to_string(), record.summary()
+        );
+        for note in &record.notes {
+            println!("{:<40}  note: {}", "", note);
+        }
+    }
+    let high = records.iter().filter(|r| r.confidence == Confidence::High).count();
+    println!("\n{} record(s) carved, {} with high confidence.", records.len(), high);
+}
@@ -0,0 +1,268 @@
+use crate::hive::{self, Hive, BASE_BLOCK_SIZE, NO_CELL};
+use crate::rebuild::HiveWriter;
+use crate::registry;
+use crate::types::*;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+
+// Base block fields touched by compaction
+const HIVE_FLAGS: usize = 144;
+const HIVE_DEFRAGMENTED: u32 = 0x2;
+const LAST_REORGANIZED: usize = 168;
+// Low bits of the last reorganized timestamp: reorganized to defragment
+const REORGANIZED_DEFRAGMENT: u64 = 0x1;
+
+/// What a cell holds, which decides where its cell references are
+#[derive(Debug, Clone, Copy)]
+enum CellKind {
+    Key,
+    Value,
+    Security,
+    /// lf, lh, li or ri list
+    SubkeyList,
+    ValueList(usize),
+    BigData,
+    SegmentList(usize),
+    /// Class names and value data; no references inside
+    Raw,
+}
+
+#[derive(Debug)]
+pub struct CompactReport {
+    /// Cells moved into the new layout
+    pub cells: usize,
+    /// Allocated cells nothing referred to, released rather than moved
+    pub released: usize,
+    pub released_bytes: u64,
+    /// Entirely free bins that ended the original file
+    pub trailing_free_bins: usize,
+    pub original_size: u64,
+    pub compacted_size: u64,
+    pub validation: Result<AnalysisResult>,
+}
+
+impl CompactReport {
+    pub fn saved(&self) -> u64 {
+        self.original_size.saturating_sub(self.compacted_size)
+    }
+}
+
+// Records every cell reachable from the root with its kind, in tree order
+fn classify(hive: &Hive) -> Result<Vec<(u32, CellKind)>> {
+    let mut order = Vec::new();
+    let mut kinds: HashMap<u32, CellKind> = HashMap::new();
+    let mut add = |order: &mut Vec<(u32, CellKind)>, cell: u32, kind: CellKind| {
+        if kinds.insert(cell, kind).is_none() {
+            order.push((cell, kind));
+            true
+        } else {
+            false
+        }
+    };
+
+    let mut pending = vec![hive.root_cell_offset()];
+    while let Some(offset) = pending.pop() {
+        if !add(&mut order, offset, CellKind::Key) {
+            continue;
+        }
+        let key = hive.key(offset)?;
+
+        // The whole security chain, so flink and blink stay valid
+        let mut security = key.security;
+        while security != NO_CELL && add(&mut order, security, CellKind::Security) {
+            let data = hive.cell_data(security)?;
+            if data.get(0..2) != Some(b"sk") {
+                bail!("Security cell at 0x{:X} is invalid", security);
+            }
+            security = hive::read_u32(data, 4);
+        }
+        if key.class_name != NO_CELL {
+            add(&mut order, key.class_name, CellKind::Raw);
+        }
+
+        if key.value_count > 0 && key.value_list != NO_CELL {
+            add(&mut order, key.value_list, CellKind::ValueList(key.value_count as usize));
+            for value_offset in hive.value_offsets(&key)? {
+                add(&mut order, value_offset, CellKind::Value);
+                let cells = hive.value_data_cells(&hive.value(value_offset)?)?;
+                if cells.len() > 1 {
+                    add(&mut order, cells[0], CellKind::BigData);
+                    add(&mut order, cells[1], CellKind::SegmentList(cells.len() - 2));
+                    for &segment in &cells[2..] {
+                        add(&mut order, segment, CellKind::Raw);
+                    }
+                } else if let Some(&data) = cells.first() {
+                    add(&mut order, data, CellKind::Raw);
+                }
+            }
+        }
+
+        if key.subkey_count > 0 && key.subkey_list != NO_CELL {
+            add(&mut order, key.subkey_list, CellKind::SubkeyList);
+            let list = hive.cell_data(key.subkey_list)?;
+            if list.get(0..2) == Some(b"ri") {
+                let count = hive::read_u16(list, 2) as usize;
+                for i in 0..count {
+                    add(&mut order, hive::read_u32(list, 4 + i * 4), CellKind::SubkeyList);
+                }
+            }
+            pending.extend(hive.subkey_offsets(key.subkey_list)?.into_iter().rev());
+        }
+    }
+    Ok(order)
+}
+
+// Offsets, relative to the payload, of the references held by a cell
+fn reference_fields(kind: CellKind, writer: &HiveWriter, cell: u32, payload: &[u8]) -> Vec<usize> {
+    match kind {
+        CellKind::Key => {
+            let mut fields = vec![16, 44];
+            if writer.get_u32(cell, 20) > 0 {
+                fields.push(28);
+            }
+            if writer.get_u32(cell, 36) > 0 {
+                fields.push(40);
+            }
+            if writer.get_u32(cell, 48) != NO_CELL {
+                fields.push(48);
+            }
+            fields
+        }
+        CellKind::Value => {
+            let raw_size = writer.get_u32(cell, 4);
+            if raw_size & hive::DATA_INLINE_FLAG != 0 || raw_size == 0 {
+                Vec::new()
+            } else {
+                vec![8]
+            }
+        }
+        CellKind::Security => vec![4, 8],
+        CellKind::SubkeyList => {
+            let count = hive::read_u16(payload, 2) as usize;
+            let stride = if matches!(&payload[0..2], b"lf" | b"lh") { 8 } else { 4 };
+            (0..count).map(|i| 4 + i * stride).collect()
+        }
+        CellKind::ValueList(count) | CellKind::SegmentList(count) => (0..count).map(|i| i * 4).collect(),
+        CellKind::BigData => vec![4],
+        CellKind::Raw => Vec::new(),
+    }
+}
+
+/// Number of bins at the end of the hive that hold nothing but free space
+fn trailing_free_bins(hive: &Hive) -> usize {
+    hive.walk_bins().bins.iter().rev()
+        .take_while(|bin| hive.cells_in_bin(bin).map(|cells| cells.iter().all(|c| !c.allocated)).unwrap_or(false))
+        .count()
+}
+
+/// Moves every cell reachable from the root into tightly packed bins,
+/// rewriting all references, and writes the result to `output_path` or back
+/// to `file_path` after a backup. Refuses damaged hives and hives with
+/// orphans, which would otherwise be lost.
+pub fn compact_hive(file_path: &str, output_path: Option<&str>) -> Result<CompactReport> {
+    let hive = Hive::open(file_path)?;
+    let bins = hive.walk_bins();
+    if bins.error.is_some() || bins.valid_bytes != hive.bins_len() {
+        bail!("The bin chain is damaged; repair the hive or use rebuild instead");
+    }
+    let walk = hive.walk_tree();
+    if !walk.errors.is_empty() {
+        bail!("The key tree is damaged ({}); repair the hive or use rebuild instead", walk.errors[0]);
+    }
+    let orphans = registry::find_orphans(&hive);
+    if !orphans.keys.is_empty() || !orphans.loose_values.is_empty() {
+        bail!(
+            "The hive has {} orphaned key(s) and {} loose value(s); recover them with --fix first",
+            orphans.keys.len(), orphans.loose_values.len()
+        );
+    }
+
+    let order = classify(&hive)?;
+    let mut writer = HiveWriter::new(hive.minor_version());
+    let mut moved = HashMap::new();
+    for &(offset, _) in &order {
+        moved.insert(offset, writer.allocate(hive.cell_data(offset)?));
+    }
+    let mut released = 0;
+    let mut released_bytes = 0u64;
+    for bin in &bins.bins {
+        for cell in hive.cells_in_bin(bin)?.into_iter().filter(|c| c.allocated && !moved.contains_key(&c.offset)) {
+            released += 1;
+            released_bytes += cell.size as u64;
+        }
+    }
+    for &(offset, kind) in &order {
+        let cell = moved[&offset];
+        for field in reference_fields(kind, &writer, cell, hive.cell_data(offset)?) {
+            let old = writer.get_u32(cell, field);
+            if old == NO_CELL {
+                continue;
+            }
+            let new = match moved.get(&old) {
+                Some(&new) => new,
+                // The root's parent pointer leads outside the tree
+                None if field == 16 && matches!(kind, CellKind::Key) => continue,
+                None => return Err(anyhow!("Cell at 0x{:X} refers to 0x{:X}, which was not moved", offset, old)),
+            };
+            writer.set_u32(cell, field, new);
+        }
+        if matches!(kind, CellKind::Key) {
+            // Volatile subkeys never survive on disk
+            writer.set_u32(cell, 24, 0);
+            writer.set_u32(cell, 32, NO_CELL);
+        }
+    }
+    let mut new_bins = writer.finish();
+    // Keep the timestamp of the first bin
+    if let Ok(first) = hive.bin_at(0) {
+        new_bins[20..28].copy_from_slice(&first.timestamp.to_le_bytes());
+    }
+
+    let mut base = hive.data()[..BASE_BLOCK_SIZE].to_vec();
+    let sequence = hive.primary_seq_num().wrapping_add(1);
+    let flags = hive::read_u32(&base, HIVE_FLAGS) | HIVE_DEFRAGMENTED;
+    let reorganized = (hive::filetime_now() & !0x3) | REORGANIZED_DEFRAGMENT;
+    base[4..8].copy_from_slice(&sequence.to_le_bytes());
+    base[8..12].copy_from_slice(&sequence.to_le_bytes());
+    base[12..20].copy_from_slice(&hive::filetime_now().to_le_bytes());
+    base[36..40].copy_from_slice(&moved[&hive.root_cell_offset()].to_le_bytes());
+    base[40..44].copy_from_slice(&(new_bins.len() as u32).to_le_bytes());
+    base[HIVE_FLAGS..HIVE_FLAGS + 4].copy_from_slice(&flags.to_le_bytes());
+    base[LAST_REORGANIZED..LAST_REORGANIZED + 8].copy_from_slice(&reorganized.to_le_bytes());
+    let checksum = registry::calculate_header_checksum(&base);
+    base[508..512].copy_from_slice(&checksum.to_le_bytes());
+    base.extend_from_slice(&new_bins);
+
+    let target = registry::prepare_target(file_path, output_path)?;
+    registry::create_output_file(target, &base)?;
+
+    Ok(CompactReport {
+        cells: order.len(),
+        released,
+        released_bytes,
+        trailing_free_bins: trailing_free_bins(&hive),
+        original_size: hive.data().len() as u64,
+        compacted_size: base.len() as u64,
+        validation: registry::check_registry_file(target),
+    })
+}
+
+pub fn print_report(report: &CompactReport) {
+    println!("Moved {} cell(s) into {} bytes of hive bins",
+        report.cells, report.compacted_size - BASE_BLOCK_SIZE as u64);
+    if report.trailing_free_bins > 0 {
+        println!("{} entirely free bin(s) at the end of the file were dropped", report.trailing_free_bins);
+    }
+    if report.released > 0 {
+        println!("{} allocated cell(s) ({} bytes) referenced by nothing were released",
+            report.released, report.released_bytes);
+    }
+    println!("Size: {} bytes -> {} bytes ({} bytes saved)",
+        report.original_size, report.compacted_size, report.saved());
+
+    match &report.validation {
+        Ok(result) if result.issues.is_empty() => println!("\nCompacted hive validated with no issues."),
+        Ok(result) => println!("\nCompacted hive validated with {} issue(s).", result.issues.len()),
+        Err(e) => println!("\nCompacted hive failed validation: {}", e),
+    }
+}
@@ -0,0 +1,223 @@
+use crate::audit::{self, AuditLog};
+use crate::hive::{self, Hive};
+use crate::types::*;
+use anyhow::Result;
+
+// Weights of the integrity score, out of 100
+const HEADER_POINTS: u32 = 25;
+const BIN_POINTS: u32 = 25;
+const KEY_POINTS: u32 = 35;
+const RECENCY_POINTS: u32 = 15;
+
+/// Integrity measurements of one candidate copy of a hive
+#[derive(Debug, Clone)]
+pub struct HiveHealth {
+    pub analysis: AnalysisResult,
+    pub root_name: Option<String>,
+    pub bin_count: usize,
+    pub valid_bin_bytes: u32,
+    pub bin_error: Option<String>,
+    pub reachable_keys: usize,
+    pub reachable_values: usize,
+    pub tree_errors: usize,
+    pub newest_key_time: u64,
+    pub header_points: u32,
+    pub score: u32,
+}
+
+impl HiveHealth {
+    /// Header fields agree with each other, so the last write completed
+    pub fn consistent(&self) -> bool {
+        let info = &self.analysis.file_info;
+        info.signature == "regf"
+            && info.primary_seq_num == info.secondary_seq_num
+            && info.stored_checksum == info.calculated_checksum
+    }
+
+    /// Later of the base block's last-written time and the newest key
+    /// timestamp, or `None` when the header is not consistent
+    pub fn consistent_last_written(&self) -> Option<u64> {
+        self.consistent()
+            .then(|| self.analysis.file_info.last_written.max(self.newest_key_time))
+    }
+
+    pub fn bin_coverage(&self) -> f64 {
+        let measured = self.analysis.file_info.measured_hive_bins_size;
+        if measured == 0 {
+            0.0
+        } else {
+            self.valid_bin_bytes as f64 / measured as f64
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Candidate {
+    pub path: String,
+    pub health: Result<HiveHealth>,
+}
+
+#[derive(Debug)]
+pub struct Comparison {
+    pub candidates: Vec<Candidate>,
+    /// Index of the candidate recommended as the repair base
+    pub recommended: Option<usize>,
+    pub warnings: Vec<String>,
+}
+
+/// Measures a single copy; the score is filled in by `compare`
+pub fn assess(file_path: &str, audit_log: Option<&AuditLog>) -> Result<HiveHealth> {
+    let (analysis, _) = audit::analyze(file_path, audit_log)?;
+    let hive = Hive::open(file_path)?;
+    let bins = hive.walk_bins();
+    let tree = hive.walk_tree();
+    let info = &analysis.file_info;
+
+    let mut header_points = 0;
+    if info.signature == "regf" {
+        header_points += 10;
+    }
+    if info.stored_checksum == info.calculated_checksum {
+        header_points += 5;
+    }
+    if info.primary_seq_num == info.secondary_seq_num {
+        header_points += 5;
+    }
+    if info.hive_bins_size == info.measured_hive_bins_size {
+        header_points += 5;
+    }
+
+    Ok(HiveHealth {
+        root_name: hive.root_key().ok().map(|key| key.name),
+        bin_count: bins.bins.len(),
+        valid_bin_bytes: bins.valid_bytes,
+        bin_error: bins.error,
+        reachable_keys: tree.keys,
+        reachable_values: tree.values,
+        tree_errors: tree.errors.len(),
+        newest_key_time: tree.newest_key_time,
+        header_points,
+        score: 0,
+        analysis,
+    })
+}
+
+/// Scores several copies of the same hive against each other and recommends
+/// the healthiest one as the base for a repair
+pub fn compare(paths: &[String], audit_log: Option<&AuditLog>) -> Comparison {
+    let mut candidates: Vec<Candidate> = paths.iter()
+        .map(|path| Candidate { path: path.clone(), health: assess(path, audit_log) })
+        .collect();
+
+    let healthy = || candidates.iter().filter_map(|c| c.health.as_ref().ok());
+    let max_keys = healthy().map(|h| h.reachable_keys).max().unwrap_or(0);
+    let newest = healthy().filter_map(|h| h.consistent_last_written()).max();
+
+    let mut warnings = Vec::new();
+    let mut root_names: Vec<&str> = healthy().filter_map(|h| h.root_name.as_deref()).collect();
+    root_names.sort_unstable();
+    root_names.dedup();
+    if root_names.len() > 1 {
+        warnings.push(format!(
+            "The candidates have different root keys ({}); they may not be copies of the same hive",
+            root_names.join(", ")
+        ));
+    }
+
+    for candidate in &mut candidates {
+        let Ok(health) = &mut candidate.health else {
+            continue;
+        };
+        let bin_points = (BIN_POINTS as f64 * health.bin_coverage().min(1.0)).round() as u32;
+        let key_points = if max_keys == 0 {
+            0
+        } else {
+            let reach = health.reachable_keys as f64 / max_keys as f64;
+            let penalty = if health.tree_errors > 0 { 5 } else { 0 };
+            ((KEY_POINTS - 5) as f64 * reach).round() as u32 + 5 - penalty
+        };
+        let recency_points = match (health.consistent_last_written(), newest) {
+            (Some(time), Some(newest)) if time == newest => RECENCY_POINTS,
+            (Some(_), _) => RECENCY_POINTS / 3,
+            _ => 0,
+        };
+        health.score = health.header_points.min(HEADER_POINTS) + bin_points + key_points + recency_points;
+    }
+
+    let recommended = candidates.iter()
+        .enumerate()
+        .filter_map(|(i, c)| c.health.as_ref().ok().map(|h| (i, h)))
+        .max_by_key(|(i, h)| (h.score, h.consistent_last_written(), std::cmp::Reverse(*i)))
+        .map(|(i, _)| i);
+
+    Comparison { candidates, recommended, warnings }
+}
+
+/// Rows of the side-by-side table: a label followed by one cell per candidate
+pub fn comparison_rows(comparison: &Comparison) -> Vec<(String, Vec<String>)> {
+    let field = |label: &str, f: &dyn Fn(&HiveHealth) -> String| {
+        let cells = comparison.candidates.iter()
+            .map(|c| match &c.health {
+                Ok(health) => f(health),
+                Err(e) => if label == "Score" { format!("unreadable: {}", e) } else { "-".to_string() },
+            })
+            .collect();
+        (label.to_string(), cells)
+    };
+
+    vec![
+        ("File".to_string(), comparison.candidates.iter().map(|c| c.path.clone()).collect()),
+        field("Score", &|h| format!("{}/100", h.score)),
+        field("Size", &|h| format!("{} bytes", h.analysis.file_info.size)),
+        field("Signature", &|h| h.analysis.file_info.signature.clone()),
+        field("Sequence Numbers", &|h| format!("{} / {}",
+            h.analysis.file_info.primary_seq_num, h.analysis.file_info.secondary_seq_num)),
+        field("Checksum", &|h| {
+            let info = &h.analysis.file_info;
+            if info.stored_checksum == info.calculated_checksum {
+                format!("0x{:08X} (valid)", info.stored_checksum)
+            } else {
+                format!("0x{:08X} (expected 0x{:08X})", info.stored_checksum, info.calculated_checksum)
+            }
+        }),
+        field("Last Written", &|h| hive::format_filetime(h.analysis.file_info.last_written)),
+        field("Version", &|h| format!("{}.{}",
+            h.analysis.file_info.major_version, h.analysis.file_info.minor_version)),
+        field("Hive Bins Size", &|h| format!("{} stored / {} measured",
+            h.analysis.file_info.hive_bins_size, h.analysis.file_info.measured_hive_bins_size)),
+        field("Valid Bins", &|h| format!("{} ({:.1}% of data){}", h.bin_count, h.bin_coverage() * 100.0,
+            if h.bin_error.is_some() { ", walk stopped early" } else { "" })),
+        field("Reachable Keys", &|h| h.reachable_keys.to_string()),
+        field("Reachable Values", &|h| h.reachable_values.to_string()),
+        field("Tree Errors", &|h| h.tree_errors.to_string()),
+        field("Newest Key Time", &|h| hive::format_filetime(h.newest_key_time)),
+        field("Consistent", &|h| if h.consistent() { "yes" } else { "no" }.to_string()),
+    ]
+}
+
+pub fn print_comparison(comparison: &Comparison) {
+    let rows = comparison_rows(comparison);
+    let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    let mut widths = vec![0usize; comparison.candidates.len()];
+    for (_, cells) in &rows {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for (label, cells) in &rows {
+        let cells: Vec<String> = cells.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{:<label_width$}  {}", label, cells.join("  ").trim_end(), label_width = label_width);
+    }
+
+    for warning in &comparison.warnings {
+        println!("\nWARNING: {}", warning);
+    }
+    match comparison.recommended {
+        Some(index) => println!("\nRecommended base: {}", comparison.candidates[index].path),
+        None => println!("\nNone of the candidates could be read."),
+    }
+}
@@ -0,0 +1,372 @@
+use crate::hive::{self, Hive, KeyNode, NO_CELL};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl Change {
+    pub fn marker(&self) -> char {
+        match self {
+            Change::Added => '+',
+            Change::Removed => '-',
+            Change::Modified => '~',
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Modified => "modified",
+        }
+    }
+}
+
+/// Type and data of a value on one side; the data is None when unreadable
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueState {
+    pub data_type: u32,
+    pub data: Option<Vec<u8>>,
+}
+
+impl ValueState {
+    pub fn describe(&self) -> String {
+        let data = match &self.data {
+            Some(data) => hive::format_value_data(self.data_type, data),
+            None => "<unreadable>".to_string(),
+        };
+        format!("{} {}", hive::value_type_name(self.data_type), data)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "type": hive::value_type_name(self.data_type),
+            "data": self.data.as_ref().map(|d| hive::format_value_data(self.data_type, d)),
+            "data_hex": self.data.as_ref().map(|d| d.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ValueDiff {
+    pub name: String,
+    pub change: Change,
+    pub before: Option<ValueState>,
+    pub after: Option<ValueState>,
+}
+
+impl ValueDiff {
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() { "(Default)" } else { &self.name }
+    }
+}
+
+/// A key that differs, or that has differences somewhere below it
+#[derive(Debug, Clone)]
+pub struct KeyDiff {
+    pub name: String,
+    /// Path relative to the root
+    pub path: String,
+    /// None when the key itself is unchanged
+    pub change: Option<Change>,
+    pub last_written_before: Option<u64>,
+    pub last_written_after: Option<u64>,
+    pub values: Vec<ValueDiff>,
+    pub subkeys: Vec<KeyDiff>,
+}
+
+impl KeyDiff {
+    pub fn display_path(&self) -> &str {
+        if self.path.is_empty() { "(root)" } else { &self.path }
+    }
+
+    pub fn timestamp_changed(&self) -> bool {
+        self.change == Some(Change::Modified) && self.last_written_before != self.last_written_after
+    }
+
+    fn to_json(&self) -> Value {
+        let time = |t: Option<u64>| t.map(hive::format_filetime);
+        json!({
+            "path": self.path,
+            "change": self.change.map(|c| c.name()),
+            "last_written_before": time(self.last_written_before),
+            "last_written_after": time(self.last_written_after),
+            "values": self.values.iter().map(|v| json!({
+                "name": v.name,
+                "change": v.change.name(),
+                "before": v.before.as_ref().map(ValueState::to_json),
+                "after": v.after.as_ref().map(ValueState::to_json),
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HiveDiff {
+    pub before_path: String,
+    pub after_path: String,
+    /// The root key's differences; None when the hives are identical
+    pub root: Option<KeyDiff>,
+    /// Parts of either tree that could not be read
+    pub errors: Vec<String>,
+}
+
+/// Counts of added, removed and modified items
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiffSummary {
+    pub keys: [usize; 3],
+    pub values: [usize; 3],
+}
+
+impl HiveDiff {
+    /// Keys with value changes are counted even when the key itself is unchanged
+    pub fn summary(&self) -> DiffSummary {
+        let mut summary = DiffSummary::default();
+        let index = |change: Change| change as usize;
+        let mut pending: Vec<&KeyDiff> = self.root.iter().collect();
+        while let Some(key) = pending.pop() {
+            if let Some(change) = key.change {
+                summary.keys[index(change)] += 1;
+            }
+            for value in &key.values {
+                summary.values[index(value.change)] += 1;
+            }
+            pending.extend(&key.subkeys);
+        }
+        summary
+    }
+
+    pub fn to_json(&self) -> Value {
+        let summary = self.summary();
+        let counts = |c: [usize; 3]| json!({ "added": c[0], "removed": c[1], "modified": c[2] });
+        let mut keys = Vec::new();
+        let mut pending: Vec<&KeyDiff> = self.root.iter().collect();
+        while let Some(key) = pending.pop() {
+            if key.change.is_some() || !key.values.is_empty() {
+                keys.push(key.to_json());
+            }
+            pending.extend(key.subkeys.iter().rev());
+        }
+        json!({
+            "before": self.before_path,
+            "after": self.after_path,
+            "summary": { "keys": counts(summary.keys), "values": counts(summary.values) },
+            "keys": keys,
+            "errors": self.errors,
+        })
+    }
+}
+
+struct Differ<'a> {
+    before: &'a Hive,
+    after: &'a Hive,
+    seen: [HashSet<u32>; 2],
+    errors: Vec<String>,
+}
+
+impl<'a> Differ<'a> {
+    fn values(&mut self, hive: &Hive, key: Option<&KeyNode>, path: &str) -> Vec<(String, ValueState)> {
+        let Some(key) = key else {
+            return Vec::new();
+        };
+        if key.value_count == 0 || key.value_list == NO_CELL {
+            return Vec::new();
+        }
+        let offsets = match hive.value_offsets(key) {
+            Ok(offsets) => offsets,
+            Err(e) => {
+                self.errors.push(format!("{}: {}", path, e));
+                return Vec::new();
+            }
+        };
+        offsets.into_iter()
+            .filter_map(|offset| match hive.value(offset) {
+                Ok(value) => {
+                    let data = hive.value_data(&value).ok();
+                    Some((value.name, ValueState { data_type: value.data_type, data }))
+                }
+                Err(e) => {
+                    self.errors.push(format!("{}: {}", path, e));
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn subkeys(&mut self, side: usize, key: Option<&KeyNode>, path: &str) -> Vec<KeyNode> {
+        let Some(key) = key else {
+            return Vec::new();
+        };
+        let hive = if side == 0 { self.before } else { self.after };
+        let subkeys = match hive.subkeys(key) {
+            Ok(subkeys) => subkeys,
+            Err(e) => {
+                self.errors.push(format!("{}: {}", path, e));
+                return Vec::new();
+            }
+        };
+        subkeys.into_iter()
+            .filter(|subkey| {
+                let first = self.seen[side].insert(subkey.offset);
+                if !first {
+                    self.errors.push(format!("{}: key at 0x{:X} is referenced more than once", path, subkey.offset));
+                }
+                first
+            })
+            .collect()
+    }
+
+    fn key(&mut self, before: Option<&KeyNode>, after: Option<&KeyNode>, path: String, depth: usize) -> Option<KeyDiff> {
+        if depth > hive::MAX_DEPTH {
+            self.errors.push(format!("{}: key tree is deeper than {} levels", path, hive::MAX_DEPTH));
+            return None;
+        }
+        let name = after.or(before).map(|k| k.name.clone()).unwrap_or_default();
+
+        let old_values = self.values(self.before, before, &path);
+        let new_values = self.values(self.after, after, &path);
+        // The first of several values with the same name wins, on either side
+        let mut old_index = HashMap::new();
+        for (name, state) in &old_values {
+            old_index.entry(hive::fold_name(name)).or_insert(state);
+        }
+        let mut new_index = HashMap::new();
+        for (name, state) in &new_values {
+            new_index.entry(hive::fold_name(name)).or_insert(state);
+        }
+        let mut values = Vec::new();
+        for (name, state) in &old_values {
+            match new_index.get(&hive::fold_name(name)) {
+                None => values.push(ValueDiff { name: name.clone(), change: Change::Removed, before: Some(state.clone()), after: None }),
+                Some(&new) if new != state => values.push(ValueDiff {
+                    name: name.clone(), change: Change::Modified, before: Some(state.clone()), after: Some(new.clone()),
+                }),
+                Some(_) => {}
+            }
+        }
+        for (name, state) in &new_values {
+            if !old_index.contains_key(&hive::fold_name(name)) {
+                values.push(ValueDiff { name: name.clone(), change: Change::Added, before: None, after: Some(state.clone()) });
+            }
+        }
+
+        // Walk both sides sorted by folded name, pairing keys with the same name
+        let old_subkeys = sorted_by_name(self.subkeys(0, before, &path));
+        let new_subkeys = sorted_by_name(self.subkeys(1, after, &path));
+        let mut subkeys = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < old_subkeys.len() || j < new_subkeys.len() {
+            let order = match (old_subkeys.get(i), new_subkeys.get(j)) {
+                (Some(old), Some(new)) => old.0.cmp(&new.0),
+                (Some(_), None) => Ordering::Less,
+                _ => Ordering::Greater,
+            };
+            let old = (order != Ordering::Greater).then(|| &old_subkeys[i]);
+            let new = (order != Ordering::Less).then(|| &new_subkeys[j]);
+            let (folded, name) = old.or(new).map(|(folded, key)| (folded, key.name.as_str())).unwrap();
+            if let Some(diff) = self.key(old.map(|o| &o.1), new.map(|n| &n.1), hive::join_path(&path, name), depth + 1) {
+                subkeys.push(diff);
+            }
+            // Later keys of the same name on one side are not compared
+            while old_subkeys.get(i).is_some_and(|o| &o.0 == folded) {
+                i += 1;
+            }
+            while new_subkeys.get(j).is_some_and(|n| &n.0 == folded) {
+                j += 1;
+            }
+        }
+
+        let last_written_before = before.map(|k| k.last_written);
+        let last_written_after = after.map(|k| k.last_written);
+        let change = match (before, after) {
+            (None, _) => Some(Change::Added),
+            (_, None) => Some(Change::Removed),
+            _ if last_written_before != last_written_after || !values.is_empty() => Some(Change::Modified),
+            _ => None,
+        };
+        if change.is_none() && subkeys.is_empty() {
+            return None;
+        }
+        Some(KeyDiff { name, path, change, last_written_before, last_written_after, values, subkeys })
+    }
+}
+
+// Pairs each key with its folded name, sorted by that name
+fn sorted_by_name(keys: Vec<KeyNode>) -> Vec<(Vec<u16>, KeyNode)> {
+    let mut keys: Vec<(Vec<u16>, KeyNode)> = keys.into_iter().map(|key| (hive::fold_name(&key.name), key)).collect();
+    keys.sort_by(|a, b| a.0.cmp(&b.0));
+    keys
+}
+
+/// Walks both hives from their roots and reports every added, removed and
+/// modified key and value. Keys are matched by name, ignoring case.
+pub fn diff_hives(before_path: &str, after_path: &str) -> Result<HiveDiff> {
+    let before = Hive::open(before_path)?;
+    let after = Hive::open(after_path)?;
+    let old_root = before.root_key()?;
+    let new_root = after.root_key()?;
+
+    let mut differ = Differ { before: &before, after: &after, seen: [HashSet::new(), HashSet::new()], errors: Vec::new() };
+    differ.seen[0].insert(old_root.offset);
+    differ.seen[1].insert(new_root.offset);
+    let root = differ.key(Some(&old_root), Some(&new_root), String::new(), 0);
+    Ok(HiveDiff {
+        before_path: before_path.to_string(),
+        after_path: after_path.to_string(),
+        root,
+        errors: differ.errors,
+    })
+}
+
+pub fn print_diff(diff: &HiveDiff) {
+    println!("--- {}", diff.before_path);
+    println!("+++ {}", diff.after_path);
+
+    let mut pending: Vec<&KeyDiff> = diff.root.iter().collect();
+    while let Some(key) = pending.pop() {
+        pending.extend(key.subkeys.iter().rev());
+        let Some(change) = key.change.or((!key.values.is_empty()).then_some(Change::Modified)) else {
+            continue;
+        };
+        println!();
+        print!("{} {}", change.marker(), key.display_path());
+        match (key.last_written_before, key.last_written_after) {
+            (Some(before), Some(after)) if before != after => println!(
+                "  (last written {} -> {})", hive::format_filetime(before), hive::format_filetime(after)
+            ),
+            (Some(time), None) | (None, Some(time)) => println!("  (last written {})", hive::format_filetime(time)),
+            _ => println!(),
+        }
+        for value in &key.values {
+            match (&value.before, &value.after) {
+                (Some(before), Some(after)) => {
+                    println!("    ~ {}: {} -> {}", value.display_name(), before.describe(), after.describe());
+                }
+                (Some(state), None) | (None, Some(state)) => {
+                    println!("    {} {}: {}", value.change.marker(), value.display_name(), state.describe());
+                }
+                (None, None) => {}
+            }
+        }
+    }
+
+    let summary = diff.summary();
+    println!("\nKeys: {} added, {} removed, {} modified", summary.keys[0], summary.keys[1], summary.keys[2]);
+    println!("Values: {} added, {} removed, {} modified", summary.values[0], summary.values[1], summary.values[2]);
+    if diff.root.is_none() {
+        println!("The hives hold the same keys and values.");
+    }
+    if !diff.errors.is_empty() {
+        println!("\n{} part(s) could not be read:", diff.errors.len());
+        for error in &diff.errors {
+            println!("  {}", error);
+        }
+    }
+}
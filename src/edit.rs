@@ -0,0 +1,763 @@
+use crate::hive::{self, Hive, KeyNode, BIG_DATA_SEGMENT_SIZE, DATA_INLINE_FLAG, NO_CELL};
+use crate::registry;
+use crate::types::*;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashSet;
+use std::fmt;
+
+// Longest names Windows accepts, in characters
+const MAX_KEY_NAME: usize = 255;
+const MAX_VALUE_NAME: usize = 16383;
+// Big data records are only understood by hives of format 1.4 and later
+const BIG_DATA_MINOR_VERSION: u32 = 4;
+
+// Key node fields (offsets into the cell payload)
+const KEY_LAST_WRITTEN: usize = 4;
+const KEY_PARENT: usize = 16;
+const KEY_SUBKEY_COUNT: usize = 20;
+const KEY_SUBKEY_LIST: usize = 28;
+const KEY_VALUE_COUNT: usize = 36;
+const KEY_VALUE_LIST: usize = 40;
+const KEY_MAX_NAME: usize = 52;
+const KEY_MAX_VALUE_NAME: usize = 60;
+const KEY_MAX_VALUE_DATA: usize = 64;
+// Security cell fields
+const SECURITY_FLINK: usize = 4;
+const SECURITY_BLINK: usize = 8;
+const SECURITY_REFERENCES: usize = 12;
+// Signatures of a subkey list, and of the lists an ri index may point at
+const LIST_SIGNATURES: [&[u8]; 4] = [b"lf", b"lh", b"li", b"ri"];
+const LEAF_SIGNATURES: [&[u8]; 3] = [b"lf", b"lh", b"li"];
+
+/// One change to an offline hive. Key paths are relative to the hive root.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    /// Creates the key and any missing parents; existing keys are left alone
+    CreateKey(String),
+    /// Deletes the key and everything below it
+    DeleteKey(String),
+    RenameKey { path: String, new_name: String },
+    /// Creates or replaces a value; an empty name is the default value
+    SetValue { key: String, name: String, data_type: u32, data: Vec<u8> },
+    DeleteValue { key: String, name: String },
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() { "(root)" } else { path }
+}
+
+fn display_value(name: &str) -> String {
+    if name.is_empty() { "(Default)".to_string() } else { format!("'{}'", name) }
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Edit::CreateKey(path) => write!(f, "Create key {}", display_path(path)),
+            Edit::DeleteKey(path) => write!(f, "Delete key {} and everything below it", display_path(path)),
+            Edit::RenameKey { path, new_name } => write!(f, "Rename key {} to '{}'", display_path(path), new_name),
+            Edit::SetValue { key, name, data_type, data } => write!(
+                f, "Set value {} in {} to {} {}",
+                display_value(name), display_path(key), hive::value_type_name(*data_type),
+                hive::format_value_data(*data_type, data)
+            ),
+            Edit::DeleteValue { key, name } => write!(f, "Delete value {} from {}", display_value(name), display_path(key)),
+        }
+    }
+}
+
+fn validate_key_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains('\\') {
+        bail!("'{}' is not a valid key name", name);
+    }
+    if name.chars().count() > MAX_KEY_NAME {
+        bail!("Key names are limited to {} characters", MAX_KEY_NAME);
+    }
+    Ok(())
+}
+
+fn touch(hive: &mut Hive, key: u32) {
+    hive.set_cell_u64(key, KEY_LAST_WRITTEN, hive::filetime_now());
+}
+
+/// Raises the "largest subkey name" field of a key so it covers `name`
+pub fn widen_max_name(hive: &mut Hive, key: u32, name: &str) -> Result<()> {
+    widen_field(hive, key, KEY_MAX_NAME, hive::utf16_len(name))
+}
+
+// The upper half of the subkey name field holds flags on recent Windows versions
+fn widen_field(hive: &mut Hive, key: u32, field: usize, length: u32) -> Result<()> {
+    let current = hive::read_u32(hive.cell_data(key)?, field);
+    let mask = if field == KEY_MAX_NAME { 0xFFFF } else { u32::MAX };
+    if length > current & mask {
+        hive.set_cell_u32(key, field, (current & !mask) | length);
+    }
+    Ok(())
+}
+
+pub fn add_security_reference(hive: &mut Hive, security: u32) -> Result<()> {
+    let data = hive.cell_data(security)?;
+    if data.get(0..2) != Some(b"sk") {
+        bail!("Security cell at 0x{:X} is invalid", security);
+    }
+    let count = hive::read_u32(data, SECURITY_REFERENCES);
+    hive.set_cell_u32(security, SECURITY_REFERENCES, count.wrapping_add(1));
+    Ok(())
+}
+
+/// Drops one reference to a security cell, unlinking and freeing it when no
+/// key uses it any more. Cells in `in_use` are never freed.
+fn release_security(hive: &mut Hive, security: u32, in_use: &HashSet<u32>) -> Result<()> {
+    let data = hive.cell_data(security)?;
+    if data.get(0..2) != Some(b"sk") {
+        bail!("Security cell at 0x{:X} is invalid", security);
+    }
+    let count = hive::read_u32(data, SECURITY_REFERENCES);
+    let flink = hive::read_u32(data, SECURITY_FLINK);
+    let blink = hive::read_u32(data, SECURITY_BLINK);
+    // A descriptor whose neighbours are not descriptors is left linked rather
+    // than patching whatever its damaged links point at
+    let linked = [flink, blink].iter().all(|&cell| hive.cell_data(cell).is_ok_and(|d| d.get(0..2) == Some(b"sk")));
+    if count > 1 || in_use.contains(&security) || flink == security || !linked {
+        hive.set_cell_u32(security, SECURITY_REFERENCES, count.saturating_sub(1).max(1));
+        return Ok(());
+    }
+    hive.set_cell_u32(blink, SECURITY_FLINK, flink);
+    hive.set_cell_u32(flink, SECURITY_BLINK, blink);
+    hive.free_cell(security)
+}
+
+/// Copies a key or value record into a new cell carrying a different name
+/// and frees the old one
+pub fn rename_record(hive: &mut Hive, cell: u32, name: &str, is_key: bool) -> Result<u32> {
+    let data = hive.cell_data(cell)?;
+    let (name_bytes, compressed) = hive::encode_name(name);
+    let (header_len, length_field, flags_field, flag) = if is_key {
+        (76, 72, 2, hive::KEY_COMP_NAME)
+    } else {
+        (20, 2, 16, hive::VALUE_COMP_NAME)
+    };
+
+    let mut record = data[..header_len].to_vec();
+    record[length_field..length_field + 2].copy_from_slice(&(name_bytes.len() as u16).to_le_bytes());
+    let flags = hive::read_u16(&record, flags_field);
+    let flags = if compressed { flags | flag } else { flags & !flag };
+    record[flags_field..flags_field + 2].copy_from_slice(&flags.to_le_bytes());
+    record.extend_from_slice(&name_bytes);
+
+    let renamed = hive.allocate_cell(&record)?;
+    hive.free_cell(cell)?;
+    Ok(renamed)
+}
+
+/// `(offset, name)` of every subkey; refuses lists with unreadable entries,
+/// which would otherwise be dropped when the list is rewritten
+pub fn subkey_entries(hive: &Hive, key: &KeyNode) -> Result<Vec<(u32, String)>> {
+    if key.subkey_count == 0 || key.subkey_list == NO_CELL {
+        return Ok(Vec::new());
+    }
+    hive.subkey_offsets(key.subkey_list)?
+        .into_iter()
+        .map(|offset| {
+            hive.key(offset)
+                .map(|k| (offset, k.name))
+                .map_err(|e| anyhow!("Subkey list of '{}' is damaged: {}", key.name, e))
+        })
+        .collect()
+}
+
+/// The subkey list cell of a key and, for an index root, its sublists.
+/// Cells that do not hold a subkey list are left out.
+pub fn subkey_list_cells(hive: &Hive, key: &KeyNode) -> Vec<u32> {
+    if key.subkey_count == 0 || key.subkey_list == NO_CELL {
+        return Vec::new();
+    }
+    let Ok(data) = hive.cell_data(key.subkey_list) else {
+        return Vec::new();
+    };
+    if data.len() < 4 || !LIST_SIGNATURES.contains(&&data[0..2]) {
+        return Vec::new();
+    }
+    let mut cells = vec![key.subkey_list];
+    if &data[0..2] == b"ri" {
+        let count = (hive::read_u16(data, 2) as usize).min((data.len() - 4) / 4);
+        cells.extend((0..count)
+            .map(|i| hive::read_u32(data, 4 + i * 4))
+            .filter(|&leaf| hive.cell_data(leaf).is_ok_and(|d| d.len() >= 4 && LEAF_SIGNATURES.contains(&&d[0..2]))));
+    }
+    cells
+}
+
+/// Frees `cell` when an allocated cell starts there. References read from
+/// damaged records may point anywhere, even into live data; those are left
+/// alone.
+pub fn free_if_cell(hive: &mut Hive, cell: u32) -> Result<()> {
+    if hive.is_allocated_cell(cell) {
+        hive.free_cell(cell)?;
+    }
+    Ok(())
+}
+
+/// Gives a key a new sorted, hashed subkey list holding `entries`, split
+/// under an ri index when they do not fit in one lh list
+pub fn replace_subkey_list(hive: &mut Hive, key: u32, entries: &[(u32, String)]) -> Result<()> {
+    if entries.len() > u16::MAX as usize {
+        bail!("A key cannot hold more than {} subkeys", u16::MAX);
+    }
+    let node = hive.key(key)?;
+    let old = subkey_list_cells(hive, &node);
+
+    if entries.is_empty() {
+        hive.set_cell_u32(key, KEY_SUBKEY_COUNT, 0);
+        hive.set_cell_u32(key, KEY_SUBKEY_LIST, NO_CELL);
+    } else {
+        let list = hive::write_subkey_list(entries, |payload| hive.allocate_cell(payload))?;
+        hive.set_cell_u32(key, KEY_SUBKEY_COUNT, entries.len() as u32);
+        hive.set_cell_u32(key, KEY_SUBKEY_LIST, list);
+    }
+    for cell in old {
+        free_if_cell(hive, cell)?;
+    }
+
+    let longest = entries.iter().map(|(_, name)| hive::utf16_len(name)).max().unwrap_or(0);
+    let current = hive::read_u32(hive.cell_data(key)?, KEY_MAX_NAME);
+    hive.set_cell_u32(key, KEY_MAX_NAME, (current & 0xFFFF_0000) | longest);
+    touch(hive, key);
+    Ok(())
+}
+
+fn replace_value_list(hive: &mut Hive, key: u32, values: &[u32]) -> Result<()> {
+    let node = hive.key(key)?;
+    if values.is_empty() {
+        hive.set_cell_u32(key, KEY_VALUE_COUNT, 0);
+        hive.set_cell_u32(key, KEY_VALUE_LIST, NO_CELL);
+    } else {
+        let list: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let list = hive.allocate_cell(&list)?;
+        hive.set_cell_u32(key, KEY_VALUE_COUNT, values.len() as u32);
+        hive.set_cell_u32(key, KEY_VALUE_LIST, list);
+    }
+    if node.value_count > 0 && node.value_list != NO_CELL {
+        free_if_cell(hive, node.value_list)?;
+    }
+    touch(hive, key);
+    Ok(())
+}
+
+/// Stores value data and returns the `(raw size, data offset)` pair for the
+/// vk cell: inline up to 4 bytes, big data records above one segment
+pub fn store_data(hive: &mut Hive, data: &[u8]) -> Result<(u32, u32)> {
+    if data.len() <= 4 {
+        let mut inline = [0u8; 4];
+        inline[..data.len()].copy_from_slice(data);
+        return Ok((data.len() as u32 | DATA_INLINE_FLAG, u32::from_le_bytes(inline)));
+    }
+    if data.len() <= BIG_DATA_SEGMENT_SIZE || hive.minor_version() < BIG_DATA_MINOR_VERSION {
+        return Ok((data.len() as u32, hive.allocate_cell(data)?));
+    }
+
+    let mut segments = Vec::new();
+    for chunk in data.chunks(BIG_DATA_SEGMENT_SIZE) {
+        segments.push(hive.allocate_cell(chunk)?);
+    }
+    let list: Vec<u8> = segments.iter().flat_map(|s| s.to_le_bytes()).collect();
+    let list = hive.allocate_cell(&list)?;
+    let mut record = vec![0u8; 8];
+    record[0..2].copy_from_slice(b"db");
+    record[2..4].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+    record[4..8].copy_from_slice(&list.to_le_bytes());
+    Ok((data.len() as u32, hive.allocate_cell(&record)?))
+}
+
+// Frees a value and its data. Data that cannot be followed is left where it
+// is, so a value with broken data can still be replaced or deleted.
+fn free_value(hive: &mut Hive, offset: u32) -> Result<()> {
+    let value = hive.value(offset)?;
+    for cell in hive.value_data_cells(&value).unwrap_or_default() {
+        free_if_cell(hive, cell)?;
+    }
+    free_if_cell(hive, offset)
+}
+
+fn find_value(hive: &Hive, key: &KeyNode, name: &str) -> Result<(Vec<u32>, Option<usize>)> {
+    let offsets = hive.value_offsets(key)?;
+    let index = offsets.iter().position(|&offset| {
+        hive.value(offset).map(|v| hive::names_equal(&v.name, name)).unwrap_or(false)
+    });
+    Ok((offsets, index))
+}
+
+/// Opens the key at `path`, creating it and any missing parents. New keys
+/// inherit the security descriptor of their parent.
+pub fn create_key(hive: &mut Hive, path: &str) -> Result<u32> {
+    let mut key = hive.root_key()?;
+    for name in path.split('\\').filter(|n| !n.is_empty()) {
+        let existing = subkey_entries(hive, &key)?.into_iter().find(|(_, n)| hive::names_equal(n, name));
+        let offset = match existing {
+            Some((offset, _)) => offset,
+            None => {
+                validate_key_name(name)?;
+                let record = hive::build_key_node(name, key.offset, key.security, hive::filetime_now());
+                let offset = hive.allocate_cell(&record)?;
+                add_security_reference(hive, key.security)?;
+                let mut entries = subkey_entries(hive, &key)?;
+                entries.push((offset, name.to_string()));
+                replace_subkey_list(hive, key.offset, &entries)?;
+                offset
+            }
+        };
+        key = hive.key(offset)?;
+    }
+    Ok(key.offset)
+}
+
+// The parent of the key at `path`, looked up by path: the key's own parent
+// pointer may be damaged
+fn parent_key(hive: &Hive, path: &str) -> Result<KeyNode> {
+    let names: Vec<&str> = path.split('\\').filter(|n| !n.is_empty()).collect();
+    hive.find_key(&names[..names.len().saturating_sub(1)].join("\\"))
+}
+
+/// Deletes a key and its whole subtree. Only cells no longer referenced by
+/// the rest of the tree are freed, so a corrupt key whose references point
+/// into live data can be removed safely.
+pub fn delete_key(hive: &mut Hive, path: &str) -> Result<()> {
+    let key = hive.find_key(path)?;
+    if key.offset == hive.root_cell_offset() {
+        bail!("The root key cannot be deleted");
+    }
+    let parent = parent_key(hive, path)?;
+    let entries: Vec<(u32, String)> = subkey_entries(hive, &parent)?
+        .into_iter()
+        .filter(|(offset, _)| *offset != key.offset)
+        .collect();
+    replace_subkey_list(hive, parent.offset, &entries)?;
+    free_subtree(hive, key.offset)
+}
+
+/// Frees a subtree that has already been unlinked from its parent. Only
+/// cells no longer referenced by the rest of the tree are freed, and only
+/// where a cell holding the expected record starts.
+pub fn free_subtree(hive: &mut Hive, offset: u32) -> Result<()> {
+    let in_use = hive.walk_tree().referenced_cells;
+    let mut cells = HashSet::new();
+    let mut securities = Vec::new();
+    collect_subtree(hive, offset, 0, &mut cells, &mut securities);
+
+    for security in securities {
+        // Damaged keys may point at anything; skip what cannot be released
+        let _ = release_security(hive, security, &in_use);
+    }
+    // Free in file order so the resulting layout does not depend on hashing
+    let mut cells: Vec<u32> = cells.into_iter().filter(|c| !in_use.contains(c)).collect();
+    cells.sort_unstable();
+    for cell in cells {
+        free_if_cell(hive, cell)?;
+    }
+    Ok(())
+}
+
+// Gathers every cell of a subtree, tolerating damage. Keys, values and
+// lists are only taken when their cell holds the matching record.
+fn collect_subtree(hive: &Hive, offset: u32, depth: usize, cells: &mut HashSet<u32>, securities: &mut Vec<u32>) {
+    if depth > hive::MAX_DEPTH || cells.contains(&offset) {
+        return;
+    }
+    let Ok(key) = hive.key(offset) else {
+        return;
+    };
+    cells.insert(offset);
+    if key.subkey_count > 0 && key.subkey_list != NO_CELL {
+        for child in hive.subkey_offsets(key.subkey_list).unwrap_or_default() {
+            collect_subtree(hive, child, depth + 1, cells, securities);
+        }
+        cells.extend(subkey_list_cells(hive, &key));
+    }
+    for value in hive.value_offsets(&key).unwrap_or_default() {
+        if let Ok(value) = hive.value(value) {
+            cells.insert(value.offset);
+            cells.extend(hive.value_data_cells(&value).unwrap_or_default());
+        }
+    }
+    if key.value_count > 0 && key.value_list != NO_CELL {
+        cells.insert(key.value_list);
+    }
+    if key.class_name != NO_CELL {
+        cells.insert(key.class_name);
+    }
+    if key.security != NO_CELL {
+        securities.push(key.security);
+    }
+}
+
+pub fn rename_key(hive: &mut Hive, path: &str, new_name: &str) -> Result<u32> {
+    validate_key_name(new_name)?;
+    let key = hive.find_key(path)?;
+    if key.offset == hive.root_cell_offset() {
+        bail!("The root key cannot be renamed");
+    }
+    let parent = parent_key(hive, path)?;
+    let mut entries = subkey_entries(hive, &parent)?;
+    if entries.iter().any(|(offset, name)| *offset != key.offset && hive::names_equal(name, new_name)) {
+        bail!("A key named '{}' already exists", new_name);
+    }
+    let children = if key.subkey_count > 0 && key.subkey_list != NO_CELL {
+        hive.subkey_offsets(key.subkey_list)?
+    } else {
+        Vec::new()
+    };
+
+    let renamed = rename_record(hive, key.offset, new_name, true)?;
+    for child in children {
+        hive.set_cell_u32(child, KEY_PARENT, renamed);
+    }
+    for entry in entries.iter_mut().filter(|(offset, _)| *offset == key.offset) {
+        *entry = (renamed, new_name.to_string());
+    }
+    replace_subkey_list(hive, parent.offset, &entries)?;
+    touch(hive, renamed);
+    Ok(renamed)
+}
+
+pub fn set_value(hive: &mut Hive, key_path: &str, name: &str, data_type: u32, data: &[u8]) -> Result<()> {
+    if name.chars().count() > MAX_VALUE_NAME {
+        bail!("Value names are limited to {} characters", MAX_VALUE_NAME);
+    }
+    let key = hive.find_key(key_path)?;
+    let (mut offsets, existing) = find_value(hive, &key, name)?;
+
+    let (raw_size, data_offset) = store_data(hive, data)?;
+    let value = hive.allocate_cell(&hive::build_value_key(name, data_type, raw_size, data_offset))?;
+    match existing {
+        Some(index) => {
+            free_value(hive, offsets[index])?;
+            offsets[index] = value;
+        }
+        None => offsets.push(value),
+    }
+    replace_value_list(hive, key.offset, &offsets)?;
+    widen_field(hive, key.offset, KEY_MAX_VALUE_NAME, hive::utf16_len(name))?;
+    widen_field(hive, key.offset, KEY_MAX_VALUE_DATA, data.len() as u32)
+}
+
+pub fn delete_value(hive: &mut Hive, key_path: &str, name: &str) -> Result<()> {
+    let key = hive.find_key(key_path)?;
+    let (mut offsets, existing) = find_value(hive, &key, name)?;
+    let Some(index) = existing else {
+        bail!("Value {} not found in {}", display_value(name), display_path(key_path));
+    };
+    free_value(hive, offsets.remove(index))?;
+    replace_value_list(hive, key.offset, &offsets)
+}
+
+pub fn apply_edit(hive: &mut Hive, edit: &Edit) -> Result<()> {
+    match edit {
+        Edit::CreateKey(path) => create_key(hive, path).map(|_| ()),
+        Edit::DeleteKey(path) => delete_key(hive, path),
+        Edit::RenameKey { path, new_name } => rename_key(hive, path, new_name).map(|_| ()),
+        Edit::SetValue { key, name, data_type, data } => set_value(hive, key, name, *data_type, data),
+        Edit::DeleteValue { key, name } => delete_value(hive, key, name),
+    }
+}
+
+/// Applies `edits` in order and writes the result to a copy at `output_path`
+/// or, after a `.backup` has been made, to `file_path` itself. Nothing is
+/// written unless every edit succeeds. Returns the analysis of the result.
+pub fn apply_edits(file_path: &str, output_path: Option<&str>, edits: &[Edit]) -> Result<AnalysisResult> {
+    let mut hive = Hive::open(file_path)?;
+    for edit in edits {
+        apply_edit(&mut hive, edit).map_err(|e| anyhow!("{}: {}", edit, e))?;
+    }
+    let target = registry::prepare_target(file_path, output_path)?;
+    registry::commit_hive(target, &hive)?;
+    registry::check_registry_file(target)
+}
+
+/// Parses a data type given as a name (`REG_DWORD`, `dword`) or a number
+pub fn parse_value_type(text: &str) -> Result<u32> {
+    let upper = text.to_uppercase();
+    let name = upper.strip_prefix("REG_").unwrap_or(&upper);
+    (0..=hive::REG_QWORD)
+        .find(|&t| hive::value_type_name(t).strip_prefix("REG_") == Some(name))
+        .or_else(|| parse_number(text).ok().map(|n| n as u32))
+        .ok_or_else(|| anyhow!("Unknown value type '{}'", text))
+}
+
+pub fn parse_number(text: &str) -> Result<u64> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| anyhow!("'{}' is not a number", text))
+}
+
+/// Converts command line data to the bytes stored for `data_type`: strings
+/// are written as UTF-16 with a terminator, one argument per REG_MULTI_SZ
+/// entry, numbers as decimal or 0x-prefixed hex, anything else as hex bytes
+pub fn parse_value_data(data_type: u32, args: &[String]) -> Result<Vec<u8>> {
+    let utf16 = |text: &str| -> Vec<u8> {
+        text.encode_utf16().chain([0]).flat_map(|u| u.to_le_bytes()).collect()
+    };
+    let single = || -> Result<&str> {
+        match args {
+            [one] => Ok(one.as_str()),
+            _ => bail!("{} takes exactly one data argument", hive::value_type_name(data_type)),
+        }
+    };
+    match data_type {
+        hive::REG_SZ | hive::REG_EXPAND_SZ => Ok(utf16(single()?)),
+        hive::REG_MULTI_SZ => {
+            let mut data: Vec<u8> = args.iter().flat_map(|s| utf16(s)).collect();
+            data.extend_from_slice(&[0, 0]);
+            Ok(data)
+        }
+        hive::REG_DWORD => {
+            let value = u32::try_from(parse_number(single()?)?).map_err(|_| anyhow!("DWORD value out of range"))?;
+            Ok(value.to_le_bytes().to_vec())
+        }
+        hive::REG_DWORD_BIG_ENDIAN => {
+            let value = u32::try_from(parse_number(single()?)?).map_err(|_| anyhow!("DWORD value out of range"))?;
+            Ok(value.to_be_bytes().to_vec())
+        }
+        hive::REG_QWORD => Ok(parse_number(single()?)?.to_le_bytes().to_vec()),
+        _ => {
+            let hex: String = args.concat().chars().filter(|c| !c.is_whitespace() && *c != ',').collect();
+            if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+                bail!("Hex data must be pairs of hex digits");
+            }
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| anyhow!(e)))
+                .collect()
+        }
+    }
+}
+
+/// What an edit would do to the hive, worked out before anything is written
+#[derive(Debug, Clone)]
+pub enum Effect {
+    Change(String),
+    /// The hive already matches; the edit is dropped
+    NoChange(String),
+    Fails(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PreviewEntry {
+    pub edit: Edit,
+    pub effect: Effect,
+}
+
+impl fmt::Display for PreviewEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.effect {
+            Effect::Change(note) if note.is_empty() => write!(f, "{}", self.edit),
+            Effect::Change(note) => write!(f, "{} ({})", self.edit, note),
+            Effect::NoChange(note) => write!(f, "{} (skipped: {})", self.edit, note),
+            Effect::Fails(error) => write!(f, "{} (FAILS: {})", self.edit, error),
+        }
+    }
+}
+
+// Describes an edit against the current state of `hive`
+fn describe(hive: &Hive, edit: &Edit) -> Effect {
+    match edit {
+        Edit::CreateKey(path) => match hive.find_key(path) {
+            Ok(_) => Effect::NoChange("already exists".to_string()),
+            Err(_) => Effect::Change("new key".to_string()),
+        },
+        Edit::DeleteKey(path) => match hive.find_key(path) {
+            Ok(key) => {
+                let mut cells = HashSet::new();
+                collect_subtree(hive, key.offset, 0, &mut cells, &mut Vec::new());
+                let keys = cells.iter().filter(|&&c| hive.key(c).is_ok()).count();
+                let values = cells.iter().filter(|&&c| hive.value(c).is_ok()).count();
+                Effect::Change(format!("removes {} key(s) and {} value(s)", keys, values))
+            }
+            Err(_) => Effect::NoChange("not present".to_string()),
+        },
+        Edit::RenameKey { .. } => Effect::Change(String::new()),
+        Edit::SetValue { key, name, data_type, data } => {
+            let current = hive.find_key(key).ok()
+                .and_then(|k| {
+                    let (offsets, index) = find_value(hive, &k, name).ok()?;
+                    hive.value(offsets[index?]).ok()
+                })
+                .map(|v| (v.data_type, hive.value_data(&v).ok()));
+            match current {
+                Some((current_type, Some(current_data))) if current_type == *data_type && &current_data == data => {
+                    Effect::NoChange("already set".to_string())
+                }
+                Some((current_type, current_data)) => Effect::Change(format!(
+                    "was {} {}",
+                    hive::value_type_name(current_type),
+                    current_data.map(|d| hive::format_value_data(current_type, &d)).unwrap_or_else(|| "<unreadable>".to_string())
+                )),
+                None => Effect::Change("new value".to_string()),
+            }
+        }
+        Edit::DeleteValue { key, name } => {
+            let present = hive.find_key(key).ok()
+                .and_then(|k| find_value(hive, &k, name).ok())
+                .map(|(_, index)| index.is_some())
+                .unwrap_or(false);
+            if present {
+                Effect::Change(String::new())
+            } else {
+                Effect::NoChange("not present".to_string())
+            }
+        }
+    }
+}
+
+/// Runs `edits` against an in-memory copy of `hive` and reports what each one
+/// would do, without touching any file
+pub fn preview_edits(hive: &Hive, edits: &[Edit]) -> Vec<PreviewEntry> {
+    let mut simulated = hive.clone();
+    edits.iter()
+        .map(|edit| {
+            let effect = match describe(&simulated, edit) {
+                Effect::Change(note) => match apply_edit(&mut simulated, edit) {
+                    Ok(()) => Effect::Change(note),
+                    Err(e) => Effect::Fails(e.to_string()),
+                },
+                other => other,
+            };
+            PreviewEntry { edit: edit.clone(), effect }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rebuild::{self, SalvagedKey};
+
+    #[test]
+    fn subkey_lists_are_sorted_split_and_freed() {
+        let mut hive = rebuild::build_test_hive(&SalvagedKey::named("ROOT"));
+        let root = hive.root_cell_offset();
+        let security = hive.root_key().unwrap().security;
+        let entries: Vec<(u32, String)> = (0..hive::MAX_LEAF_ENTRIES + 1)
+            .rev()
+            .map(|i| {
+                let name = format!("key{:04}", i);
+                (hive.allocate_cell(&hive::build_key_node(&name, root, security, 0)).unwrap(), name)
+            })
+            .collect();
+
+        replace_subkey_list(&mut hive, root, &entries).unwrap();
+        let key = hive.root_key().unwrap();
+        assert_eq!(key.subkey_count as usize, entries.len());
+        assert_eq!(&hive.cell_payload(key.subkey_list).unwrap()[0..2], b"ri");
+        let names: Vec<String> = hive.subkeys(&key).unwrap().into_iter().map(|k| k.name).collect();
+        let mut expected: Vec<String> = entries.iter().map(|(_, name)| name.clone()).collect();
+        expected.sort();
+        assert_eq!(names, expected);
+        assert!(hive.walk_tree().errors.is_empty());
+        assert_eq!(hive::read_u32(hive.cell_payload(root).unwrap(), KEY_MAX_NAME) & 0xFFFF, 14);
+
+        // A short list replaces the index, and the index and its leaves are freed
+        let old = subkey_list_cells(&hive, &key);
+        assert_eq!(old.len(), 3);
+        replace_subkey_list(&mut hive, root, &entries[..2]).unwrap();
+        let key = hive.root_key().unwrap();
+        assert_eq!(&hive.cell_payload(key.subkey_list).unwrap()[0..2], b"lh");
+        assert_eq!(hive.subkeys(&key).unwrap().len(), 2);
+        for cell in old {
+            assert!(!hive.cell(cell).unwrap().allocated, "list cell 0x{:X} still allocated", cell);
+        }
+
+        replace_subkey_list(&mut hive, root, &[]).unwrap();
+        let key = hive.root_key().unwrap();
+        assert_eq!((key.subkey_count, key.subkey_list), (0, NO_CELL));
+    }
+
+    #[test]
+    fn values_with_broken_data_can_be_replaced_and_deleted() {
+        let mut key = SalvagedKey::named("Key");
+        for name in ["Small", "Big", "Other"] {
+            key.values.push(rebuild::SalvagedValue { name: name.to_string(), data_type: hive::REG_BINARY, data: vec![1; 3 * BIG_DATA_SEGMENT_SIZE] });
+        }
+        let mut root = SalvagedKey::named("ROOT");
+        root.subkeys.push(key);
+        let mut hive = rebuild::build_test_hive(&root);
+        let key = hive.find_key("Key").unwrap();
+        let values = hive.value_offsets(&key).unwrap();
+        // Data offset beyond the end of the hive, and a big data record whose segment list is gone
+        hive.set_cell_u32(values[0], 8, 0x7FFF_FFF0);
+        let big = hive.value(values[1]).unwrap();
+        hive.set_cell_u32(big.data_offset, 4, 0x7FFF_FFF0);
+        assert!(hive.value_data(&hive.value(values[1]).unwrap()).is_err());
+
+        set_value(&mut hive, "Key", "Small", hive::REG_DWORD, &5u32.to_le_bytes()).unwrap();
+        delete_value(&mut hive, "Key", "Big").unwrap();
+        let key = hive.find_key("Key").unwrap();
+        let values: Vec<(String, Vec<u8>)> = hive.value_offsets(&key).unwrap().into_iter()
+            .map(|offset| hive.value(offset).unwrap())
+            .map(|value| (value.name.clone(), hive.value_data(&value).unwrap()))
+            .collect();
+        assert_eq!(values, vec![
+            ("Small".to_string(), 5u32.to_le_bytes().to_vec()),
+            ("Other".to_string(), vec![1; 3 * BIG_DATA_SEGMENT_SIZE]),
+        ]);
+    }
+
+    #[test]
+    fn keys_are_unlinked_from_their_parent_by_path() {
+        let mut parent = SalvagedKey::named("Parent");
+        parent.subkeys = vec![SalvagedKey::named("A"), SalvagedKey::named("B"), SalvagedKey::named("C")];
+        let mut root = SalvagedKey::named("ROOT");
+        root.subkeys = vec![parent, SalvagedKey::named("Other")];
+        let mut hive = rebuild::build_test_hive(&root);
+        // Damaged parent pointers: one at another key, one at nothing
+        let other = hive.find_key("Other").unwrap().offset;
+        let a = hive.find_key("Parent\\A").unwrap().offset;
+        let b = hive.find_key("Parent\\B").unwrap().offset;
+        hive.set_cell_u32(a, KEY_PARENT, other);
+        hive.set_cell_u32(b, KEY_PARENT, 0x7FFF_FFF0);
+
+        rename_key(&mut hive, "Parent\\A", "Renamed").unwrap();
+        delete_key(&mut hive, "Parent\\B").unwrap();
+        let names = |hive: &Hive, path: &str| -> Vec<String> {
+            hive.subkeys(&hive.find_key(path).unwrap()).unwrap().into_iter().map(|k| k.name).collect()
+        };
+        assert_eq!(names(&hive, "Parent"), vec!["C", "Renamed"]);
+        assert!(names(&hive, "Other").is_empty());
+        assert_eq!(names(&hive, ""), vec!["Other", "Parent"]);
+    }
+
+    #[test]
+    fn deleting_a_damaged_key_leaves_live_cells_alone() {
+        // Inside the data of a live value, bytes that look like a key node cell
+        let mut decoy = vec![0u8; 4];
+        decoy.extend_from_slice(&(-88i32).to_le_bytes());
+        decoy.extend_from_slice(&hive::build_key_node("Decoy", NO_CELL, NO_CELL, 0));
+        decoy.resize(128, 0xCC);
+        let mut keep = SalvagedKey::named("Keep");
+        keep.values.push(rebuild::SalvagedValue { name: "Data".to_string(), data_type: hive::REG_BINARY, data: decoy.clone() });
+        let mut parent = SalvagedKey::named("Parent");
+        parent.subkeys = vec![SalvagedKey::named("A"), SalvagedKey::named("B")];
+        let mut root = SalvagedKey::named("ROOT");
+        root.subkeys = vec![keep, parent];
+        let mut hive = rebuild::build_test_hive(&root);
+
+        let keep = hive.find_key("Keep").unwrap();
+        let value = hive.value(hive.value_offsets(&keep).unwrap()[0]).unwrap();
+        // Point the second entry of Parent's subkey list into the middle of that data
+        let parent = hive.find_key("Parent").unwrap();
+        hive.set_cell_u32(parent.subkey_list, 12, value.data_offset + 8);
+        assert_eq!(hive.find_key("Parent\\Decoy").unwrap().offset, value.data_offset + 8);
+
+        delete_key(&mut hive, "Parent").unwrap();
+        assert!(hive.find_key("Parent").is_err());
+        assert!(hive.is_allocated_cell(value.data_offset));
+        assert_eq!(hive.value_data(&value).unwrap(), decoy);
+        for _ in 0..8 {
+            let cell = hive.allocate_cell(&[0xEE; 80]).unwrap();
+            assert!(cell < value.data_offset || cell >= value.data_offset + 136, "allocated 0x{:X} inside live data", cell);
+        }
+        assert_eq!(hive.value_data(&value).unwrap(), decoy);
+        assert!(hive.walk_tree().errors.is_empty());
+    }
+}
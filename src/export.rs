@@ -0,0 +1,335 @@
+use crate::hive::{self, Hive, KeyNode};
+use crate::regfile;
+use crate::types::*;
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+/// A value flattened for export
+#[derive(Debug, Clone)]
+pub struct ExportedValue {
+    pub name: String,
+    pub data_type: u32,
+    pub size: u32,
+    pub data: Option<Vec<u8>>,
+    /// Why the data could not be read
+    pub error: Option<String>,
+}
+
+impl ExportedValue {
+    pub fn decoded(&self) -> Option<String> {
+        self.data.as_ref().map(|data| hive::format_value_data(self.data_type, data))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "type": hive::value_type_name(self.data_type),
+            "size": self.size,
+            "data": self.decoded(),
+            "data_hex": self.data.as_ref().map(|d| d.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+            "error": self.error,
+        })
+    }
+}
+
+/// A key flattened for export, with its values
+#[derive(Debug, Clone)]
+pub struct ExportedKey {
+    pub path: String,
+    pub name: String,
+    pub offset: u32,
+    pub last_written: u64,
+    pub class_name: Option<String>,
+    /// Offset of the key's sk cell
+    pub security: u32,
+    pub subkey_count: u32,
+    pub values: Vec<ExportedValue>,
+}
+
+impl ExportedKey {
+    fn to_json(&self) -> Value {
+        json!({
+            "record": "key",
+            "path": self.path,
+            "name": self.name,
+            "cell": self.offset,
+            "last_written": hive::format_filetime(self.last_written),
+            "last_written_filetime": self.last_written,
+            "class": self.class_name,
+            "security_cell": self.security,
+            "subkey_count": self.subkey_count,
+            "values": self.values.iter().map(ExportedValue::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// An issue from `check_registry_file` with the key it concerns, if any
+#[derive(Debug, Clone)]
+pub struct ExportedIssue {
+    pub issue: ValidationIssue,
+    pub path: Option<String>,
+}
+
+impl ExportedIssue {
+    fn to_json(&self) -> Value {
+        json!({
+            "record": "issue",
+            "code": self.issue.code.as_str(),
+            "severity": self.issue.severity.to_string(),
+            "message": self.issue.message,
+            "details": self.issue.details,
+            "fix": self.issue.fix_type.as_ref().map(|f| format!("{:?}", f)),
+            "path": self.path,
+            "location": self.issue.location.as_ref().map(|l| l.to_json()),
+        })
+    }
+}
+
+/// The readable part of a hive flattened into rows
+#[derive(Debug, Default)]
+pub struct HiveExport {
+    pub keys: Vec<ExportedKey>,
+    pub issues: Vec<ExportedIssue>,
+    /// Parts of the tree that could not be read
+    pub errors: Vec<String>,
+}
+
+fn export_key(hive: &Hive, key: &KeyNode, path: String, errors: &mut Vec<String>) -> ExportedKey {
+    let class_name = hive.class_name(key).unwrap_or_else(|e| {
+        errors.push(format!("{}: {}", path, e));
+        None
+    });
+
+    let mut values = Vec::new();
+    match hive.value_offsets(key) {
+        Ok(offsets) => {
+            for offset in offsets {
+                match hive.value(offset) {
+                    Ok(value) => {
+                        let (data, error) = match hive.value_data(&value) {
+                            Ok(data) => (Some(data), None),
+                            Err(e) => (None, Some(e.to_string())),
+                        };
+                        values.push(ExportedValue {
+                            size: value.data_size(),
+                            name: value.name,
+                            data_type: value.data_type,
+                            data,
+                            error,
+                        });
+                    }
+                    Err(e) => errors.push(format!("{}: {}", path, e)),
+                }
+            }
+        }
+        Err(e) => errors.push(format!("{}: {}", path, e)),
+    }
+
+    ExportedKey {
+        path,
+        name: key.name.clone(),
+        offset: key.offset,
+        last_written: key.last_written,
+        class_name,
+        security: key.security,
+        subkey_count: key.subkey_count,
+        values,
+    }
+}
+
+/// Key an issue concerns; orphaned keys are placed under their parent's path
+fn issue_path(issue: &ValidationIssue, root_prefix: &str) -> Option<String> {
+    let path = issue.location.as_ref()?.key_path.as_ref()?;
+    Some(regfile::full_path(root_prefix, path))
+}
+
+/// Flattens every reachable key, in tree order, together with the issues
+/// found by `analysis`. Paths start with `root_prefix`, or with the root
+/// key's name when it is empty.
+pub fn flatten(hive: &Hive, analysis: &AnalysisResult, root_prefix: &str) -> Result<HiveExport> {
+    let root = hive.root_key()?;
+    let root_prefix = if root_prefix.is_empty() {
+        root.name.clone()
+    } else {
+        root_prefix.trim_end_matches('\\').to_string()
+    };
+
+    let mut export = HiveExport::default();
+    let mut seen = HashSet::new();
+    let mut pending = vec![(root, String::new())];
+    while let Some((key, path)) = pending.pop() {
+        if !seen.insert(key.offset) {
+            export.errors.push(format!("Key at 0x{:X} is referenced more than once", key.offset));
+            continue;
+        }
+        match hive.subkeys(&key) {
+            Ok(subkeys) => {
+                for subkey in subkeys.into_iter().rev() {
+                    let subkey_path = hive::join_path(&path, &subkey.name);
+                    pending.push((subkey, subkey_path));
+                }
+            }
+            Err(e) => export.errors.push(format!("{}: {}", regfile::full_path(&root_prefix, &path), e)),
+        }
+        let full = regfile::full_path(&root_prefix, &path);
+        let exported = export_key(hive, &key, full, &mut export.errors);
+        export.keys.push(exported);
+    }
+
+    export.issues = analysis.issues.iter()
+        .map(|issue| ExportedIssue {
+            path: issue_path(issue, &root_prefix),
+            issue: issue.clone(),
+        })
+        .collect();
+    Ok(export)
+}
+
+/// Writes one JSON object per key followed by one per issue; the `record`
+/// field tells them apart
+pub fn write_json_lines(export: &HiveExport, output_path: &str) -> Result<()> {
+    let mut file = BufWriter::new(File::create(output_path)?);
+    for key in &export.keys {
+        writeln!(file, "{}", key.to_json())?;
+    }
+    for issue in &export.issues {
+        writeln!(file, "{}", issue.to_json())?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE hive (
+        file TEXT NOT NULL,
+        root_name TEXT,
+        last_written TEXT,
+        primary_seq INTEGER,
+        secondary_seq INTEGER,
+        exported_at TEXT NOT NULL
+    );
+    CREATE TABLE keys (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        parent_id INTEGER REFERENCES keys(id),
+        name TEXT NOT NULL,
+        cell INTEGER NOT NULL,
+        last_written TEXT,
+        last_written_filetime INTEGER,
+        class TEXT,
+        security_cell INTEGER,
+        subkey_count INTEGER
+    );
+    CREATE TABLE reg_values (
+        id INTEGER PRIMARY KEY,
+        key_id INTEGER NOT NULL REFERENCES keys(id),
+        name TEXT NOT NULL,
+        type TEXT NOT NULL,
+        size INTEGER,
+        data TEXT,
+        data_raw BLOB,
+        error TEXT
+    );
+    CREATE TABLE issues (
+        id INTEGER PRIMARY KEY,
+        code TEXT NOT NULL,
+        severity TEXT NOT NULL,
+        message TEXT NOT NULL,
+        details TEXT,
+        fix TEXT,
+        key_path TEXT,
+        file_offset INTEGER,
+        length INTEGER,
+        cell INTEGER
+    );
+    CREATE INDEX keys_path ON keys(path);
+    CREATE INDEX values_key ON reg_values(key_id);
+    CREATE INDEX issues_path ON issues(key_path);
+";
+
+/// Writes the export into a new SQLite database with `keys`, `reg_values` and
+/// `issues` tables. An existing file at `output_path` is replaced.
+pub fn write_sqlite(export: &HiveExport, analysis: &AnalysisResult, output_path: &str) -> Result<()> {
+    if fs::metadata(output_path).is_ok() {
+        fs::remove_file(output_path)?;
+    }
+    let mut db = Connection::open(output_path)?;
+    db.execute_batch(SCHEMA)?;
+
+    let transaction = db.transaction()?;
+    let info = &analysis.file_info;
+    transaction.execute(
+        "INSERT INTO hive (file, root_name, last_written, primary_seq, secondary_seq, exported_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            info.path,
+            export.keys.first().map(|k| k.name.clone()),
+            hive::format_filetime(info.last_written),
+            info.primary_seq_num,
+            info.secondary_seq_num,
+            chrono::Utc::now().to_rfc3339(),
+        ],
+    )?;
+
+    let mut ids: HashMap<&str, i64> = HashMap::new();
+    for key in &export.keys {
+        let parent = key.path.rsplit_once('\\').and_then(|(parent, _)| ids.get(parent).copied());
+        transaction.execute(
+            "INSERT INTO keys (path, parent_id, name, cell, last_written, last_written_filetime, class, security_cell, subkey_count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                key.path,
+                parent,
+                key.name,
+                key.offset,
+                hive::format_filetime(key.last_written),
+                key.last_written as i64,
+                key.class_name,
+                key.security,
+                key.subkey_count,
+            ],
+        )?;
+        let id = transaction.last_insert_rowid();
+        ids.insert(&key.path, id);
+
+        for value in &key.values {
+            transaction.execute(
+                "INSERT INTO reg_values (key_id, name, type, size, data, data_raw, error)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    id,
+                    value.name,
+                    hive::value_type_name(value.data_type),
+                    value.size,
+                    value.decoded(),
+                    value.data,
+                    value.error,
+                ],
+            )?;
+        }
+    }
+
+    for issue in &export.issues {
+        transaction.execute(
+            "INSERT INTO issues (code, severity, message, details, fix, key_path, file_offset, length, cell)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                issue.issue.code.as_str(),
+                issue.issue.severity.to_string(),
+                issue.issue.message,
+                issue.issue.details,
+                issue.issue.fix_type.as_ref().map(|f| format!("{:?}", f)),
+                issue.path,
+                issue.issue.location.as_ref().map(|l| l.offset as i64),
+                issue.issue.location.as_ref().map(|l| l.length as i64),
+                issue.issue.location.as_ref().and_then(|l| l.cell),
+            ],
+        )?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
@@ -0,0 +1,354 @@
+use crate::edit;
+use crate::hive::{self, Hive, NO_CELL};
+use crate::rebuild::{self, SalvagedKey};
+use crate::registry;
+use crate::types::*;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashSet;
+
+// Key node fields set on grafted keys
+const KEY_FLAGS: usize = 2;
+const KEY_LAST_WRITTEN: usize = 4;
+const KEY_VALUE_COUNT: usize = 36;
+const KEY_VALUE_LIST: usize = 40;
+const KEY_CLASS: usize = 48;
+const KEY_MAX_CLASS: usize = 56;
+const KEY_MAX_VALUE_NAME: usize = 60;
+const KEY_MAX_VALUE_DATA: usize = 64;
+const KEY_CLASS_LENGTH: usize = 74;
+// Security cell fields
+const SECURITY_FLINK: usize = 4;
+const SECURITY_BLINK: usize = 8;
+const SECURITY_DESCRIPTOR_SIZE: usize = 16;
+const SECURITY_HEADER: usize = 20;
+
+#[derive(Debug)]
+pub struct GraftReport {
+    pub key_path: String,
+    /// Whether a key already at the path was replaced (or merged into)
+    pub existing: bool,
+    pub keys_added: usize,
+    pub values_written: usize,
+    /// Security cells added to the target because it had no identical descriptor
+    pub security_added: usize,
+    /// Unreadable subkey entries dropped from the parent key's list
+    pub dropped_entries: Vec<u32>,
+    /// Parts of the donor subtree that could not be read
+    pub donor_dropped: Vec<String>,
+    /// Problems that did not stop the graft
+    pub warnings: Vec<String>,
+    pub target_before: AnalysisResult,
+    pub donor_before: AnalysisResult,
+    pub validation: Result<AnalysisResult>,
+}
+
+struct Grafter<'a> {
+    hive: &'a mut Hive,
+    descriptors: &'a [Vec<u8>],
+    /// Security cell in the target for each donor descriptor, once needed
+    security: Vec<Option<u32>>,
+    security_added: usize,
+    keys_added: usize,
+    values_written: usize,
+}
+
+impl<'a> Grafter<'a> {
+    /// The target's security cell holding donor descriptor `index`, reusing an
+    /// identical descriptor when the target already has one
+    fn security_cell(&mut self, index: usize) -> Result<u32> {
+        if let Some(cell) = self.security[index] {
+            return Ok(cell);
+        }
+        let descriptor = &self.descriptors[index];
+        let first = self.hive.root_key()?.security;
+        let mut seen = HashSet::new();
+        let mut cell = first;
+        while seen.insert(cell) {
+            let data = self.hive.cell_data(cell)
+                .map_err(|e| anyhow!("The target's security chain is damaged at 0x{:X}: {}", cell, e))?;
+            if data.get(0..2) != Some(b"sk") {
+                bail!("The target's security chain is damaged at 0x{:X}", cell);
+            }
+            let size = hive::read_u32(data, SECURITY_DESCRIPTOR_SIZE) as usize;
+            if data.get(SECURITY_HEADER..SECURITY_HEADER + size) == Some(descriptor.as_slice()) {
+                self.security[index] = Some(cell);
+                return Ok(cell);
+            }
+            cell = hive::read_u32(data, SECURITY_FLINK);
+        }
+
+        // Not found: link a new cell into the chain right after the root's
+        let mut record = vec![0u8; SECURITY_HEADER];
+        record[0..2].copy_from_slice(b"sk");
+        record[SECURITY_DESCRIPTOR_SIZE..SECURITY_HEADER].copy_from_slice(&(descriptor.len() as u32).to_le_bytes());
+        record.extend_from_slice(descriptor);
+        let added = self.hive.allocate_cell(&record)?;
+        let next = hive::read_u32(self.hive.cell_data(first)?, SECURITY_FLINK);
+        self.hive.set_cell_u32(added, SECURITY_FLINK, next);
+        self.hive.set_cell_u32(added, SECURITY_BLINK, first);
+        self.hive.set_cell_u32(first, SECURITY_FLINK, added);
+        self.hive.set_cell_u32(next, SECURITY_BLINK, added);
+        self.security[index] = Some(added);
+        self.security_added += 1;
+        Ok(added)
+    }
+
+    /// Writes a donor key and its subtree below `parent`, returning its nk cell.
+    /// The caller links it into the parent's subkey list.
+    fn create(&mut self, key: &SalvagedKey, parent: u32) -> Result<u32> {
+        let security = self.security_cell(key.security)?;
+        let mut record = hive::build_key_node(&key.name, parent, security, key.last_written);
+        let flags = hive::read_u16(&record, KEY_FLAGS) | key.flags;
+        record[KEY_FLAGS..KEY_FLAGS + 2].copy_from_slice(&flags.to_le_bytes());
+
+        if let Some(class) = &key.class {
+            let cell = self.hive.allocate_cell(class)?;
+            record[KEY_CLASS..KEY_CLASS + 4].copy_from_slice(&cell.to_le_bytes());
+            record[KEY_CLASS_LENGTH..KEY_CLASS_LENGTH + 2].copy_from_slice(&(class.len() as u16).to_le_bytes());
+        }
+
+        if !key.values.is_empty() {
+            let mut list = Vec::with_capacity(key.values.len() * 4);
+            for value in &key.values {
+                let (raw_size, data_offset) = edit::store_data(self.hive, &value.data)?;
+                let cell = self.hive.allocate_cell(&hive::build_value_key(&value.name, value.data_type, raw_size, data_offset))?;
+                list.extend_from_slice(&cell.to_le_bytes());
+            }
+            let list = self.hive.allocate_cell(&list)?;
+            let longest_name = key.values.iter().map(|v| hive::utf16_len(&v.name)).max().unwrap_or(0);
+            let largest_data = key.values.iter().map(|v| v.data.len() as u32).max().unwrap_or(0);
+            record[KEY_VALUE_COUNT..KEY_VALUE_COUNT + 4].copy_from_slice(&(key.values.len() as u32).to_le_bytes());
+            record[KEY_VALUE_LIST..KEY_VALUE_LIST + 4].copy_from_slice(&list.to_le_bytes());
+            record[KEY_MAX_VALUE_NAME..KEY_MAX_VALUE_NAME + 4].copy_from_slice(&longest_name.to_le_bytes());
+            record[KEY_MAX_VALUE_DATA..KEY_MAX_VALUE_DATA + 4].copy_from_slice(&largest_data.to_le_bytes());
+            self.values_written += key.values.len();
+        }
+        if let Some(largest_class) = key.subkeys.iter().filter_map(|k| k.class.as_ref()).map(|c| c.len() as u32).max() {
+            record[KEY_MAX_CLASS..KEY_MAX_CLASS + 4].copy_from_slice(&largest_class.to_le_bytes());
+        }
+
+        let offset = self.hive.allocate_cell(&record)?;
+        edit::add_security_reference(self.hive, security)?;
+        self.keys_added += 1;
+
+        if !key.subkeys.is_empty() {
+            let mut entries = Vec::with_capacity(key.subkeys.len());
+            for subkey in &key.subkeys {
+                entries.push((self.create(subkey, offset)?, subkey.name.clone()));
+            }
+            edit::replace_subkey_list(self.hive, offset, &entries)?;
+            // Linking the subkeys touches the key; keep the donor's timestamp
+            self.hive.set_cell_u64(offset, KEY_LAST_WRITTEN, key.last_written);
+        }
+        Ok(offset)
+    }
+
+    /// Copies the donor's values and missing subkeys into an existing key,
+    /// keeping everything of the target's that the donor lacks
+    fn merge(&mut self, key: &SalvagedKey, path: &str) -> Result<()> {
+        for value in &key.values {
+            edit::set_value(self.hive, path, &value.name, value.data_type, &value.data)?;
+            self.values_written += 1;
+        }
+        let target = self.hive.find_key(path)?;
+        let mut entries = edit::subkey_entries(self.hive, &target)?;
+        let mut added = false;
+        for subkey in &key.subkeys {
+            if entries.iter().any(|(_, name)| hive::names_equal(name, &subkey.name)) {
+                self.merge(subkey, &hive::join_path(path, &subkey.name))?;
+            } else {
+                entries.push((self.create(subkey, target.offset)?, subkey.name.clone()));
+                added = true;
+            }
+        }
+        if added {
+            edit::replace_subkey_list(self.hive, target.offset, &entries)?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies the key at `key_path` in `donor_path`, with all its subkeys, values
+/// and security descriptors, into the same place in `file_path`. An existing
+/// key there is replaced, or with `merge` overlaid: donor values win and
+/// subkeys only the target has are kept. Writes to `output_path` or, after a
+/// backup, to `file_path` itself.
+pub fn graft(file_path: &str, donor_path: &str, key_path: &str, output_path: Option<&str>, merge: bool) -> Result<GraftReport> {
+    let key_path = key_path.trim_matches('\\');
+    if key_path.is_empty() {
+        bail!("The root key cannot be grafted; use rebuild or merge to replace a whole hive");
+    }
+    let target_before = registry::check_registry_file(file_path)?;
+    let donor_before = registry::check_registry_file(donor_path)?;
+
+    let donor = Hive::open(donor_path)?;
+    let salvaged = rebuild::salvage_subtree(&donor, key_path)
+        .map_err(|e| anyhow!("Cannot read '{}' from the donor: {}", key_path, e))?;
+
+    let mut hive = Hive::open(file_path)?;
+    let (parent_path, name) = key_path.rsplit_once('\\').unwrap_or(("", key_path));
+    let parent = edit::create_key(&mut hive, parent_path)
+        .map_err(|e| anyhow!("Cannot open the parent key in the target: {}", e))?;
+
+    // Read the parent's list by hand: the key being replaced may be unreadable
+    let parent_node = hive.key(parent)?;
+    let offsets = if parent_node.subkey_count == 0 || parent_node.subkey_list == NO_CELL {
+        Vec::new()
+    } else {
+        hive.subkey_offsets(parent_node.subkey_list)?
+    };
+    let mut entries = Vec::new();
+    let mut existing = None;
+    let mut dropped_entries = Vec::new();
+    for offset in offsets {
+        match hive.key(offset) {
+            Ok(key) if hive::names_equal(&key.name, name) => existing = Some(offset),
+            Ok(key) => entries.push((offset, key.name)),
+            Err(_) => dropped_entries.push(offset),
+        }
+    }
+
+    let mut grafter = Grafter {
+        hive: &mut hive,
+        descriptors: &salvaged.descriptors,
+        security: vec![None; salvaged.descriptors.len()],
+        security_added: 0,
+        keys_added: 0,
+        values_written: 0,
+    };
+    let mut warnings = Vec::new();
+    match existing {
+        Some(_) if merge => {
+            if !dropped_entries.is_empty() {
+                bail!("The subkey list of '{}' has unreadable entries; graft without --merge to replace them", parent_path);
+            }
+            grafter.merge(&salvaged.key, key_path)?;
+        }
+        _ => {
+            let grafted = grafter.create(&salvaged.key, parent)?;
+            entries.push((grafted, salvaged.key.name.clone()));
+            edit::replace_subkey_list(grafter.hive, parent, &entries)?;
+            if let Some(old) = existing {
+                // The old subtree is typically the damaged one; free_subtree
+                // skips references that do not lead to a cell start holding
+                // the expected record, so live cells are never released
+                if let Err(e) = edit::free_subtree(grafter.hive, old) {
+                    warnings.push(format!("The replaced subtree could not be freed completely: {}", e));
+                }
+            }
+        }
+    }
+    let (keys_added, values_written, security_added) = (grafter.keys_added, grafter.values_written, grafter.security_added);
+
+    let target = registry::prepare_target(file_path, output_path)?;
+    registry::commit_hive(target, &hive)?;
+
+    Ok(GraftReport {
+        key_path: key_path.to_string(),
+        existing: existing.is_some(),
+        keys_added,
+        values_written,
+        security_added,
+        dropped_entries,
+        donor_dropped: salvaged.dropped,
+        warnings,
+        target_before,
+        donor_before,
+        validation: registry::check_registry_file(target),
+    })
+}
+
+pub fn print_report(report: &GraftReport, merge: bool) {
+    println!("Before: target has {} issue(s), donor has {} issue(s)",
+        report.target_before.issues.len(), report.donor_before.issues.len());
+
+    let action = match (report.existing, merge) {
+        (true, true) => "Merged into",
+        (true, false) => "Replaced",
+        (false, _) => "Created",
+    };
+    println!("{} {}: {} key(s) added, {} value(s) written, {} security descriptor(s) added",
+        action, report.key_path, report.keys_added, report.values_written, report.security_added);
+
+    if !report.dropped_entries.is_empty() {
+        println!("\n{} unreadable subkey entr{} removed from the parent key:",
+            report.dropped_entries.len(), if report.dropped_entries.len() == 1 { "y was" } else { "ies were" });
+        for offset in &report.dropped_entries {
+            println!("  0x{:X}", offset);
+        }
+        println!("Whatever they referred to is left in place; analyze the hive to recover or discard it.");
+    }
+    if !report.donor_dropped.is_empty() {
+        println!("\n{} part(s) of the donor subtree could not be read and were left out:", report.donor_dropped.len());
+        for item in &report.donor_dropped {
+            println!("  {}", item);
+        }
+    }
+    for warning in &report.warnings {
+        println!("\nWARNING: {}", warning);
+    }
+
+    match &report.validation {
+        Ok(result) if result.issues.is_empty() => println!("\nGrafted hive validated with no issues."),
+        Ok(result) => println!("\nGrafted hive validated with {} issue(s).", result.issues.len()),
+        Err(e) => println!("\nGrafted hive failed validation: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rebuild::SalvagedValue;
+    use std::fs;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("regfix_graft_test_{}_{}", std::process::id(), name)).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn replacing_a_damaged_key_keeps_live_cells() {
+        // Value data holding bytes that look like a key node cell
+        let mut payload = vec![0u8; 4];
+        payload.extend_from_slice(&(-88i32).to_le_bytes());
+        payload.extend_from_slice(&hive::build_key_node("Decoy", NO_CELL, NO_CELL, 0));
+        payload.resize(128, 0xCC);
+        let mut keep = SalvagedKey::named("Keep");
+        keep.values.push(SalvagedValue { name: "Data".to_string(), data_type: hive::REG_BINARY, data: payload.clone() });
+        let mut damaged = SalvagedKey::named("Damaged");
+        damaged.subkeys = vec![SalvagedKey::named("A"), SalvagedKey::named("B")];
+        let mut root = SalvagedKey::named("ROOT");
+        root.subkeys = vec![keep, damaged.clone()];
+        let mut target = rebuild::build_test_hive(&root);
+
+        // Point one subkey entry of the key being replaced into the live value data
+        let keep = target.find_key("Keep").unwrap();
+        let value = target.value(target.value_offsets(&keep).unwrap()[0]).unwrap();
+        let damaged_node = target.find_key("Damaged").unwrap();
+        target.set_cell_u32(damaged_node.subkey_list, 4, value.data_offset + 8);
+
+        damaged.subkeys.push(SalvagedKey::named("C"));
+        let mut donor_root = SalvagedKey::named("ROOT");
+        donor_root.subkeys = vec![damaged];
+        let donor = rebuild::build_test_hive(&donor_root);
+
+        let (target_path, donor_path, output_path) = (temp_path("target"), temp_path("donor"), temp_path("output"));
+        fs::write(&target_path, target.data()).unwrap();
+        fs::write(&donor_path, donor.data()).unwrap();
+        let report = graft(&target_path, &donor_path, "Damaged", Some(&output_path), false);
+        let grafted = Hive::open(&output_path);
+        for path in [&target_path, &donor_path, &output_path] {
+            let _ = fs::remove_file(path);
+        }
+
+        let report = report.unwrap();
+        assert!(report.existing);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        let grafted = grafted.unwrap();
+        assert_eq!(grafted.value_data(&value).unwrap(), payload);
+        assert_eq!(grafted.subkeys(&grafted.find_key("Damaged").unwrap()).unwrap().len(), 3);
+        // Only the key the damaged entry had displaced is left over, as an orphan
+        let validation = report.validation.unwrap();
+        assert!(validation.issues.iter().all(|i| i.code == IssueCode::OrphanedKey), "{:?}", validation.issues);
+        assert_eq!(validation.issues.len(), 1);
+    }
+}
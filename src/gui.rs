@@ -1,10 +1,23 @@
 use eframe::egui;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
-use std::fs::File;
-use memmap::MmapOptions;
 use crate::types::*;
 use crate::registry;
+use crate::audit::{self, AuditLog, FileHashes};
+use crate::browse::{HiveBrowser, KeyEntry, KeyHealth};
+use crate::compare::{self, Comparison};
+use crate::diff::{self, Change, HiveDiff, KeyDiff};
+use crate::guide;
+use crate::hive;
+use crate::edit;
+use crate::header::{self, FieldChange, HeaderField};
+use crate::inspect::{self, Inspection};
+use crate::pagemap::{self, PageMap, PageStatus};
+use crate::preview::{self, ByteChange};
+use crate::progress::{self, Monitor, Progress};
+use crate::recent;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 
 const SPACING: f32 = 10.0;
 const INNER_SPACING: f32 = 5.0;
@@ -13,6 +26,22 @@ const CLOSE_BUTTON_SIZE: f32 = 32.0;
 const HEADER_HEIGHT: f32 = 48.0;
 const HEADER_WITH_FILE_HEIGHT: f32 = 100.0;
 
+const KEY_TREE_HEIGHT: f32 = 400.0;
+const HEX_VIEW_HEIGHT: f32 = 320.0;
+const HEX_BYTES_PER_ROW: usize = 16;
+
+const PAGE_MAP_HEIGHT: f32 = 320.0;
+const PAGE_MAP_CELL: f32 = 10.0;
+const PAGE_MAP_GAP: f32 = 2.0;
+const PAGE_MAP_TOOLTIP_ISSUES: usize = 5;
+
+const FIX_PREVIEW_HEIGHT: f32 = 260.0;
+const FIX_PREVIEW_MAX_RANGES: usize = 8;
+const FIX_PREVIEW_BYTES: usize = 16;
+
+const PROGRESS_BAR_WIDTH: f32 = 420.0;
+const WIZARD_LIST_HEIGHT: f32 = 280.0;
+
 const CONTENT_PADDING: f32 = 20.0;  // Added padding constant
 const WINDOW_ROUNDING: f32 = 15.0;  // Added window rounding constant
 
@@ -21,12 +50,275 @@ const LOGO_BYTES: &[u8] = include_bytes!("../assets/logo.png");
 
 #[derive(Default)]
 struct UiState {
+    /// Open hives, each analysed and repaired on its own
+    tabs: Vec<HiveTab>,
+    active_tab: usize,
+    next_tab_id: TabId,
+    /// Most recently opened first
+    recent_files: Vec<PathBuf>,
+    /// Status of work not tied to a tab, such as comparisons and diffs
+    status_message: String,
+    read_only: bool,
+    audit_log: Option<AuditLog>,
+    comparison: Option<Arc<Comparison>>,
+    diff: Option<Arc<HiveDiff>>,
+}
+
+impl UiState {
+    fn tab(&self) -> Option<&HiveTab> {
+        self.tabs.get(self.active_tab)
+    }
+
+    fn tab_mut(&mut self) -> Option<&mut HiveTab> {
+        self.tabs.get_mut(self.active_tab)
+    }
+
+    /// The tab a background result belongs to, if it is still open
+    fn tab_by_id(&mut self, id: TabId) -> Option<&mut HiveTab> {
+        self.tabs.iter_mut().find(|tab| tab.id == id)
+    }
+
+    fn analysis(&self) -> Option<Arc<AnalysisResult>> {
+        self.tab().and_then(|tab| tab.analysis_result.clone())
+    }
+}
+
+/// A hive open in a tab, with its own analysis, fix selections and views
+#[derive(Default)]
+struct HiveTab {
+    id: TabId,
+    path: PathBuf,
+    status_message: String,
     show_fix_dialog: bool,
     fix_selections: Vec<bool>,
-    status_message: String,
-    selected_file: Option<std::path::PathBuf>,
     analysis_result: Option<Arc<AnalysisResult>>,
-    selected_fixes: Vec<FixType>,
+    /// Issues, as indices into the analysis, awaiting confirmation
+    fix_dialog_issues: Vec<usize>,
+    fix_previews: HashMap<usize, Result<Vec<ByteChange>, String>>,
+    preview_worker: Option<PreviewWorker>,
+    browser: Option<Arc<Mutex<HiveBrowser>>>,
+    selected_key: Option<KeyEntry>,
+    hex_view: Option<HexView>,
+    header_editor: Option<HeaderEditor>,
+    show_page_map: bool,
+    page_map: Option<Arc<PageMap>>,
+    /// The analysis or repair running in the background
+    running: Option<Monitor>,
+    progress: Option<Progress>,
+    wizard: Option<Wizard>,
+}
+
+impl HiveTab {
+    fn new(id: TabId, path: PathBuf) -> Self {
+        Self { id, path, ..Default::default() }
+    }
+
+    fn title(&self) -> String {
+        self.path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.to_string_lossy().to_string())
+    }
+
+    /// Cancels whatever is still running and hands out a monitor for a new
+    /// operation that reports through `tx`
+    fn start_operation(&mut self, tx: &Sender<Message>) -> Monitor {
+        if let Some(previous) = self.running.take() {
+            previous.cancel();
+        }
+        let (tx, id) = (tx.clone(), self.id);
+        let monitor = Monitor::new(move |progress| {
+            let _ = tx.send(Message::Progress(id, progress));
+        });
+        self.running = Some(monitor.clone());
+        self.progress = None;
+        monitor
+    }
+
+    fn finish_operation(&mut self) {
+        self.running = None;
+        self.progress = None;
+    }
+
+    /// Hides the fix dialog and stops working out its previews
+    fn close_fix_dialog(&mut self) {
+        self.show_fix_dialog = false;
+        self.fix_dialog_issues.clear();
+        self.fix_previews.clear();
+        if let Some(worker) = self.preview_worker.take() {
+            worker.monitor.cancel();
+        }
+    }
+}
+
+/// Works out the byte changes of the fixes in the confirm dialog, one issue
+/// at a time as their previews are opened. The hive is read once, on the
+/// first request; closing the dialog cancels the worker.
+struct PreviewWorker {
+    requests: Sender<usize>,
+    monitor: Monitor,
+    requested: HashSet<usize>,
+}
+
+impl PreviewWorker {
+    fn spawn(id: TabId, analysis: Arc<AnalysisResult>, tx: &Sender<Message>) -> Self {
+        let (requests, pending) = channel::<usize>();
+        let monitor = Monitor::default();
+        let (tx, cancelled) = (tx.clone(), monitor.clone());
+        std::thread::spawn(move || {
+            let mut hive = None;
+            while let Ok(issue) = pending.recv() {
+                let hive = hive.get_or_insert_with(|| {
+                    hive::Hive::open(&analysis.file_info.path).map_err(|e| e.to_string())
+                });
+                let changes = hive.as_ref().map_err(|e| e.clone()).and_then(|hive| {
+                    preview::preview_fix(hive, &analysis, issue, &cancelled).map_err(|e| e.to_string())
+                });
+                if cancelled.is_cancelled() || tx.send(Message::FixPreviewReady(id, issue, changes)).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { requests, monitor, requested: HashSet::new() }
+    }
+
+    fn request(&mut self, issue: usize) {
+        if self.requested.insert(issue) {
+            let _ = self.requests.send(issue);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WizardStep {
+    Backup,
+    Analyze,
+    ChooseFixes,
+    Apply,
+    Validate,
+    NextSteps,
+}
+
+impl WizardStep {
+    const ALL: [WizardStep; 6] = [
+        WizardStep::Backup,
+        WizardStep::Analyze,
+        WizardStep::ChooseFixes,
+        WizardStep::Apply,
+        WizardStep::Validate,
+        WizardStep::NextSteps,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            WizardStep::Backup => "Backup",
+            WizardStep::Analyze => "Analyze",
+            WizardStep::ChooseFixes => "Choose Fixes",
+            WizardStep::Apply => "Apply",
+            WizardStep::Validate => "Validate",
+            WizardStep::NextSteps => "Next Steps",
+        }
+    }
+}
+
+/// Progress through the guided repair of one tab's hive
+#[derive(Clone)]
+struct Wizard {
+    step: WizardStep,
+    /// Where fixes are written; None patches the file in place after a backup
+    output_path: Option<PathBuf>,
+    /// Verified backup made in the first step
+    backup: Option<String>,
+    /// Nothing is written, as in read-only mode
+    analyze_only: bool,
+    /// Analysis the fixes are chosen from
+    analysis: Option<Arc<AnalysisResult>>,
+    /// One entry per issue of `analysis`
+    selections: Vec<bool>,
+    /// Status reported by the repair
+    outcome: Option<String>,
+    /// Analysis of the file the fixes were written to
+    repaired: Option<Arc<AnalysisResult>>,
+}
+
+impl Wizard {
+    fn new(analyze_only: bool) -> Self {
+        Self {
+            step: WizardStep::Backup,
+            output_path: None,
+            backup: None,
+            analyze_only,
+            analysis: None,
+            selections: Vec::new(),
+            outcome: None,
+            repaired: None,
+        }
+    }
+
+    fn selected(&self) -> Vec<usize> {
+        self.selections.iter().enumerate().filter(|(_, &on)| on).map(|(i, _)| i).collect()
+    }
+}
+
+/// Raw bytes of the selected file with the structures at the cursor decoded
+struct HexView {
+    data: Arc<Vec<u8>>,
+    cursor: usize,
+    inspection: Arc<Inspection>,
+    goto: String,
+    /// Set when the cursor jumps, so the next frame scrolls to it
+    scroll_to_cursor: bool,
+}
+
+impl HexView {
+    fn open(path: &str, cursor: usize) -> std::io::Result<Self> {
+        let data = Arc::new(std::fs::read(path)?);
+        let cursor = cursor.min(data.len().saturating_sub(1));
+        let inspection = Arc::new(inspect::inspect(&data, cursor));
+        Ok(Self { data, cursor, inspection, goto: format!("0x{:X}", cursor), scroll_to_cursor: true })
+    }
+
+    fn move_to(&mut self, cursor: usize, scroll: bool) {
+        self.cursor = cursor.min(self.data.len().saturating_sub(1));
+        self.inspection = Arc::new(inspect::inspect(&self.data, self.cursor));
+        self.goto = format!("0x{:X}", self.cursor);
+        self.scroll_to_cursor = scroll;
+    }
+}
+
+/// Base block fields as typed, checked against the file on disk
+struct HeaderEditor {
+    data: Arc<Vec<u8>>,
+    /// One entry per `HeaderField::ALL`
+    inputs: Vec<String>,
+}
+
+impl HeaderEditor {
+    fn open(path: &str) -> std::io::Result<Self> {
+        let data = std::fs::read(path)?;
+        if data.len() < header::HEADER_LEN {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "the file is too small to hold a base block"));
+        }
+        let inputs = HeaderField::ALL.iter().map(|f| f.format(f.bytes(&data))).collect();
+        Ok(Self { data: Arc::new(data), inputs })
+    }
+
+    /// Fields whose typed value parses and differs from the file, and the
+    /// typing errors of the rest
+    fn changes(&self) -> (Vec<FieldChange>, Vec<Option<String>>) {
+        let mut changes = Vec::new();
+        let errors = HeaderField::ALL.iter().zip(&self.inputs)
+            .map(|(field, input)| match field.parse(input) {
+                Ok(bytes) => {
+                    if bytes != field.bytes(&self.data) {
+                        changes.push((*field, bytes));
+                    }
+                    None
+                }
+                Err(e) => Some(e.to_string()),
+            })
+            .collect();
+        (changes, errors)
+    }
 }
 
 pub struct RegistryFixerApp {
@@ -39,12 +331,45 @@ pub struct RegistryFixerApp {
 // New message type for UI updates
 enum UiUpdate {
     ToggleFixSelection(usize),
-    ShowFixDialog(Vec<FixType>),
+    /// Asks for confirmation before fixing these issues
+    ShowFixDialog(Vec<usize>),
     ClearFixDialog,
+    /// Works out the byte changes of one issue's fix in the confirm dialog
+    RequestFixPreview(usize),
+    SetReadOnly(bool),
+    ClearComparison,
+    ClearDiff,
+    SelectKey(KeyEntry),
+    /// Opens the hex view, or jumps within it, and scrolls to the offset
+    ShowBytes(usize),
+    /// Moves the hex cursor without scrolling
+    MoveHexCursor(usize),
+    SetHexGoto(String),
+    CloseHexView,
+    OpenHeaderEditor,
+    /// New text for the field at this index of `HeaderField::ALL`
+    SetHeaderInput(usize, String),
+    CloseHeaderEditor,
+    OpenPageMap,
+    ClosePageMap,
+    CancelOperation,
+    SelectTab(usize),
+    /// Closes the tab, cancelling anything it is running
+    CloseTab(usize),
+    OpenWizard,
+    CloseWizard,
+    /// Starts the wizard's backup before repairing in place
+    WizardBackUp,
+    /// Sends the wizard's fixes to a copy instead of backing up
+    WizardRepairCopy(PathBuf),
+    ToggleWizardSelection(usize),
+    /// Starts applying the fixes chosen in the wizard
+    WizardApply,
+    SetWizardStep(WizardStep),
 }
 
 impl RegistryFixerApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, audit_log: Option<AuditLog>) -> Self {
         // Set up dark theme
         let mut style = (*cc.egui_ctx.style()).clone();
         style.visuals = egui::Visuals::dark();
@@ -85,163 +410,611 @@ impl RegistryFixerApp {
         };
         
         let (tx, rx) = channel();
+        let ui_state = UiState {
+            read_only: registry::is_read_only(),
+            audit_log,
+            recent_files: recent::load(),
+            ..Default::default()
+        };
         
         Self {
             tx,
             rx,
-            ui_state: Arc::new(Mutex::new(UiState::default())),
+            ui_state: Arc::new(Mutex::new(ui_state)),
             logo,
         }
     }
 
     fn update_ui_state(&self, update: UiUpdate) {
         let mut state = self.ui_state.lock().unwrap();
+        match update {
+            UiUpdate::SetReadOnly(enabled) => {
+                registry::set_read_only(enabled);
+                state.read_only = enabled;
+                if enabled {
+                    for tab in &mut state.tabs {
+                        tab.close_fix_dialog();
+                    }
+                    if state.audit_log.is_none() {
+                        state.audit_log = Some(AuditLog::new(audit::DEFAULT_AUDIT_LOG));
+                    }
+                }
+                return;
+            }
+            UiUpdate::ClearComparison => {
+                state.comparison = None;
+                return;
+            }
+            UiUpdate::ClearDiff => {
+                state.diff = None;
+                return;
+            }
+            UiUpdate::SelectTab(index) => {
+                if index < state.tabs.len() {
+                    state.active_tab = index;
+                }
+                return;
+            }
+            UiUpdate::CloseTab(index) => {
+                if index < state.tabs.len() {
+                    let mut tab = state.tabs.remove(index);
+                    tab.close_fix_dialog();
+                    if let Some(monitor) = tab.running {
+                        monitor.cancel();
+                    }
+                    if state.active_tab > index || state.active_tab == state.tabs.len() {
+                        state.active_tab = state.active_tab.saturating_sub(1);
+                    }
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        // Everything else acts on the tab being shown
+        let Some(tab) = state.tab_mut() else {
+            return;
+        };
         match update {
             UiUpdate::ToggleFixSelection(index) => {
-                if let Some(selection) = state.fix_selections.get_mut(index) {
+                if let Some(selection) = tab.fix_selections.get_mut(index) {
+                    *selection = !*selection;
+                }
+            }
+            UiUpdate::ShowFixDialog(issues) => {
+                tab.close_fix_dialog();
+                tab.show_fix_dialog = true;
+                if let Some(analysis) = tab.analysis_result.clone() {
+                    tab.preview_worker = Some(PreviewWorker::spawn(tab.id, analysis, &self.tx));
+                }
+                tab.fix_dialog_issues = issues;
+            }
+            UiUpdate::ClearFixDialog => tab.close_fix_dialog(),
+            UiUpdate::RequestFixPreview(issue) => {
+                if let Some(worker) = &mut tab.preview_worker {
+                    worker.request(issue);
+                }
+            }
+            UiUpdate::SelectKey(key) => {
+                tab.selected_key = Some(key);
+            }
+            UiUpdate::ShowBytes(offset) => {
+                if let Some(view) = &mut tab.hex_view {
+                    view.move_to(offset, true);
+                } else if let Some(path) = tab.analysis_result.as_ref().map(|r| r.file_info.path.clone()) {
+                    match HexView::open(&path, offset) {
+                        Ok(view) => tab.hex_view = Some(view),
+                        Err(e) => tab.status_message = format!("Cannot read {}: {}", path, e),
+                    }
+                }
+            }
+            UiUpdate::MoveHexCursor(offset) => {
+                if let Some(view) = &mut tab.hex_view {
+                    view.move_to(offset, false);
+                }
+            }
+            UiUpdate::SetHexGoto(text) => {
+                if let Some(view) = &mut tab.hex_view {
+                    view.goto = text;
+                }
+            }
+            UiUpdate::CloseHexView => {
+                tab.hex_view = None;
+            }
+            UiUpdate::OpenHeaderEditor => {
+                if let Some(path) = tab.analysis_result.as_ref().map(|r| r.file_info.path.clone()) {
+                    match HeaderEditor::open(&path) {
+                        Ok(editor) => tab.header_editor = Some(editor),
+                        Err(e) => tab.status_message = format!("Cannot read {}: {}", path, e),
+                    }
+                }
+            }
+            UiUpdate::SetHeaderInput(index, text) => {
+                if let Some(input) = tab.header_editor.as_mut().and_then(|e| e.inputs.get_mut(index)) {
+                    *input = text;
+                }
+            }
+            UiUpdate::CloseHeaderEditor => {
+                tab.header_editor = None;
+            }
+            UiUpdate::OpenPageMap => {
+                tab.show_page_map = true;
+                tab.page_map = None;
+                if let Some(analysis) = tab.analysis_result.clone() {
+                    self.spawn_page_map(tab.id, analysis);
+                }
+            }
+            UiUpdate::ClosePageMap => {
+                tab.show_page_map = false;
+                tab.page_map = None;
+            }
+            UiUpdate::CancelOperation => {
+                if let Some(monitor) = &tab.running {
+                    monitor.cancel();
+                    tab.status_message = "Cancelling...".to_string();
+                }
+            }
+            UiUpdate::OpenWizard => {
+                tab.wizard = Some(Wizard::new(registry::is_read_only()));
+            }
+            UiUpdate::CloseWizard => {
+                tab.wizard = None;
+            }
+            UiUpdate::WizardBackUp => {
+                let path = tab.path.to_string_lossy().to_string();
+                let (tx, id) = (self.tx.clone(), tab.id);
+                tab.status_message = "Backing up...".to_string();
+                std::thread::spawn(move || {
+                    let backup = registry::backup_file(&path)
+                        .and_then(|backup| {
+                            if audit::hash_file(&path)? != audit::hash_file(&backup)? {
+                                anyhow::bail!("The backup {} does not match the original", backup);
+                            }
+                            Ok(backup)
+                        })
+                        .map_err(|e| e.to_string());
+                    tx.send(Message::BackupComplete(id, backup)).unwrap();
+                });
+            }
+            UiUpdate::WizardRepairCopy(output_path) => {
+                if let Some(wizard) = &mut tab.wizard {
+                    wizard.output_path = Some(output_path);
+                }
+            }
+            UiUpdate::ToggleWizardSelection(index) => {
+                if let Some(selection) = tab.wizard.as_mut().and_then(|w| w.selections.get_mut(index)) {
                     *selection = !*selection;
                 }
             }
-            UiUpdate::ShowFixDialog(fixes) => {
-                state.show_fix_dialog = true;
-                state.selected_fixes = fixes;
+            UiUpdate::WizardApply => {
+                if let Some(wizard) = &mut tab.wizard {
+                    wizard.step = WizardStep::Apply;
+                    wizard.outcome = None;
+                    wizard.repaired = None;
+                    self.tx.send(Message::FixSelected(tab.id, wizard.selected(), wizard.output_path.clone())).unwrap();
+                }
             }
-            UiUpdate::ClearFixDialog => {
-                state.show_fix_dialog = false;
-                state.selected_fixes.clear();
+            UiUpdate::SetWizardStep(step) => {
+                if let Some(wizard) = &mut tab.wizard {
+                    wizard.step = step;
+                }
             }
+            UiUpdate::SetReadOnly(_)
+            | UiUpdate::ClearComparison
+            | UiUpdate::ClearDiff
+            | UiUpdate::SelectTab(_)
+            | UiUpdate::CloseTab(_) => {}
         }
     }
 
+    fn spawn_analysis(&self, tab_id: TabId, path_str: String, report_errors: bool) {
+        let tx = self.tx.clone();
+        let (audit_log, monitor) = {
+            let mut state = self.ui_state.lock().unwrap();
+            let audit_log = state.audit_log.clone();
+            let Some(tab) = state.tab_by_id(tab_id) else {
+                return;
+            };
+            (audit_log, tab.start_operation(&self.tx))
+        };
+        std::thread::spawn(move || {
+            let message = match audit::analyze_with(&path_str, audit_log.as_ref(), &monitor) {
+                // A cancel that arrives as the analysis finishes still wins
+                Ok((result, _)) if !monitor.is_cancelled() => Message::AnalysisComplete(tab_id, result),
+                Ok(_) => Message::OperationStopped(tab_id, monitor, Some("Analysis cancelled.".to_string())),
+                Err(e) if progress::is_cancelled(&e) => {
+                    Message::OperationStopped(tab_id, monitor, Some("Analysis cancelled.".to_string()))
+                }
+                Err(e) if report_errors => Message::FixComplete(tab_id, format!("Analysis failed: {}", e)),
+                Err(_) => Message::OperationStopped(tab_id, monitor, None),
+            };
+            tx.send(message).unwrap();
+        });
+    }
+
+    /// Moves the wizard to its analysis step and analyzes the hive afresh
+    fn start_wizard_analysis(&self, tab_id: TabId) {
+        let path = {
+            let mut state = self.ui_state.lock().unwrap();
+            let Some(tab) = state.tab_by_id(tab_id) else {
+                return;
+            };
+            let Some(wizard) = &mut tab.wizard else {
+                return;
+            };
+            wizard.step = WizardStep::Analyze;
+            wizard.analysis = None;
+            wizard.outcome = None;
+            tab.path.to_string_lossy().to_string()
+        };
+        self.spawn_analysis(tab_id, path, true);
+    }
+
+    fn spawn_page_map(&self, tab_id: TabId, analysis: Arc<AnalysisResult>) {
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            let map = hive::Hive::open(&analysis.file_info.path)
+                .map(|hive| pagemap::build(&hive, &analysis.issues))
+                .map_err(|e| e.to_string());
+            tx.send(Message::PageMapReady(tab_id, map)).unwrap();
+        });
+    }
+
     fn process_messages(&self) {
         while let Ok(message) = self.rx.try_recv() {
             match message {
                 Message::FileSelected(path) => {
                     let mut state = self.ui_state.lock().unwrap();
-                    state.selected_file = Some(path.clone());
-                    state.status_message = "File selected. Analyzing...".to_string();
+                    recent::add(&mut state.recent_files, &path);
+                    // Forgetting the list is not worth interrupting the user over
+                    let _ = recent::save(&state.recent_files);
+                    let index = match state.tabs.iter().position(|tab| tab.path == path) {
+                        Some(index) => index,
+                        None => {
+                            let id = state.next_tab_id;
+                            state.next_tab_id += 1;
+                            state.tabs.push(HiveTab::new(id, path.clone()));
+                            state.tabs.len() - 1
+                        }
+                    };
+                    state.active_tab = index;
+                    let tab = &mut state.tabs[index];
+                    tab.status_message = "File selected. Analyzing...".to_string();
+                    let id = tab.id;
                     drop(state);
-                    
-                    let tx = self.tx.clone();
-                    let path_str = path.to_string_lossy().to_string();
-                    std::thread::spawn(move || {
-                        match registry::check_registry_file(&path_str) {
-                            Ok(result) => {
-                                tx.send(Message::AnalysisComplete(result)).unwrap();
-                            }
+
+                    self.spawn_analysis(id, path.to_string_lossy().to_string(), true);
+                }
+                Message::AnalysisComplete(id, result) => {
+                    let len = result.issues.len();
+                    let path = result.file_info.path.clone();
+                    let result = Arc::new(result);
+                    let mut state = self.ui_state.lock().unwrap();
+                    let Some(tab) = state.tab_by_id(id) else {
+                        continue;
+                    };
+                    tab.finish_operation();
+                    if tab.show_page_map {
+                        self.spawn_page_map(id, result.clone());
+                    }
+                    if let Some(wizard) = tab.wizard.as_mut().filter(|w| w.step == WizardStep::Analyze) {
+                        // The riskiest fixes are left for the user to opt into
+                        wizard.selections = result.issues.iter()
+                            .map(|i| i.fix_type.as_ref().is_some_and(|f| f.risk() < FixRisk::High))
+                            .collect();
+                        wizard.analysis = Some(result.clone());
+                        wizard.outcome = None;
+                        wizard.step = if wizard.analyze_only { WizardStep::NextSteps } else { WizardStep::ChooseFixes };
+                    }
+                    tab.analysis_result = Some(result);
+                    tab.status_message = "Analysis complete.".to_string();
+                    tab.fix_selections = vec![false; len];
+                    // Issue indices refer to the previous analysis
+                    tab.close_fix_dialog();
+                    // Show the bytes as they are now, after any fix
+                    if let Some(view) = &mut tab.hex_view {
+                        match HexView::open(&path, view.cursor) {
+                            Ok(reloaded) => *view = reloaded,
+                            Err(e) => tab.status_message = format!("Cannot read {}: {}", path, e),
+                        }
+                    }
+                    if tab.header_editor.is_some() {
+                        match HeaderEditor::open(&path) {
+                            Ok(reloaded) => tab.header_editor = Some(reloaded),
                             Err(e) => {
-                                tx.send(Message::FixComplete(format!("Analysis failed: {}", e))).unwrap();
+                                tab.header_editor = None;
+                                tab.status_message = format!("Cannot read {}: {}", path, e);
                             }
                         }
+                    }
+                    drop(state);
+
+                    // Reload the key tree too, since a fix may have changed it
+                    let tx = self.tx.clone();
+                    std::thread::spawn(move || {
+                        let browser = HiveBrowser::open(&path).map_err(|e| e.to_string());
+                        tx.send(Message::BrowserReady(id, browser)).unwrap();
                     });
                 }
-                Message::AnalysisComplete(result) => {
-                    let len = result.issues.len();
-                    let result = Arc::new(result);
-                    let mut state = self.ui_state.lock().unwrap();
-                    state.analysis_result = Some(result);
-                    state.status_message = "Analysis complete.".to_string();
-                    state.fix_selections = vec![false; len];
-                }
-                Message::FixSelected(fixes) => {
-                    let analysis = {
-                        let state = self.ui_state.lock().unwrap();
-                        state.analysis_result.clone()
+                Message::FixSelected(id, issues, output_path) => {
+                    let (analysis, audit_log, monitor) = {
+                        let mut state = self.ui_state.lock().unwrap();
+                        let audit_log = state.audit_log.clone();
+                        let Some(tab) = state.tab_by_id(id) else {
+                            continue;
+                        };
+                        tab.status_message = "Applying fixes...".to_string();
+                        (tab.analysis_result.clone(), audit_log, tab.start_operation(&self.tx))
                     };
                     
                     if let Some(analysis) = analysis {
+                        let analysis = registry::select_issues(&analysis, &issues);
+                        let fixes = registry::fix_types(&analysis);
                         let file_path = analysis.file_info.path.clone();
                         let tx = self.tx.clone();
                         
+                        let output_path = output_path.map(|p| p.to_string_lossy().to_string());
+                        
                         std::thread::spawn(move || {
-                            match registry::backup_file(&file_path) {
-                                Ok(_backup_path) => {
-                                    let mut needs_checksum_update = false;
-                                    let mut error_occurred = false;
-                                    
-                                    for fix_type in fixes {
-                                        if let Some(issue) = analysis.issues.iter()
-                                            .find(|i| i.fix_type.as_ref() == Some(&fix_type))
-                                        {
-                                            match (&fix_type, &issue.fix_data) {
-                                                (FixType::HiveBinsSize, Some(FixData::HiveBinsSize(new_size))) => {
-                                                    if let Err(e) = registry::update_hive_bins_size(&file_path, *new_size) {
-                                                        tx.send(Message::FixComplete(format!("Failed to update hive bins size: {}", e))).unwrap();
-                                                        error_occurred = true;
-                                                        break;
-                                                    }
-                                                    needs_checksum_update = true;
-                                                }
-                                                (FixType::Checksum, Some(FixData::Checksum(new_checksum))) => {
-                                                    if let Err(e) = registry::update_checksum(&file_path, *new_checksum) {
-                                                        tx.send(Message::FixComplete(format!("Failed to update checksum: {}", e))).unwrap();
-                                                        error_occurred = true;
-                                                        break;
-                                                    }
-                                                }
-                                                (FixType::SequenceNumbers, Some(FixData::SequenceNumbers(primary, secondary))) => {
-                                                    if let Err(e) = registry::update_sequence_numbers(&file_path, *primary, *secondary) {
-                                                        tx.send(Message::FixComplete(format!("Failed to update sequence numbers: {}", e))).unwrap();
-                                                        error_occurred = true;
-                                                        break;
-                                                    }
-                                                    needs_checksum_update = true;
-                                                }
-                                                _ => {}
-                                            }
-                                        }
-                                    }
+                            let target = output_path.clone().unwrap_or_else(|| file_path.clone());
+                            let before = audit_log.as_ref().map(|_| audit::hash_file(&file_path));
+                            let result = registry::apply_fixes_with(&file_path, output_path.as_deref(), &analysis, &fixes, &monitor);
+                            let mut outcome = match &result {
+                                Ok(repaired) if output_path.is_some() => format!(
+                                    "Fixes for {} issue(s) applied to repaired copy {} ({} issue(s) remaining).",
+                                    issues.len(), target, repaired.issues.len()
+                                ),
+                                Ok(_) => format!("Fixes for {} issue(s) applied successfully.", issues.len()),
+                                Err(e) => e.to_string(),
+                            };
 
-                                    if !error_occurred {
-                                        if needs_checksum_update {
-                                            match File::open(&file_path) {
-                                                Ok(file) => {
-                                                    if let Ok(mmap) = unsafe { MmapOptions::new().map(&file) } {
-                                                        let new_checksum = registry::calculate_header_checksum(&mmap);
-                                                        if let Err(e) = registry::update_checksum(&file_path, new_checksum) {
-                                                            tx.send(Message::FixComplete(format!("Failed to update final checksum: {}", e))).unwrap();
-                                                            return;
-                                                        }
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    tx.send(Message::FixComplete(format!("Failed to open file for checksum update: {}", e))).unwrap();
-                                                    return;
-                                                }
-                                            }
-                                        }
-                                        tx.send(Message::FixComplete("All fixes applied successfully.".to_string())).unwrap();
-                                    }
-                                }
-                                Err(e) => {
-                                    tx.send(Message::FixComplete(format!("Failed to create backup: {}", e))).unwrap();
+                            if let (Some(log), Some(before)) = (&audit_log, before) {
+                                let hashes = before.map(|before| FileHashes {
+                                    before,
+                                    after: audit::hash_file(&target).ok(),
+                                });
+                                let logged = hashes.and_then(|hashes| {
+                                    log.record_fix(&file_path, output_path.as_deref(), &fixes, &hashes, &outcome)
+                                });
+                                if let Err(e) = logged {
+                                    outcome = format!("{} (audit log failed: {})", outcome, e);
                                 }
                             }
+                            if let Ok(repaired) = result {
+                                tx.send(Message::RepairValidated(id, repaired)).unwrap();
+                            }
+                            tx.send(Message::FixComplete(id, outcome)).unwrap();
                         });
                     }
                 }
-                Message::FixComplete(msg) => {
-                    let selected_file = {
+                Message::HeaderEditSelected(id, changes, output_path) => {
+                    let (path, audit_log) = {
                         let mut state = self.ui_state.lock().unwrap();
-                        state.status_message = msg;
-                        state.show_fix_dialog = false;
-                        state.selected_file.clone()
+                        let audit_log = state.audit_log.clone();
+                        let Some(tab) = state.tab_by_id(id) else {
+                            continue;
+                        };
+                        tab.status_message = "Writing the base block...".to_string();
+                        (tab.analysis_result.as_ref().map(|r| r.file_info.path.clone()), audit_log)
                     };
-                    
-                    if let Some(path) = selected_file {
-                        let tx = self.tx.clone();
-                        let path_str = path.to_string_lossy().to_string();
-                        std::thread::spawn(move || {
-                            if let Ok(result) = registry::check_registry_file(&path_str) {
-                                tx.send(Message::AnalysisComplete(result)).unwrap();
+                    let Some(file_path) = path else {
+                        continue;
+                    };
+                    let tx = self.tx.clone();
+                    let output_path = output_path.map(|p| p.to_string_lossy().to_string());
+                    std::thread::spawn(move || {
+                        let target = output_path.clone().unwrap_or_else(|| file_path.clone());
+                        let before = audit_log.as_ref().map(|_| audit::hash_file(&file_path));
+                        let names: Vec<&str> = changes.iter().map(|(field, _)| field.name()).collect();
+                        let outcome = match header::set_fields(&file_path, output_path.as_deref(), &changes) {
+                            Ok(result) => format!(
+                                "Set {} in {} ({} issue(s) remaining).",
+                                names.join(", "), target, result.issues.len()
+                            ),
+                            Err(e) => e.to_string(),
+                        };
+
+                        if let (Some(log), Some(before)) = (&audit_log, before) {
+                            let logged = before
+                                .map(|before| FileHashes { before, after: audit::hash_file(&target).ok() })
+                                .and_then(|hashes| log.record_change("set-field", &file_path, output_path.as_deref(), &hashes, &outcome));
+                            if let Err(e) = logged {
+                                tx.send(Message::FixComplete(id, format!("{} (audit log failed: {})", outcome, e))).unwrap();
+                                return;
                             }
-                        });
+                        }
+                        tx.send(Message::FixComplete(id, outcome)).unwrap();
+                    });
+                }
+                Message::FixComplete(id, msg) => {
+                    let path = {
+                        let mut state = self.ui_state.lock().unwrap();
+                        let Some(tab) = state.tab_by_id(id) else {
+                            continue;
+                        };
+                        tab.finish_operation();
+                        if let Some(wizard) = &mut tab.wizard {
+                            match wizard.step {
+                                WizardStep::Apply => {
+                                    wizard.outcome = Some(msg.clone());
+                                    wizard.step = WizardStep::Validate;
+                                }
+                                // The analysis failed
+                                WizardStep::Analyze => wizard.outcome = Some(msg.clone()),
+                                _ => {}
+                            }
+                        }
+                        tab.status_message = msg;
+                        tab.close_fix_dialog();
+                        tab.path.clone()
+                    };
+                    
+                    self.spawn_analysis(id, path.to_string_lossy().to_string(), false);
+                }
+                Message::CompareSelected(paths) => {
+                    let audit_log = {
+                        let mut state = self.ui_state.lock().unwrap();
+                        state.status_message = format!("Comparing {} copies...", paths.len());
+                        state.audit_log.clone()
+                    };
+
+                    let tx = self.tx.clone();
+                    let paths: Vec<String> = paths.iter().map(|p| p.to_string_lossy().to_string()).collect();
+                    std::thread::spawn(move || {
+                        let comparison = compare::compare(&paths, audit_log.as_ref());
+                        tx.send(Message::ComparisonComplete(comparison)).unwrap();
+                    });
+                }
+                Message::ComparisonComplete(comparison) => {
+                    let mut state = self.ui_state.lock().unwrap();
+                    state.status_message = match comparison.recommended {
+                        Some(index) => format!("Comparison complete. Recommended base: {}",
+                            comparison.candidates[index].path),
+                        None => "Comparison complete. None of the copies could be read.".to_string(),
+                    };
+                    state.comparison = Some(Arc::new(comparison));
+                }
+                Message::DiffSelected(before, after) => {
+                    self.ui_state.lock().unwrap().status_message = "Comparing keys and values...".to_string();
+                    let tx = self.tx.clone();
+                    std::thread::spawn(move || {
+                        let result = diff::diff_hives(&before.to_string_lossy(), &after.to_string_lossy())
+                            .map_err(|e| e.to_string());
+                        tx.send(Message::DiffComplete(result)).unwrap();
+                    });
+                }
+                Message::DiffComplete(result) => {
+                    let mut state = self.ui_state.lock().unwrap();
+                    match result {
+                        Ok(diff) => {
+                            let summary = diff.summary();
+                            state.status_message = format!(
+                                "Diff complete: {} key(s) and {} value(s) differ.",
+                                summary.keys.iter().sum::<usize>(), summary.values.iter().sum::<usize>()
+                            );
+                            state.diff = Some(Arc::new(diff));
+                        }
+                        Err(e) => state.status_message = format!("Diff failed: {}", e),
+                    }
+                }
+                Message::FixPreviewReady(id, issue, changes) => {
+                    let mut state = self.ui_state.lock().unwrap();
+                    if let Some(tab) = state.tab_by_id(id) {
+                        if tab.show_fix_dialog && tab.fix_dialog_issues.contains(&issue) {
+                            tab.fix_previews.insert(issue, changes);
+                        }
+                    }
+                }
+                Message::Progress(id, progress) => {
+                    let mut state = self.ui_state.lock().unwrap();
+                    if let Some(tab) = state.tab_by_id(id).filter(|tab| tab.running.is_some()) {
+                        tab.progress = Some(progress);
+                    }
+                }
+                Message::OperationStopped(id, monitor, status) => {
+                    let mut state = self.ui_state.lock().unwrap();
+                    let Some(tab) = state.tab_by_id(id) else {
+                        continue;
+                    };
+                    // Ignore operations a newer one has already replaced
+                    if tab.running.as_ref().is_some_and(|running| running.same(&monitor)) {
+                        tab.finish_operation();
+                        if let Some(status) = status {
+                            tab.status_message = status;
+                        }
+                    }
+                }
+                Message::PageMapReady(id, result) => {
+                    let mut state = self.ui_state.lock().unwrap();
+                    let Some(tab) = state.tab_by_id(id) else {
+                        continue;
+                    };
+                    match result {
+                        Ok(map) if tab.show_page_map => tab.page_map = Some(Arc::new(map)),
+                        Ok(_) => {}
+                        Err(e) => {
+                            tab.show_page_map = false;
+                            tab.status_message = format!("Cannot map the hive bins: {}", e);
+                        }
+                    }
+                }
+                Message::RepairValidated(id, repaired) => {
+                    let mut state = self.ui_state.lock().unwrap();
+                    let wizard = state.tab_by_id(id).and_then(|tab| tab.wizard.as_mut());
+                    if let Some(wizard) = wizard.filter(|w| w.step == WizardStep::Apply) {
+                        wizard.repaired = Some(Arc::new(repaired));
+                    }
+                }
+                Message::BackupComplete(id, result) => {
+                    let mut state = self.ui_state.lock().unwrap();
+                    let Some(tab) = state.tab_by_id(id) else {
+                        continue;
+                    };
+                    let Some(wizard) = &mut tab.wizard else {
+                        continue;
+                    };
+                    match result {
+                        Ok(backup) => {
+                            tab.status_message = format!("Backup verified: {}", backup);
+                            wizard.backup = Some(backup);
+                            wizard.outcome = None;
+                            drop(state);
+                            self.start_wizard_analysis(id);
+                        }
+                        Err(e) => {
+                            wizard.outcome = Some(format!("Backup failed: {}", e));
+                            tab.status_message = format!("Backup failed: {}", e);
+                        }
+                    }
+                }
+                Message::BrowserReady(id, result) => {
+                    let mut state = self.ui_state.lock().unwrap();
+                    let Some(tab) = state.tab_by_id(id) else {
+                        continue;
+                    };
+                    match result {
+                        Ok(browser) => {
+                            // Keep the selection across a reload if the key still exists
+                            tab.selected_key = tab.selected_key.take()
+                                .and_then(|key| browser.find(&key.path));
+                            tab.browser = Some(Arc::new(Mutex::new(browser)));
+                        }
+                        Err(e) => {
+                            tab.browser = None;
+                            tab.selected_key = None;
+                            tab.status_message = format!("Cannot browse the key tree: {}", e);
+                        }
                     }
                 }
             }
         }
     }
 
+    /// Opens hives dropped on the window in tabs, and darkens the window
+    /// while files are dragged over it
+    fn open_dropped_files(&self, ctx: &egui::Context) {
+        let (hovering, dropped) = ctx.input(|i| {
+            let dropped: Vec<PathBuf> = i.raw.dropped_files.iter().filter_map(|f| f.path.clone()).collect();
+            (!i.raw.hovered_files.is_empty(), dropped)
+        });
+        for path in dropped.into_iter().filter(|p| p.is_file()) {
+            self.tx.send(Message::FileSelected(path)).unwrap();
+        }
+
+        if hovering {
+            let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop_overlay")));
+            let rect = ctx.screen_rect();
+            painter.rect_filled(rect, WINDOW_ROUNDING, egui::Color32::from_black_alpha(192));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop hives to open them",
+                egui::FontId::proportional(24.0),
+                egui::Color32::WHITE,
+            );
+        }
+    }
+
     fn render_file_info(ui: &mut egui::Ui, file_info: &FileInfo) {
         ui.add_space(SPACING);
         egui::Grid::new("file_info_grid")
@@ -291,7 +1064,7 @@ impl RegistryFixerApp {
     fn render_header(&self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
         let (has_file, file_path) = {
             let state = self.ui_state.lock().unwrap();
-            (state.selected_file.is_some(), state.selected_file.clone())
+            (!state.tabs.is_empty() || state.comparison.is_some() || state.diff.is_some(), state.tab().map(|t| t.path.clone()))
         };
 
         if has_file {
@@ -359,13 +1132,12 @@ impl RegistryFixerApp {
                                 .size(16.0))
                                 .clicked() 
                             {
-                                if let Some(path) = rfd::FileDialog::new()
-                                    .set_title("Select Registry File")
-                                    .pick_file() 
-                                {
-                                    self.tx.send(Message::FileSelected(path)).unwrap();
-                                }
+                                self.pick_hives();
                             }
+                            self.render_recent_menu(ui);
+                            self.render_compare_button(ui, 16.0);
+                            self.render_diff_button(ui, 16.0);
+                            self.render_read_only_toggle(ui);
                         });
                     });
                     ui.add_space(SPACING);  // Add spacing at the bottom
@@ -427,22 +1199,318 @@ impl RegistryFixerApp {
                     .size(20.0))
                     .clicked() 
                 {
-                    if let Some(path) = rfd::FileDialog::new()
-                        .set_title("Select Registry File")
-                        .pick_file() 
-                    {
-                        self.tx.send(Message::FileSelected(path)).unwrap();
-                    }
+                    self.pick_hives();
+                }
+                ui.label(egui::RichText::new("or drop hive files onto the window")
+                    .color(ui.style().visuals.widgets.noninteractive.text_color()));
+                self.render_compare_button(ui, 16.0);
+                self.render_diff_button(ui, 16.0);
+
+                ui.add_space(SPACING);
+                self.render_read_only_toggle(ui);
+
+                let recent_files = self.ui_state.lock().unwrap().recent_files.clone();
+                if !recent_files.is_empty() {
+                    ui.add_space(SPACING);
+                    ui.label(egui::RichText::new("Recent hives").strong());
+                    for path in recent_files {
+                        if ui.link(path.to_string_lossy()).clicked() {
+                            self.tx.send(Message::FileSelected(path)).unwrap();
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    /// Opens every hive picked in the file dialog in its own tab
+    fn pick_hives(&self) {
+        if let Some(paths) = rfd::FileDialog::new()
+            .set_title("Select Registry Files")
+            .pick_files()
+        {
+            for path in paths {
+                self.tx.send(Message::FileSelected(path)).unwrap();
+            }
+        }
+    }
+
+    fn render_recent_menu(&self, ui: &mut egui::Ui) {
+        let recent_files = self.ui_state.lock().unwrap().recent_files.clone();
+        if recent_files.is_empty() {
+            return;
+        }
+        ui.menu_button(egui::RichText::new("Recent").size(16.0), |ui| {
+            for path in recent_files {
+                if ui.button(path.to_string_lossy()).clicked() {
+                    self.tx.send(Message::FileSelected(path)).unwrap();
+                    ui.close_menu();
+                }
+            }
+        });
+    }
+
+    fn render_tabs(&self, ui: &mut egui::Ui) {
+        let (tabs, active) = {
+            let state = self.ui_state.lock().unwrap();
+            let tabs: Vec<(String, PathBuf, String)> = state.tabs.iter()
+                .map(|tab| {
+                    let badge = match &tab.analysis_result {
+                        _ if tab.running.is_some() => " …".to_string(),
+                        Some(result) if result.issues.is_empty() => " ✓".to_string(),
+                        Some(result) => format!(" ({})", result.issues.len()),
+                        None => String::new(),
+                    };
+                    (tab.title(), tab.path.clone(), badge)
+                })
+                .collect();
+            (tabs, state.active_tab)
+        };
+        if tabs.is_empty() {
+            return;
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            for (i, (title, path, badge)) in tabs.iter().enumerate() {
+                let label = egui::RichText::new(format!("{}{}", title, badge)).size(14.0);
+                if ui.selectable_label(i == active, label).on_hover_text(path.to_string_lossy()).clicked() {
+                    self.update_ui_state(UiUpdate::SelectTab(i));
+                }
+                if ui.small_button("✕").on_hover_text("Close this hive").clicked() {
+                    self.update_ui_state(UiUpdate::CloseTab(i));
+                }
+                ui.add_space(INNER_SPACING);
+            }
+        });
+        ui.separator();
+    }
+
+    fn render_compare_button(&self, ui: &mut egui::Ui, size: f32) {
+        if ui.button(egui::RichText::new("Compare Copies")
+            .size(size))
+            .on_hover_text("Score several copies of the same hive and recommend the healthiest one")
+            .clicked() 
+        {
+            if let Some(paths) = rfd::FileDialog::new()
+                .set_title("Select copies of the same hive")
+                .pick_files() 
+            {
+                if paths.len() > 1 {
+                    self.tx.send(Message::CompareSelected(paths)).unwrap();
+                } else {
+                    self.ui_state.lock().unwrap().status_message =
+                        "Select at least two copies of the hive to compare.".to_string();
+                }
+            }
+        }
+    }
+
+    fn render_comparison(&self, ui: &mut egui::Ui, comparison: &Comparison) {
+        ui.horizontal(|ui| {
+            ui.heading(egui::RichText::new("Candidate Comparison").size(20.0));
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Close").clicked() {
+                    self.update_ui_state(UiUpdate::ClearComparison);
+                }
+                if let Some(index) = comparison.recommended {
+                    if ui.button("Open Recommended").clicked() {
+                        let path = std::path::PathBuf::from(&comparison.candidates[index].path);
+                        self.tx.send(Message::FileSelected(path)).unwrap();
+                    }
+                }
+            });
+        });
+
+        for warning in &comparison.warnings {
+            ui.label(egui::RichText::new(warning).color(egui::Color32::from_rgb(255, 180, 76)));
+        }
+
+        let recommended_color = egui::Color32::from_rgb(76, 175, 80);
+        ui.add_space(SPACING);
+        egui::ScrollArea::horizontal()
+            .id_source("comparison_scroll")
+            .show(ui, |ui| {
+                egui::Grid::new("comparison_grid")
+                    .striped(true)
+                    .spacing(egui::vec2(SPACING * 2.0, INNER_SPACING))
+                    .show(ui, |ui| {
+                        let label_color = ui.style().visuals.widgets.noninteractive.text_color();
+                        for (label, cells) in compare::comparison_rows(comparison) {
+                            ui.label(egui::RichText::new(format!("{}:", label)).color(label_color));
+                            for (i, cell) in cells.iter().enumerate() {
+                                let mut text = egui::RichText::new(cell);
+                                if comparison.recommended == Some(i) {
+                                    text = text.color(recommended_color);
+                                    if label == "Score" {
+                                        text = egui::RichText::new(format!("{}  ★ Recommended", cell))
+                                            .color(recommended_color)
+                                            .strong();
+                                    }
+                                }
+                                ui.label(text);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        ui.add_space(SPACING);
+    }
+
+    fn render_diff_button(&self, ui: &mut egui::Ui, size: f32) {
+        if ui.button(egui::RichText::new("Diff Hives")
+            .size(size))
+            .on_hover_text("Show the keys and values added, removed or changed between two hives")
+            .clicked()
+        {
+            let before = rfd::FileDialog::new()
+                .set_title("Select the earlier hive (e.g. RegBack)")
+                .pick_file();
+            let after = before.as_ref().and_then(|_| rfd::FileDialog::new()
+                .set_title("Select the later hive (e.g. the repaired copy)")
+                .pick_file());
+            if let (Some(before), Some(after)) = (before, after) {
+                self.tx.send(Message::DiffSelected(before, after)).unwrap();
+            }
+        }
+    }
+
+    fn change_color(change: Option<Change>) -> egui::Color32 {
+        match change {
+            Some(Change::Added) => egui::Color32::from_rgb(76, 175, 80),
+            Some(Change::Removed) => egui::Color32::from_rgb(255, 88, 88),
+            Some(Change::Modified) => egui::Color32::from_rgb(255, 180, 76),
+            None => egui::Color32::GRAY,
+        }
+    }
+
+    fn render_diff_key(ui: &mut egui::Ui, key: &KeyDiff, depth: usize) {
+        let marker = key.change.map(|c| c.marker()).unwrap_or(' ');
+        let name = if key.path.is_empty() { "(root)" } else { &key.name };
+        let title = egui::RichText::new(format!("{} {}", marker, name))
+            .color(Self::change_color(key.change))
+            .monospace();
+        egui::CollapsingHeader::new(title)
+            .id_source(("diff", &key.path))
+            .default_open(depth < 2 || key.change == Some(Change::Modified))
+            .show(ui, |ui| {
+                if key.timestamp_changed() {
+                    let times = key.last_written_before.zip(key.last_written_after);
+                    if let Some((before, after)) = times {
+                        ui.label(egui::RichText::new(format!("Last written {} → {}",
+                            hive::format_filetime(before), hive::format_filetime(after)))
+                            .color(ui.style().visuals.widgets.noninteractive.text_color()));
+                    }
+                }
+                for value in &key.values {
+                    let text = match (&value.before, &value.after) {
+                        (Some(before), Some(after)) => format!("~ {}: {} → {}",
+                            value.display_name(), before.describe(), after.describe()),
+                        (Some(state), None) | (None, Some(state)) => format!("{} {}: {}",
+                            value.change.marker(), value.display_name(), state.describe()),
+                        (None, None) => continue,
+                    };
+                    ui.label(egui::RichText::new(text).color(Self::change_color(Some(value.change))).monospace());
+                }
+                for subkey in &key.subkeys {
+                    Self::render_diff_key(ui, subkey, depth + 1);
+                }
+            });
+    }
+
+    fn render_diff(&self, ui: &mut egui::Ui, diff: &HiveDiff) {
+        ui.horizontal(|ui| {
+            ui.heading(egui::RichText::new("Hive Differences").size(20.0));
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Close").clicked() {
+                    self.update_ui_state(UiUpdate::ClearDiff);
                 }
             });
+        });
+
+        let label_color = ui.style().visuals.widgets.noninteractive.text_color();
+        ui.label(egui::RichText::new(format!("Before: {}", diff.before_path)).color(label_color));
+        ui.label(egui::RichText::new(format!("After: {}", diff.after_path)).color(label_color));
+        let summary = diff.summary();
+        ui.horizontal(|ui| {
+            for (i, (label, change)) in [("added", Change::Added), ("removed", Change::Removed), ("modified", Change::Modified)]
+                .into_iter()
+                .enumerate()
+            {
+                ui.label(egui::RichText::new(format!("{} {} key(s), {} value(s) {}",
+                    change.marker(), summary.keys[i], summary.values[i], label))
+                    .color(Self::change_color(Some(change))));
+            }
+        });
+        for error in &diff.errors {
+            ui.label(egui::RichText::new(error).color(egui::Color32::from_rgb(255, 180, 76)));
+        }
+
+        ui.add_space(SPACING);
+        match &diff.root {
+            Some(root) => Self::render_diff_key(ui, root, 0),
+            None => {
+                ui.label("The hives hold the same keys and values.");
+            }
         }
+        ui.add_space(SPACING);
+    }
+
+    /// Shows a message in the status line of the tab being shown
+    fn set_tab_status(&self, message: String) {
+        if let Some(tab) = self.ui_state.lock().unwrap().tab_mut() {
+            tab.status_message = message;
+        }
+    }
+
+    fn render_read_only_toggle(&self, ui: &mut egui::Ui) {
+        let mut read_only = self.ui_state.lock().unwrap().read_only;
+        if ui.checkbox(&mut read_only, "Read-only (forensic)")
+            .on_hover_text("Never write to the selected file and record every action in the audit log")
+            .changed()
+        {
+            self.update_ui_state(UiUpdate::SetReadOnly(read_only));
+        }
+    }
+
+    fn render_progress(&self, ui: &mut egui::Ui) {
+        let progress = {
+            let state = self.ui_state.lock().unwrap();
+            match state.tab() {
+                Some(tab) if tab.running.is_some() => tab.progress.clone(),
+                _ => return,
+            }
+        };
+
+        ui.horizontal(|ui| {
+            let bar = match progress.as_ref().and_then(|p| p.fraction()) {
+                Some(fraction) => egui::ProgressBar::new(fraction).show_percentage(),
+                // Keys are counted as they are read, so there is no total
+                None => egui::ProgressBar::new(0.0).animate(true),
+            };
+            let text = progress.map(|p| p.to_string()).unwrap_or_else(|| "Starting...".to_string());
+            ui.add(bar.desired_width(PROGRESS_BAR_WIDTH));
+            ui.label(text);
+            if ui.button("Cancel").clicked() {
+                self.update_ui_state(UiUpdate::CancelOperation);
+            }
+        });
+        ui.add_space(INNER_SPACING);
     }
 
     fn render_issues(&self, ui: &mut egui::Ui) {
         // Get the analysis result and fix selections upfront
-        let (analysis_result, fix_selections) = {
+        let (analysis_result, fix_selections, read_only, browser, busy) = {
             let state = self.ui_state.lock().unwrap();
-            (state.analysis_result.clone(), state.fix_selections.clone())
+            let Some(tab) = state.tab() else {
+                return;
+            };
+            (
+                tab.analysis_result.clone(),
+                tab.fix_selections.clone(),
+                state.read_only,
+                tab.browser.clone(),
+                tab.running.is_some(),
+            )
         };
 
         if let Some(result) = analysis_result {
@@ -450,81 +1518,991 @@ impl RegistryFixerApp {
             ui.heading(egui::RichText::new("Issues").size(20.0));
             ui.add_space(INNER_SPACING);
 
-            let fixable_issues: Vec<_> = result.issues.iter()
-                .filter(|i| i.fix_type.is_some())
+            let fixable: Vec<usize> = result.issues.iter().enumerate()
+                .filter(|(_, issue)| issue.fix_type.is_some())
+                .map(|(i, _)| i)
                 .collect();
-            
-            if !fixable_issues.is_empty() {
-                if ui.button(egui::RichText::new("Fix All Issues")
-                    .size(16.0))
-                    .clicked() 
+            let selected: Vec<usize> = fixable.iter().copied()
+                .filter(|&i| fix_selections.get(i).copied().unwrap_or(false))
+                .collect();
+
+            if read_only {
+                ui.label(egui::RichText::new("Read-only mode: fixes are disabled")
+                    .color(egui::Color32::from_rgb(255, 180, 76)));
+            }
+            ui.horizontal(|ui| {
+                if !read_only && !fixable.is_empty() {
+                    if ui.add_enabled(!busy, egui::Button::new(egui::RichText::new("Fix All Issues").size(16.0))).clicked() {
+                        self.update_ui_state(UiUpdate::ShowFixDialog(fixable.clone()));
+                    }
+                    let label = format!("Fix Selected ({})", selected.len());
+                    if ui.add_enabled(!busy && !selected.is_empty(), egui::Button::new(egui::RichText::new(label).size(16.0)))
+                        .clicked()
+                    {
+                        self.update_ui_state(UiUpdate::ShowFixDialog(selected.clone()));
+                    }
+                }
+                if ui.add_enabled(!busy, egui::Button::new(egui::RichText::new("Repair Wizard…").size(16.0)))
+                    .on_hover_text("Step through backup, analysis, fixes and validation, ending with what to do next")
+                    .clicked()
                 {
-                    let fixes: Vec<FixType> = fixable_issues.iter()
-                        .filter_map(|i| i.fix_type.clone())
-                        .collect();
-                    
-                    self.update_ui_state(UiUpdate::ShowFixDialog(fixes.clone()));
-                    self.tx.send(Message::FixSelected(fixes)).unwrap();
-                    return;
+                    self.update_ui_state(UiUpdate::OpenWizard);
                 }
-            }
+            });
 
             ui.add_space(INNER_SPACING);
 
-            for (i, issue) in result.issues.iter().enumerate() {
-                if issue.fix_type.is_some() {
+            for &i in &fixable {
+                let issue = &result.issues[i];
+                ui.group(|ui| {
+                    Self::render_issue_summary(ui, issue);
+
+                    ui.add_space(INNER_SPACING);
+                    ui.horizontal(|ui| {
+                        let mut is_selected = fix_selections.get(i).cloned().unwrap_or(false);
+                        if ui.add_enabled(!read_only, egui::Checkbox::new(&mut is_selected, "Select for fixing")).clicked() {
+                            self.update_ui_state(UiUpdate::ToggleFixSelection(i));
+                        }
+                        self.render_issue_links(ui, issue, browser.as_ref());
+                    });
+                });
+                ui.add_space(INNER_SPACING);
+            }
+
+            let manual: Vec<&ValidationIssue> = result.issues.iter().filter(|i| i.fix_type.is_none()).collect();
+            if !manual.is_empty() {
+                ui.add_space(SPACING);
+                ui.heading(egui::RichText::new("Needs Manual Repair").size(18.0));
+                ui.label("These issues have no automatic fix. Each one lists what to try instead.");
+                ui.add_space(INNER_SPACING);
+
+                for issue in manual {
                     ui.group(|ui| {
-                        ui.horizontal(|ui| {
-                            match issue.severity {
-                                IssueSeverity::Critical => {
-                                    ui.label(egui::RichText::new("CRITICAL")
-                                        .color(egui::Color32::from_rgb(255, 88, 88))
-                                        .size(16.0));
+                        Self::render_issue_summary(ui, issue);
+
+                        ui.add_space(INNER_SPACING);
+                        ui.label(egui::RichText::new(issue.code.guidance())
+                            .color(egui::Color32::from_rgb(76, 119, 255)));
+                        ui.horizontal(|ui| self.render_issue_links(ui, issue, browser.as_ref()));
+                    });
+                    ui.add_space(INNER_SPACING);
+                }
+            }
+        }
+    }
+
+    // Severity, message, details and location of an issue
+    fn render_issue_summary(ui: &mut egui::Ui, issue: &ValidationIssue) {
+        ui.horizontal(|ui| {
+            match issue.severity {
+                IssueSeverity::Critical => {
+                    ui.label(egui::RichText::new("CRITICAL")
+                        .color(egui::Color32::from_rgb(255, 88, 88))
+                        .size(16.0));
+                }
+                IssueSeverity::Warning => {
+                    ui.label(egui::RichText::new("WARNING")
+                        .color(egui::Color32::from_rgb(255, 180, 76))
+                        .size(16.0));
+                }
+            }
+            ui.label(egui::RichText::new(&issue.message).size(16.0));
+        });
+
+        ui.add_space(INNER_SPACING);
+        if let Some(details) = &issue.details {
+            ui.label(egui::RichText::new(details)
+                .color(ui.style().visuals.widgets.noninteractive.text_color()));
+        }
+        if let Some(location) = &issue.location {
+            ui.label(egui::RichText::new(format!("{} ({})", location, issue.code))
+                .color(ui.style().visuals.widgets.noninteractive.text_color()));
+        }
+    }
+
+    // Buttons jumping to the bytes and the key an issue concerns
+    fn render_issue_links(&self, ui: &mut egui::Ui, issue: &ValidationIssue, browser: Option<&Arc<Mutex<HiveBrowser>>>) {
+        if let Some(offset) = inspect::issue_offset(issue) {
+            if ui.button("Show Bytes").clicked() {
+                self.update_ui_state(UiUpdate::ShowBytes(offset));
+            }
+        }
+        let key_path = issue.location.as_ref().and_then(|l| l.key_path.as_ref());
+        if let (Some(path), Some(browser)) = (key_path, browser) {
+            let entry = browser.lock().unwrap().find(path);
+            if let Some(entry) = entry {
+                if ui.button("Show Key").clicked() {
+                    self.update_ui_state(UiUpdate::SelectKey(entry));
+                }
+            }
+        }
+    }
+
+    fn health_color(health: KeyHealth) -> Option<egui::Color32> {
+        match health {
+            KeyHealth::Healthy => None,
+            KeyHealth::Damaged => Some(egui::Color32::from_rgb(255, 88, 88)),
+            KeyHealth::DamagedBelow => Some(egui::Color32::from_rgb(255, 180, 76)),
+        }
+    }
+
+    fn render_key_node(&self, ui: &mut egui::Ui, browser: &mut HiveBrowser, entry: &KeyEntry, selected: Option<&KeyEntry>) {
+        let is_selected = selected.map(|s| s.offset == entry.offset).unwrap_or(false);
+        let health = browser.health(entry.offset);
+        let name = if entry.path.is_empty() { format!("{} (root)", entry.name) } else { entry.name.clone() };
+        let mut text = egui::RichText::new(name).monospace();
+        if let Some(color) = Self::health_color(health) {
+            text = text.color(color);
+        }
+        let hover = match health {
+            KeyHealth::Healthy => None,
+            KeyHealth::Damaged => Some("This key has problems"),
+            KeyHealth::DamagedBelow => Some("A key below this one has problems"),
+        };
+
+        if entry.has_subkeys {
+            let id = ui.make_persistent_id(("key_tree", &browser.path, &entry.path));
+            let (_, header, _) = egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, entry.path.is_empty())
+                .show_header(ui, |ui| {
+                    let label = ui.selectable_label(is_selected, text);
+                    match hover {
+                        Some(hover) => label.on_hover_text(hover),
+                        None => label,
+                    }
+                })
+                .body(|ui| {
+                    // Subkeys are only read once the key is expanded
+                    let contents = browser.contents(entry);
+                    for subkey in &contents.subkeys {
+                        self.render_key_node(ui, browser, subkey, selected);
+                    }
+                });
+            if header.inner.clicked() {
+                self.update_ui_state(UiUpdate::SelectKey(entry.clone()));
+            }
+        } else {
+            ui.horizontal(|ui| {
+                ui.add_space(ui.spacing().indent);
+                let label = ui.selectable_label(is_selected, text);
+                let label = match hover {
+                    Some(hover) => label.on_hover_text(hover),
+                    None => label,
+                };
+                if label.clicked() {
+                    self.update_ui_state(UiUpdate::SelectKey(entry.clone()));
+                }
+            });
+        }
+    }
+
+    fn render_key_details(&self, ui: &mut egui::Ui, browser: &mut HiveBrowser, entry: &KeyEntry) {
+        let contents = browser.contents(entry);
+        let label_color = ui.style().visuals.widgets.noninteractive.text_color();
+        let problem_color = egui::Color32::from_rgb(255, 88, 88);
+
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new(if entry.path.is_empty() { "(root)" } else { &entry.path }).strong());
+            if let Some(offset) = inspect::cell_to_file(entry.offset) {
+                if ui.small_button("Show Bytes").clicked() {
+                    self.update_ui_state(UiUpdate::ShowBytes(offset));
+                }
+            }
+        });
+        ui.label(egui::RichText::new(format!("Last written: {}", hive::format_filetime(contents.last_written)))
+            .color(label_color));
+        if let Some(class_name) = &contents.class_name {
+            ui.label(egui::RichText::new(format!("Class: {}", class_name)).color(label_color));
+        }
+        for problem in &contents.problems {
+            ui.label(egui::RichText::new(problem).color(problem_color));
+        }
+
+        ui.add_space(INNER_SPACING);
+        if contents.values.is_empty() {
+            ui.label("This key has no values.");
+            return;
+        }
+        egui::ScrollArea::horizontal()
+            .id_source("value_table_scroll")
+            .show(ui, |ui| {
+                egui::Grid::new("value_table")
+                    .striped(true)
+                    .spacing(egui::vec2(SPACING * 2.0, INNER_SPACING))
+                    .show(ui, |ui| {
+                        for heading in ["Name", "Type", "Data", "Size"] {
+                            ui.label(egui::RichText::new(heading).color(label_color).strong());
+                        }
+                        ui.end_row();
+
+                        for value in &contents.values {
+                            let color = value.problem.as_ref().map(|_| problem_color);
+                            let cell = |text: String| {
+                                let text = egui::RichText::new(text).monospace();
+                                match color {
+                                    Some(color) => text.color(color),
+                                    None => text,
                                 }
-                                IssueSeverity::Warning => {
-                                    ui.label(egui::RichText::new("WARNING")
-                                        .color(egui::Color32::from_rgb(255, 180, 76))
-                                        .size(16.0));
+                            };
+                            let name = ui.label(cell(value.display_name().to_string()));
+                            if let Some(problem) = &value.problem {
+                                name.on_hover_text(problem);
+                            }
+                            ui.label(cell(hive::value_type_name(value.data_type)));
+                            ui.label(cell(value.display_data()));
+                            ui.label(cell(format!("{} bytes", value.size)));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    fn render_browser(&self, ui: &mut egui::Ui) {
+        let (browser, selected) = {
+            let state = self.ui_state.lock().unwrap();
+            let Some(tab) = state.tab() else {
+                return;
+            };
+            (tab.browser.clone(), tab.selected_key.clone())
+        };
+        let Some(browser) = browser else {
+            return;
+        };
+        let mut browser = browser.lock().unwrap();
+
+        ui.add_space(SPACING);
+        ui.heading(egui::RichText::new("Registry Keys").size(20.0));
+        if browser.damaged_count() > 0 {
+            ui.label(egui::RichText::new(format!(
+                "{} key(s) have problems: red keys are damaged, amber keys have damage below them.",
+                browser.damaged_count()
+            )).color(egui::Color32::from_rgb(255, 180, 76)));
+        }
+        ui.add_space(INNER_SPACING);
+
+        ui.columns(2, |columns| {
+            egui::ScrollArea::both()
+                .id_source("key_tree_scroll")
+                .max_height(KEY_TREE_HEIGHT)
+                .auto_shrink([false, true])
+                .show(&mut columns[0], |ui| {
+                    let root = browser.root.clone();
+                    self.render_key_node(ui, &mut browser, &root, selected.as_ref());
+                });
+            match &selected {
+                Some(entry) => self.render_key_details(&mut columns[1], &mut browser, entry),
+                None => {
+                    columns[1].label("Select a key to see its values.");
+                }
+            }
+        });
+        ui.add_space(SPACING);
+    }
+
+    fn render_header_editor(&self, ui: &mut egui::Ui) {
+        let (id, editor, read_only) = {
+            let state = self.ui_state.lock().unwrap();
+            let Some(tab) = state.tab().filter(|tab| tab.analysis_result.is_some()) else {
+                return;
+            };
+            (tab.id, tab.header_editor.as_ref().map(|e| (e.data.clone(), e.inputs.clone(), e.changes())), state.read_only)
+        };
+
+        ui.add_space(SPACING);
+        ui.horizontal(|ui| {
+            ui.heading(egui::RichText::new("Base Block Editor").size(20.0));
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if editor.is_some() {
+                    if ui.button("Close").clicked() {
+                        self.update_ui_state(UiUpdate::CloseHeaderEditor);
+                    }
+                } else if ui.button("Open").clicked() {
+                    self.update_ui_state(UiUpdate::OpenHeaderEditor);
+                }
+            });
+        });
+        let Some((data, inputs, (changes, errors))) = editor else {
+            return;
+        };
+        let edited = header::edited_header(&data, &changes);
+        let dim = ui.style().visuals.widgets.noninteractive.text_color();
+
+        egui::Grid::new("header_editor_grid")
+            .striped(true)
+            .spacing(egui::vec2(SPACING * 2.0, INNER_SPACING))
+            .show(ui, |ui| {
+                for (index, field) in HeaderField::ALL.iter().enumerate() {
+                    ui.label(egui::RichText::new(format!("{} (0x{:X})", field.label(), field.offset())).color(dim));
+                    let mut text = inputs[index].clone();
+                    if ui.add_enabled(!read_only, egui::TextEdit::singleline(&mut text)).changed() {
+                        self.update_ui_state(UiUpdate::SetHeaderInput(index, text));
+                    }
+                    match &errors[index] {
+                        Some(error) => {
+                            ui.label(egui::RichText::new(error).color(egui::Color32::from_rgb(255, 88, 88)));
+                        }
+                        None => match field.warning(&edited, &data) {
+                            Some(warning) => {
+                                ui.label(egui::RichText::new(warning).color(egui::Color32::from_rgb(255, 180, 76)));
+                            }
+                            None => {
+                                ui.label("");
+                            }
+                        },
+                    }
+                    ui.end_row();
+                }
+
+                let stored = hive::read_u32(&data, header::CHECKSUM_OFFSET);
+                let checksum = hive::read_u32(&edited, header::CHECKSUM_OFFSET);
+                ui.label(egui::RichText::new(format!("Checksum (0x{:X})", header::CHECKSUM_OFFSET)).color(dim));
+                ui.monospace(format!("0x{:08X}", checksum));
+                ui.label(egui::RichText::new("Recalculated as the fields are typed").color(dim));
+                ui.end_row();
+                if changes.is_empty() && stored != checksum {
+                    ui.label("");
+                    ui.label(egui::RichText::new(format!("Stored on disk: 0x{:08X}", stored))
+                        .color(egui::Color32::from_rgb(255, 180, 76)));
+                    ui.end_row();
+                }
+            });
+
+        ui.add_space(INNER_SPACING);
+        if changes.is_empty() {
+            ui.label(egui::RichText::new("No changes against the file on disk.").color(dim));
+        } else {
+            ui.label(egui::RichText::new("Changes against the file on disk").strong());
+            egui::Grid::new("header_diff_grid")
+                .striped(true)
+                .spacing(egui::vec2(SPACING * 2.0, INNER_SPACING / 2.0))
+                .show(ui, |ui| {
+                    let diff_row = |ui: &mut egui::Ui, label: &str, before: String, after: String| {
+                        ui.label(label);
+                        ui.monospace(egui::RichText::new(before).color(egui::Color32::from_rgb(255, 88, 88)));
+                        ui.monospace(egui::RichText::new(after).color(egui::Color32::from_rgb(76, 175, 80)));
+                        ui.end_row();
+                    };
+                    for (field, bytes) in &changes {
+                        diff_row(ui, field.label(), field.format(field.bytes(&data)), field.format(bytes));
+                    }
+                    diff_row(ui, "Checksum",
+                        format!("0x{:08X}", hive::read_u32(&data, header::CHECKSUM_OFFSET)),
+                        format!("0x{:08X}", hive::read_u32(&edited, header::CHECKSUM_OFFSET)));
+                });
+        }
+
+        ui.add_space(INNER_SPACING);
+        if read_only {
+            ui.label(egui::RichText::new("Read-only mode: the base block cannot be written")
+                .color(egui::Color32::from_rgb(255, 180, 76)));
+            return;
+        }
+        let can_write = !changes.is_empty() && errors.iter().all(Option::is_none);
+        ui.horizontal(|ui| {
+            if ui.add_enabled(can_write, egui::Button::new("Write Changes")).clicked() {
+                self.tx.send(Message::HeaderEditSelected(id, changes.clone(), None)).unwrap();
+            }
+            if ui.add_enabled(can_write, egui::Button::new("Write to a copy as…")).clicked() {
+                if let Some(output_path) = self.pick_repaired_copy_path() {
+                    self.tx.send(Message::HeaderEditSelected(id, changes.clone(), Some(output_path))).unwrap();
+                }
+            }
+            if ui.add_enabled(!changes.is_empty(), egui::Button::new("Reset")).clicked() {
+                self.update_ui_state(UiUpdate::OpenHeaderEditor);
+            }
+        });
+        ui.label(egui::RichText::new("A .backup copy is made before the file is changed in place.").color(dim));
+        ui.separator();
+    }
+
+    fn page_color(status: PageStatus) -> egui::Color32 {
+        match status {
+            PageStatus::ValidBin => egui::Color32::from_rgb(76, 175, 80),
+            PageStatus::MostlyFree => egui::Color32::from_rgb(76, 119, 255),
+            PageStatus::DamagedCells => egui::Color32::from_rgb(255, 180, 76),
+            PageStatus::BadBinHeader => egui::Color32::from_rgb(255, 88, 88),
+            PageStatus::Zero => egui::Color32::from_rgb(70, 72, 76),
+            PageStatus::HighEntropy => egui::Color32::from_rgb(171, 71, 188),
+            PageStatus::Unrecognized => egui::Color32::from_rgb(140, 140, 140),
+        }
+    }
+
+    fn render_page_map(&self, ui: &mut egui::Ui) {
+        let (show, map, analysis) = {
+            let state = self.ui_state.lock().unwrap();
+            let Some(tab) = state.tab() else {
+                return;
+            };
+            (tab.show_page_map, tab.page_map.clone(), tab.analysis_result.clone())
+        };
+        let Some(analysis) = analysis else {
+            return;
+        };
+
+        ui.add_space(SPACING);
+        ui.horizontal(|ui| {
+            ui.heading(egui::RichText::new("Allocation Map").size(20.0));
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if show {
+                    if ui.button("Close").clicked() {
+                        self.update_ui_state(UiUpdate::ClosePageMap);
+                    }
+                } else if ui.button("Open").clicked() {
+                    self.update_ui_state(UiUpdate::OpenPageMap);
+                }
+            });
+        });
+        if !show {
+            return;
+        }
+        let Some(map) = map else {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Mapping the hive bins...");
+            });
+            return;
+        };
+
+        ui.horizontal_wrapped(|ui| {
+            for status in PageStatus::ALL {
+                let (swatch, _) = ui.allocate_exact_size(egui::vec2(PAGE_MAP_CELL, PAGE_MAP_CELL), egui::Sense::hover());
+                ui.painter().rect_filled(swatch, 1.0, Self::page_color(status));
+                ui.label(format!("{} ({})", status.label(), map.count(status)));
+            }
+        });
+        ui.label(egui::RichText::new(format!(
+            "{} pages of 4 KB. The bright part of a bin page is its share of allocated cells; \
+             outlined pages hold issues. Click a page to show its bytes.",
+            map.pages.len()
+        )).color(ui.style().visuals.widgets.noninteractive.text_color()));
+
+        egui::ScrollArea::vertical()
+            .id_source("page_map")
+            .max_height(PAGE_MAP_HEIGHT)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                let step = PAGE_MAP_CELL + PAGE_MAP_GAP;
+                let columns = ((ui.available_width() + PAGE_MAP_GAP) / step).floor().max(1.0) as usize;
+                let rows = map.pages.len().div_ceil(columns);
+                let (rect, response) = ui.allocate_exact_size(
+                    egui::vec2(columns as f32 * step, rows as f32 * step),
+                    egui::Sense::click(),
+                );
+                let painter = ui.painter_at(rect);
+                let clip = ui.clip_rect();
+                for (index, page) in map.pages.iter().enumerate() {
+                    let min = rect.min + egui::vec2((index % columns) as f32 * step, (index / columns) as f32 * step);
+                    let cell = egui::Rect::from_min_size(min, egui::vec2(PAGE_MAP_CELL, PAGE_MAP_CELL));
+                    if !clip.intersects(cell) {
+                        continue;
+                    }
+                    let color = Self::page_color(page.status);
+                    match page.density() {
+                        Some(density) => {
+                            painter.rect_filled(cell, 1.0, color.linear_multiply(0.3));
+                            let top = cell.max.y - cell.height() * density;
+                            painter.rect_filled(egui::Rect::from_min_max(egui::pos2(cell.min.x, top), cell.max), 1.0, color);
+                        }
+                        None => {
+                            painter.rect_filled(cell, 1.0, color);
+                        }
+                    }
+                    if !page.issues.is_empty() {
+                        painter.rect_stroke(cell, 1.0, egui::Stroke::new(1.5, egui::Color32::WHITE));
+                    }
+                }
+
+                let hovered = response.hover_pos().and_then(|pos| {
+                    let relative = pos - rect.min;
+                    let (column, row) = ((relative.x / step) as usize, (relative.y / step) as usize);
+                    let index = row * columns + column;
+                    (column < columns && index < map.pages.len()).then_some(index)
+                });
+                if let Some(index) = hovered {
+                    if response.clicked() {
+                        self.update_ui_state(UiUpdate::ShowBytes(PageMap::file_offset(index)));
+                    }
+                    egui::show_tooltip_at_pointer(ui.ctx(), egui::Id::new("page_map_tooltip"), |ui| {
+                        Self::render_page_tooltip(ui, &map, index, &analysis);
+                    });
+                }
+            });
+        ui.separator();
+    }
+
+    fn render_page_tooltip(ui: &mut egui::Ui, map: &PageMap, index: usize, analysis: &AnalysisResult) {
+        let page = &map.pages[index];
+        ui.label(egui::RichText::new(format!("Page {} at file offset 0x{:X}", index, PageMap::file_offset(index))).strong());
+        ui.label(format!("{} ({:.2} bits of entropy per byte)", page.status.label(), page.entropy));
+        if let Some(bin) = &page.bin {
+            ui.label(format!("Bin at 0x{:X}, {} bytes", bin.offset, bin.size));
+            ui.label(format!("Cells in this page: {} bytes allocated, {} bytes free", page.allocated, page.free));
+        }
+        if page.issues.is_empty() {
+            ui.label("No issues located here");
+            return;
+        }
+        ui.label(format!("{} issue(s) in this {}:", page.issues.len(), if page.bin.is_some() { "bin" } else { "page" }));
+        for &issue in page.issues.iter().take(PAGE_MAP_TOOLTIP_ISSUES) {
+            if let Some(issue) = analysis.issues.get(issue) {
+                ui.label(format!("• {}", issue.message));
+            }
+        }
+        if page.issues.len() > PAGE_MAP_TOOLTIP_ISSUES {
+            ui.label(format!("... and {} more", page.issues.len() - PAGE_MAP_TOOLTIP_ISSUES));
+        }
+    }
+
+    fn render_hex_view(&self, ui: &mut egui::Ui) {
+        let view = {
+            let state = self.ui_state.lock().unwrap();
+            let Some(tab) = state.tab().filter(|tab| tab.analysis_result.is_some()) else {
+                return;
+            };
+            tab.hex_view.as_ref().map(|v| (v.data.clone(), v.cursor, v.inspection.clone(), v.goto.clone(), v.scroll_to_cursor))
+        };
+
+        ui.add_space(SPACING);
+        ui.horizontal(|ui| {
+            ui.heading(egui::RichText::new("Hex View").size(20.0));
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if view.is_some() {
+                    if ui.button("Close").clicked() {
+                        self.update_ui_state(UiUpdate::CloseHexView);
+                    }
+                } else if ui.button("Open").clicked() {
+                    self.update_ui_state(UiUpdate::ShowBytes(0));
+                }
+            });
+        });
+        let Some((data, cursor, inspection, mut goto, scroll_to_cursor)) = view else {
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("Go to offset:");
+            let response = ui.text_edit_singleline(&mut goto);
+            if response.changed() {
+                self.update_ui_state(UiUpdate::SetHexGoto(goto.clone()));
+            }
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Go").clicked() || entered {
+                match edit::parse_number(goto.trim()) {
+                    Ok(offset) if (offset as usize) < data.len() => self.update_ui_state(UiUpdate::ShowBytes(offset as usize)),
+                    Ok(_) => self.set_tab_status(format!("The file is only {} bytes long.", data.len())),
+                    Err(e) => self.set_tab_status(e.to_string()),
+                }
+            }
+            if cursor >= hive::BASE_BLOCK_SIZE {
+                ui.label(egui::RichText::new(format!("Cell offset 0x{:X}", cursor - hive::BASE_BLOCK_SIZE))
+                    .color(ui.style().visuals.widgets.noninteractive.text_color()));
+            }
+        });
+
+        let structure_color = egui::Color32::from_rgb(120, 170, 255);
+        let field_fill = egui::Color32::from_rgb(66, 69, 73);
+        let cursor_fill = egui::Color32::from_rgb(76, 119, 255);
+        let innermost = inspection.innermost();
+        let field = inspection.field_at(cursor);
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + INNER_SPACING;
+        let rows = data.len().div_ceil(HEX_BYTES_PER_ROW);
+        let mut scroll = egui::ScrollArea::vertical()
+            .id_source("hex_view_scroll")
+            .max_height(HEX_VIEW_HEIGHT)
+            .auto_shrink([false, true]);
+        if scroll_to_cursor {
+            // Leave a few rows of context above the cursor
+            let row = (cursor / HEX_BYTES_PER_ROW).saturating_sub(4);
+            scroll = scroll.vertical_scroll_offset(row as f32 * row_height);
+            if let Some(view) = self.ui_state.lock().unwrap().tab_mut().and_then(|tab| tab.hex_view.as_mut()) {
+                view.scroll_to_cursor = false;
+            }
+        }
+        scroll.show_rows(ui, row_height - INNER_SPACING, rows, |ui, range| {
+            ui.spacing_mut().item_spacing = egui::vec2(INNER_SPACING, INNER_SPACING);
+            for row in range {
+                let start = row * HEX_BYTES_PER_ROW;
+                let end = (start + HEX_BYTES_PER_ROW).min(data.len());
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new(format!("{:08X}", start))
+                        .monospace()
+                        .color(ui.style().visuals.widgets.noninteractive.text_color()));
+                    for (offset, byte) in data[start..end].iter().enumerate().map(|(i, b)| (start + i, b)) {
+                        let mut text = egui::RichText::new(format!("{:02X}", byte)).monospace();
+                        if innermost.map(|s| s.contains(offset)).unwrap_or(false) {
+                            text = text.color(structure_color);
+                        }
+                        if offset == cursor {
+                            text = text.background_color(cursor_fill).color(egui::Color32::WHITE);
+                        } else if field.map(|f| f.contains(offset)).unwrap_or(false) {
+                            text = text.background_color(field_fill);
+                        }
+                        if ui.add(egui::Label::new(text).sense(egui::Sense::click())).clicked() {
+                            self.update_ui_state(UiUpdate::MoveHexCursor(offset));
+                        }
+                    }
+                    let ascii: String = data[start..end].iter()
+                        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                        .collect();
+                    ui.label(egui::RichText::new(ascii).monospace());
+                });
+            }
+        });
+
+        ui.add_space(INNER_SPACING);
+        let label_color = ui.style().visuals.widgets.noninteractive.text_color();
+        for (i, structure) in inspection.structures.iter().enumerate() {
+            ui.label(egui::RichText::new(format!("{} at 0x{:X} ({} bytes)", structure.kind, structure.start, structure.len))
+                .strong());
+            egui::Grid::new(("hex_structure", i))
+                .striped(true)
+                .spacing(egui::vec2(SPACING * 2.0, INNER_SPACING))
+                .show(ui, |ui| {
+                    for field in &structure.fields {
+                        ui.label(egui::RichText::new(format!("0x{:08X}", field.start)).monospace().color(label_color));
+                        if ui.selectable_label(field.contains(cursor), &field.name).clicked() {
+                            self.update_ui_state(UiUpdate::MoveHexCursor(field.start));
+                        }
+                        ui.label(egui::RichText::new(&field.value).monospace());
+                        match field.target {
+                            Some(target) if target < data.len() => {
+                                if ui.small_button("Follow").clicked() {
+                                    self.update_ui_state(UiUpdate::ShowBytes(target));
                                 }
                             }
-                            ui.label(egui::RichText::new(&issue.message).size(16.0));
+                            _ => {
+                                ui.label("");
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            for note in &structure.notes {
+                ui.label(egui::RichText::new(note).color(egui::Color32::from_rgb(255, 180, 76)));
+            }
+        }
+        ui.add_space(SPACING);
+    }
+
+    fn render_wizard(&self, ctx: &egui::Context) {
+        let (id, path, wizard, progress, busy) = {
+            let state = self.ui_state.lock().unwrap();
+            let Some(tab) = state.tab() else {
+                return;
+            };
+            let Some(wizard) = tab.wizard.clone() else {
+                return;
+            };
+            (tab.id, tab.path.to_string_lossy().to_string(), wizard, tab.progress.clone(), tab.running.is_some())
+        };
+
+        let mut open = true;
+        egui::Window::new("Repair Wizard")
+            .open(&mut open)
+            .default_size(egui::vec2(620.0, 460.0))
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for (n, step) in WizardStep::ALL.iter().enumerate() {
+                        let text = egui::RichText::new(format!("{}. {}", n + 1, step.label()));
+                        ui.label(if *step == wizard.step {
+                            text.strong().color(egui::Color32::from_rgb(76, 119, 255))
+                        } else {
+                            text.color(egui::Color32::GRAY)
                         });
+                        if n + 1 < WizardStep::ALL.len() {
+                            ui.label(egui::RichText::new("›").color(egui::Color32::GRAY));
+                        }
+                    }
+                });
+                ui.separator();
+                ui.add_space(INNER_SPACING);
 
-                        ui.add_space(INNER_SPACING);
-                        if let Some(details) = &issue.details {
-                            ui.label(egui::RichText::new(details)
-                                .color(ui.style().visuals.widgets.noninteractive.text_color()));
+                if let Some(outcome) = &wizard.outcome {
+                    ui.label(egui::RichText::new(outcome).color(egui::Color32::from_rgb(255, 180, 76)));
+                    ui.add_space(INNER_SPACING);
+                }
+                match wizard.step {
+                    WizardStep::Backup => self.render_wizard_backup(ui, id, &path, &wizard),
+                    WizardStep::Analyze | WizardStep::Apply => {
+                        self.render_wizard_working(ui, id, &path, &wizard, progress.as_ref(), busy)
+                    }
+                    WizardStep::ChooseFixes => self.render_wizard_fixes(ui, &wizard, busy),
+                    WizardStep::Validate => self.render_wizard_validation(ui, &wizard),
+                    WizardStep::NextSteps => self.render_wizard_next_steps(ui, &path, &wizard),
+                }
+            });
+        if !open {
+            self.update_ui_state(UiUpdate::CloseWizard);
+        }
+    }
+
+    fn render_wizard_backup(&self, ui: &mut egui::Ui, id: TabId, path: &str, wizard: &Wizard) {
+        if wizard.analyze_only {
+            ui.label("Read-only mode: nothing will be written. The wizard analyzes the hive and suggests what to do next.");
+            ui.add_space(SPACING);
+            if ui.button(egui::RichText::new("Continue").size(16.0)).clicked() {
+                self.start_wizard_analysis(id);
+            }
+            return;
+        }
+
+        ui.label("Before anything is changed, make sure the original can be restored. Choose how to repair:");
+        ui.add_space(INNER_SPACING);
+        ui.label(format!("• In place: {} is copied to {}.backup and the copy is checked against the original first.", path, path));
+        ui.label("• A copy: the hive is copied to a file of your choice and only the copy is repaired.");
+        ui.add_space(SPACING);
+        ui.horizontal(|ui| {
+            if ui.button(egui::RichText::new("Back Up and Repair in Place").size(16.0)).clicked() {
+                self.update_ui_state(UiUpdate::WizardBackUp);
+            }
+            if ui.button(egui::RichText::new("Repair a Copy…").size(16.0)).clicked() {
+                if let Some(output_path) = self.pick_repaired_copy_path() {
+                    self.update_ui_state(UiUpdate::WizardRepairCopy(output_path));
+                    self.start_wizard_analysis(id);
+                }
+            }
+        });
+    }
+
+    /// The analysis and apply steps, which wait on background work
+    fn render_wizard_working(
+        &self,
+        ui: &mut egui::Ui,
+        id: TabId,
+        path: &str,
+        wizard: &Wizard,
+        progress: Option<&Progress>,
+        busy: bool,
+    ) {
+        let target = wizard.output_path.as_ref().map(|p| p.to_string_lossy().to_string()).unwrap_or_else(|| path.to_string());
+        if let Some(backup) = &wizard.backup {
+            ui.label(format!("Verified backup: {}", backup));
+        }
+        if wizard.output_path.is_some() {
+            ui.label(format!("Fixes go to the copy {}; {} is left as it is.", target, path));
+        }
+        ui.add_space(INNER_SPACING);
+
+        if busy {
+            let action = match wizard.step {
+                WizardStep::Apply => format!("Applying {} fix(es) to {}…", wizard.selected().len(), target),
+                _ => format!("Analyzing {}…", path),
+            };
+            ui.label(action);
+            ui.horizontal(|ui| {
+                let bar = match progress.and_then(|p| p.fraction()) {
+                    Some(fraction) => egui::ProgressBar::new(fraction).show_percentage(),
+                    None => egui::ProgressBar::new(0.0).animate(true),
+                };
+                ui.add(bar.desired_width(PROGRESS_BAR_WIDTH));
+                if ui.button("Cancel").clicked() {
+                    self.update_ui_state(UiUpdate::CancelOperation);
+                }
+            });
+            if let Some(progress) = progress {
+                ui.label(egui::RichText::new(progress.to_string()).color(egui::Color32::GRAY));
+            }
+        } else if wizard.step == WizardStep::Analyze && ui.button(egui::RichText::new("Analyze Again").size(16.0)).clicked() {
+            self.start_wizard_analysis(id);
+        }
+    }
+
+    fn render_wizard_fixes(&self, ui: &mut egui::Ui, wizard: &Wizard, busy: bool) {
+        let Some(analysis) = &wizard.analysis else {
+            return;
+        };
+        let fixable = analysis.issues.iter().filter(|i| i.fix_type.is_some()).count();
+        let manual: Vec<&ValidationIssue> = analysis.issues.iter().filter(|i| i.fix_type.is_none()).collect();
+        ui.label(format!(
+            "{} issue(s) found: {} with an automatic fix, {} needing manual repair.",
+            analysis.issues.len(), fixable, manual.len()
+        ));
+        ui.add_space(INNER_SPACING);
+
+        egui::ScrollArea::vertical()
+            .id_source("wizard_fixes")
+            .max_height(WIZARD_LIST_HEIGHT)
+            .show(ui, |ui| {
+                for risk in FixRisk::ALL {
+                    let issues: Vec<usize> = (0..analysis.issues.len())
+                        .filter(|&i| analysis.issues[i].fix_type.as_ref().is_some_and(|f| f.risk() == risk))
+                        .collect();
+                    if issues.is_empty() {
+                        continue;
+                    }
+                    ui.label(egui::RichText::new(risk.label()).size(16.0).strong().color(Self::risk_color(risk)));
+                    ui.label(egui::RichText::new(risk.description()).color(egui::Color32::GRAY));
+                    for i in issues {
+                        let mut selected = wizard.selections.get(i).copied().unwrap_or(false);
+                        let checkbox = egui::Checkbox::new(&mut selected, &analysis.issues[i].message);
+                        if ui.add_enabled(!wizard.analyze_only, checkbox).clicked() {
+                            self.update_ui_state(UiUpdate::ToggleWizardSelection(i));
                         }
+                    }
+                    ui.add_space(INNER_SPACING);
+                }
+                if !manual.is_empty() {
+                    ui.label(egui::RichText::new("Needs manual repair").size(16.0).strong());
+                    for issue in manual {
+                        ui.label(format!("• {}", issue.message));
+                    }
+                }
+            });
 
-                        ui.add_space(INNER_SPACING);
-                        let mut is_selected = fix_selections.get(i).cloned().unwrap_or(false);
-                        if ui.checkbox(&mut is_selected, "Select for fixing").clicked() {
-                            self.update_ui_state(UiUpdate::ToggleFixSelection(i));
+        ui.add_space(SPACING);
+        let selected = wizard.selected().len();
+        ui.horizontal(|ui| {
+            if ui.button("Back").clicked() {
+                self.update_ui_state(UiUpdate::SetWizardStep(WizardStep::Backup));
+            }
+            let apply = egui::Button::new(egui::RichText::new(format!("Apply {} Fix(es)", selected)).size(16.0));
+            if ui.add_enabled(!wizard.analyze_only && !busy && selected > 0, apply).clicked() {
+                self.update_ui_state(UiUpdate::WizardApply);
+            }
+            if ui.button("Skip to Next Steps").clicked() {
+                self.update_ui_state(UiUpdate::SetWizardStep(WizardStep::NextSteps));
+            }
+        });
+    }
+
+    fn render_wizard_validation(&self, ui: &mut egui::Ui, wizard: &Wizard) {
+        match &wizard.repaired {
+            Some(repaired) => {
+                let before = wizard.analysis.as_ref().map(|a| a.issues.len()).unwrap_or(0);
+                ui.label(format!(
+                    "Validated {}: {} issue(s) before the repair, {} now.",
+                    repaired.file_info.path, before, repaired.issues.len()
+                ));
+                ui.add_space(INNER_SPACING);
+                egui::ScrollArea::vertical()
+                    .id_source("wizard_validation")
+                    .max_height(WIZARD_LIST_HEIGHT)
+                    .show(ui, |ui| {
+                        for issue in &repaired.issues {
+                            let color = match issue.severity {
+                                IssueSeverity::Critical => egui::Color32::from_rgb(255, 88, 88),
+                                IssueSeverity::Warning => egui::Color32::from_rgb(255, 180, 76),
+                            };
+                            ui.label(egui::RichText::new(format!("• {}", issue.message)).color(color));
+                        }
+                    });
+            }
+            None => {
+                ui.label("The repair did not finish, so there is nothing new to validate.");
+            }
+        }
+        ui.add_space(SPACING);
+        if ui.button(egui::RichText::new("Next Steps").size(16.0)).clicked() {
+            self.update_ui_state(UiUpdate::SetWizardStep(WizardStep::NextSteps));
+        }
+    }
+
+    fn render_wizard_next_steps(&self, ui: &mut egui::Ui, path: &str, wizard: &Wizard) {
+        let steps = match (&wizard.repaired, &wizard.analysis) {
+            (Some(repaired), _) => guide::next_steps(path, repaired, true),
+            (None, Some(analysis)) => guide::next_steps(path, analysis, false),
+            (None, None) => Vec::new(),
+        };
+        if steps.is_empty() {
+            ui.label("The hive could not be analyzed. Restore it from a backup or another copy.");
+        }
+        egui::ScrollArea::vertical()
+            .id_source("wizard_next_steps")
+            .max_height(WIZARD_LIST_HEIGHT)
+            .show(ui, |ui| {
+                for (n, step) in steps.iter().enumerate() {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(egui::RichText::new(format!("{}.", n + 1)).strong());
+                        ui.label(step);
+                        if step.contains('`') && ui.small_button("Copy").on_hover_text("Copy the command").clicked() {
+                            let commands: Vec<&str> = step.split('`').skip(1).step_by(2).collect();
+                            ui.output_mut(|o| o.copied_text = commands.join("\n"));
                         }
                     });
                     ui.add_space(INNER_SPACING);
                 }
+            });
+
+        ui.add_space(SPACING);
+        ui.horizontal(|ui| {
+            if ui.button("Start Over").clicked() {
+                self.update_ui_state(UiUpdate::OpenWizard);
             }
+            if ui.button(egui::RichText::new("Close").size(16.0)).clicked() {
+                self.update_ui_state(UiUpdate::CloseWizard);
+            }
+        });
+    }
+
+    fn risk_color(risk: FixRisk) -> egui::Color32 {
+        match risk {
+            FixRisk::Low => egui::Color32::from_rgb(76, 175, 80),
+            FixRisk::Medium => egui::Color32::from_rgb(255, 180, 76),
+            FixRisk::High => egui::Color32::from_rgb(255, 88, 88),
         }
     }
 
+    fn pick_repaired_copy_path(&self) -> Option<std::path::PathBuf> {
+        let selected_file = self.ui_state.lock().unwrap().tab()?.path.clone();
+        let suggested = registry::default_repaired_path(&selected_file.to_string_lossy());
+        let suggested = std::path::Path::new(&suggested);
+
+        let mut dialog = rfd::FileDialog::new().set_title("Save repaired copy as…");
+        if let Some(name) = suggested.file_name() {
+            dialog = dialog.set_file_name(name.to_string_lossy());
+        }
+        if let Some(dir) = suggested.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        dialog.save_file()
+    }
+
     fn render_fix_dialog(&self, ctx: &egui::Context) {
-        let (show_dialog, selected_fixes) = {
+        let (id, show_dialog, issues, previews, analysis, read_only) = {
             let state = self.ui_state.lock().unwrap();
-            (state.show_fix_dialog, state.selected_fixes.clone())
+            let Some(tab) = state.tab() else {
+                return;
+            };
+            (tab.id, tab.show_fix_dialog, tab.fix_dialog_issues.clone(), tab.fix_previews.clone(),
+                tab.analysis_result.clone(), state.read_only)
+        };
+        let Some(analysis) = analysis else {
+            return;
         };
 
-        if show_dialog {
+        if show_dialog && !read_only {
             egui::Window::new("Confirm Fixes")
-                .fixed_size(egui::vec2(400.0, 200.0))
+                .default_size(egui::vec2(560.0, 420.0))
+                .collapsible(false)
                 .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
                 .show(ctx, |ui| {
-                    ui.heading(egui::RichText::new("Selected Fixes").size(18.0));
+                    ui.heading(egui::RichText::new(format!("Selected Fixes ({})", issues.len())).size(18.0));
                     ui.add_space(SPACING);
-                    
-                    for fix in &selected_fixes {
-                        ui.label(egui::RichText::new(format!("• {:?}", fix)).size(14.0));
-                    }
+
+                    egui::ScrollArea::vertical()
+                        .max_height(FIX_PREVIEW_HEIGHT)
+                        .show(ui, |ui| {
+                            for &i in &issues {
+                                let Some(issue) = analysis.issues.get(i) else {
+                                    continue;
+                                };
+                                // Previewing orphan recovery copies the hive in memory, so it
+                                // waits until the issue is opened
+                                let cheap = issue.fix_type.as_ref().is_some_and(|f| *f != FixType::RecoverOrphans);
+                                egui::CollapsingHeader::new(egui::RichText::new(&issue.message).size(14.0).strong())
+                                    .id_source(("fix_dialog_issue", i))
+                                    .default_open(cheap)
+                                    .show(ui, |ui| {
+                                        if !previews.contains_key(&i) {
+                                            self.update_ui_state(UiUpdate::RequestFixPreview(i));
+                                        }
+                                        Self::render_fix_preview(ui, i, previews.get(&i));
+                                    });
+                                ui.add_space(INNER_SPACING);
+                            }
+                        });
                     
                     ui.add_space(SPACING);
                     ui.separator();
@@ -535,6 +2513,7 @@ impl RegistryFixerApp {
                         .size(16.0));
                     ui.label("A backup will be created before making any changes.");
                     ui.label("Making changes to the header will require recalculating the checksum.");
+                    ui.label("To leave the original untouched, save a repaired copy instead.");
                     
                     ui.add_space(SPACING);
                     
@@ -543,7 +2522,17 @@ impl RegistryFixerApp {
                             .size(16.0))
                             .clicked() 
                         {
-                            self.tx.send(Message::FixSelected(selected_fixes.clone())).unwrap();
+                            self.tx.send(Message::FixSelected(id, issues.clone(), None)).unwrap();
+                            self.update_ui_state(UiUpdate::ClearFixDialog);
+                        }
+                        if ui.button(egui::RichText::new("Save repaired copy as…")
+                            .size(16.0))
+                            .clicked() 
+                        {
+                            if let Some(output_path) = self.pick_repaired_copy_path() {
+                                self.tx.send(Message::FixSelected(id, issues.clone(), Some(output_path))).unwrap();
+                                self.update_ui_state(UiUpdate::ClearFixDialog);
+                            }
                         }
                         if ui.button(egui::RichText::new("Cancel")
                             .size(16.0))
@@ -555,15 +2544,59 @@ impl RegistryFixerApp {
                 });
         }
     }
+
+    // Before and after bytes of one fix, as worked out in memory
+    fn render_fix_preview(ui: &mut egui::Ui, issue: usize, preview: Option<&Result<Vec<ByteChange>, String>>) {
+        match preview {
+            None => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Working out the changes...");
+                });
+            }
+            Some(Err(e)) => {
+                ui.label(egui::RichText::new(format!("Preview unavailable: {}", e))
+                    .color(egui::Color32::from_rgb(255, 88, 88)));
+            }
+            Some(Ok(changes)) if changes.is_empty() => {
+                ui.label("No bytes change.");
+            }
+            Some(Ok(changes)) => {
+                egui::Grid::new(("fix_preview", issue))
+                    .striped(true)
+                    .spacing(egui::vec2(SPACING, INNER_SPACING / 2.0))
+                    .show(ui, |ui| {
+                        ui.label(egui::RichText::new("Offset").strong());
+                        ui.label(egui::RichText::new("Before").strong());
+                        ui.label(egui::RichText::new("After").strong());
+                        ui.end_row();
+                        for change in changes.iter().take(FIX_PREVIEW_MAX_RANGES) {
+                            ui.monospace(format!("0x{:08X}", change.offset));
+                            ui.monospace(preview::hex_bytes(&change.before, FIX_PREVIEW_BYTES));
+                            ui.monospace(preview::hex_bytes(&change.after, FIX_PREVIEW_BYTES));
+                            ui.end_row();
+                        }
+                    });
+                if changes.len() > FIX_PREVIEW_MAX_RANGES {
+                    ui.label(format!("... and {} more changed range(s)", changes.len() - FIX_PREVIEW_MAX_RANGES));
+                }
+            }
+        }
+    }
 }
 
 
 impl eframe::App for RegistryFixerApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         self.process_messages();
+        self.open_dropped_files(ctx);
+        // Progress arrives over the channel, not as input, so keep redrawing
+        if self.ui_state.lock().unwrap().tabs.iter().any(|tab| tab.running.is_some()) {
+            ctx.request_repaint();
+        }
 
         // Set up the frame
-        let frame_stroke = egui::Stroke::none();
+        let frame_stroke = egui::Stroke::NONE;
         let rounding = egui::Rounding::same(WINDOW_ROUNDING);
         
         egui::CentralPanel::default()
@@ -580,31 +2613,53 @@ impl eframe::App for RegistryFixerApp {
                     .show(ui, |ui| {
                         self.render_header(ui, frame);
                         ui.add_space(SPACING);
+                        self.render_tabs(ui);
+                        self.render_progress(ui);
 
-                        let has_analysis = {
+                        let (tab_id, has_analysis, comparison, diff) = {
                             let state = self.ui_state.lock().unwrap();
-                            state.analysis_result.is_some()
+                            (state.tab().map(|t| t.id), state.analysis().is_some(), state.comparison.clone(), state.diff.clone())
                         };
 
-                        if has_analysis {
+                        if has_analysis || comparison.is_some() || diff.is_some() {
                             egui::ScrollArea::vertical()
                                 .auto_shrink([false; 2])
                                 .show(ui, |ui| {
-                                    if let Ok(state) = self.ui_state.lock() {
-                                        if let Some(result) = &state.analysis_result {
+                                    if let Some(comparison) = &comparison {
+                                        self.render_comparison(ui, comparison);
+                                        ui.separator();
+                                    }
+                                    if let Some(diff) = &diff {
+                                        self.render_diff(ui, diff);
+                                        ui.separator();
+                                    }
+                                    // Keep collapsed sections and scroll positions apart between tabs
+                                    ui.push_id(("tab", tab_id), |ui| {
+                                        let analysis = self.ui_state.lock().unwrap().analysis();
+                                        if let Some(result) = &analysis {
                                             ui.heading(egui::RichText::new("File Information").size(20.0));
                                             Self::render_file_info(ui, &result.file_info);
                                             ui.separator();
                                         }
-                                    }
-                                    self.render_issues(ui);
+                                        self.render_header_editor(ui);
+                                        self.render_issues(ui);
+                                        self.render_browser(ui);
+                                        self.render_page_map(ui);
+                                        self.render_hex_view(ui);
+                                    });
                                 });
                         }
 
                         if let Ok(state) = self.ui_state.lock() {
-                            if !state.status_message.is_empty() {
+                            let tab_status = state.tab().map(|t| t.status_message.as_str()).unwrap_or_default();
+                            let messages: Vec<&str> = [state.status_message.as_str(), tab_status].into_iter()
+                                .filter(|m| !m.is_empty())
+                                .collect();
+                            if !messages.is_empty() {
                                 ui.separator();
-                                ui.label(egui::RichText::new(&state.status_message)
+                            }
+                            for message in messages {
+                                ui.label(egui::RichText::new(message)
                                     .size(14.0)
                                     .color(egui::Color32::from_rgb(76, 175, 80)));
                             }
@@ -613,6 +2668,7 @@ impl eframe::App for RegistryFixerApp {
             });
 
         self.render_fix_dialog(ctx);
+        self.render_wizard(ctx);
     }
 }
 
@@ -0,0 +1,167 @@
+use crate::types::{AnalysisResult, IssueCode};
+use std::path::Path;
+
+/// Key below which `reg load` mounts a hive for checking
+const CHECK_KEY: &str = "RegFix_Check";
+
+/// Which hive a file holds. The base block does not say, so this goes by
+/// the file name, ignoring extensions such as `.repaired`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HiveKind {
+    System,
+    Software,
+    Sam,
+    Security,
+    Default,
+    Components,
+    UserProfile,
+    UserClasses,
+    Bcd,
+    Other,
+}
+
+impl HiveKind {
+    pub fn detect(path: &str) -> Self {
+        let name = Path::new(path).file_name().map(|n| n.to_string_lossy().to_uppercase()).unwrap_or_default();
+        match name.split('.').next().unwrap_or_default() {
+            "SYSTEM" => HiveKind::System,
+            "SOFTWARE" => HiveKind::Software,
+            "SAM" => HiveKind::Sam,
+            "SECURITY" => HiveKind::Security,
+            "DEFAULT" => HiveKind::Default,
+            "COMPONENTS" => HiveKind::Components,
+            "NTUSER" => HiveKind::UserProfile,
+            "USRCLASS" => HiveKind::UserClasses,
+            "BCD" => HiveKind::Bcd,
+            _ => HiveKind::Other,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HiveKind::System => "SYSTEM",
+            HiveKind::Software => "SOFTWARE",
+            HiveKind::Sam => "SAM",
+            HiveKind::Security => "SECURITY",
+            HiveKind::Default => "DEFAULT",
+            HiveKind::Components => "COMPONENTS",
+            HiveKind::UserProfile => "NTUSER.DAT",
+            HiveKind::UserClasses => "UsrClass.dat",
+            HiveKind::Bcd => "BCD",
+            HiveKind::Other => "hive",
+        }
+    }
+
+    /// Hives kept in `%SystemRoot%\System32\config`
+    fn is_config_hive(&self) -> bool {
+        matches!(
+            self,
+            HiveKind::System | HiveKind::Software | HiveKind::Sam | HiveKind::Security | HiveKind::Default | HiveKind::Components
+        )
+    }
+
+    /// Root key `reg load` mounts the hive under
+    fn load_root(&self) -> &'static str {
+        match self {
+            HiveKind::UserProfile | HiveKind::UserClasses => "HKU",
+            _ => "HKLM",
+        }
+    }
+}
+
+/// What to do after a repair, in order, judged from the analysis of the
+/// repaired file (or of the untouched file if `repaired` is false) and the
+/// kind of hive `original` is. Commands are quoted in backticks.
+pub fn next_steps(original: &str, analysis: &AnalysisResult, repaired: bool) -> Vec<String> {
+    let kind = HiveKind::detect(original);
+    let target = &analysis.file_info.path;
+    let in_place = target == original;
+    let mut steps = Vec::new();
+
+    let mut unfixed: Vec<IssueCode> = Vec::new();
+    for issue in analysis.issues.iter().filter(|i| i.fix_type.is_none()) {
+        if !unfixed.contains(&issue.code) {
+            unfixed.push(issue.code);
+        }
+    }
+    let unreadable = unfixed.contains(&IssueCode::InvalidSignature);
+    let damaged = unfixed.iter().any(|c| matches!(c, IssueCode::DamagedBinChain | IssueCode::BrokenReference));
+
+    if analysis.issues.iter().any(|i| i.fix_type.is_some()) {
+        steps.push("Some issues still have an automatic fix. Apply them, or run the repair again for the ones you skipped.".to_string());
+    }
+    for code in &unfixed {
+        steps.push(code.guidance().to_string());
+    }
+    if damaged {
+        steps.push(format!(
+            "To write a new hive from everything still readable: `MDC_RegFix rebuild \"{}\" --recover --output \"{}.rebuilt\"`",
+            target, target
+        ));
+    }
+    if (unreadable || damaged) && kind.is_config_hive() {
+        steps.push(format!(
+            "Look for older copies of {} in `%SystemRoot%\\System32\\config\\RegBack`, System Restore points or shadow copies, \
+             and pick the healthiest with `MDC_RegFix compare`.",
+            kind.label()
+        ));
+    }
+
+    if !unreadable {
+        match kind {
+            HiveKind::Bcd => steps.push(format!(
+                "Check that the boot entries can be read: `bcdedit /store \"{}\" /enum all`",
+                target
+            )),
+            _ => steps.push(format!(
+                "Check that Windows can load the hive: `reg load {root}\\{key} \"{}\"`, look through it in regedit, \
+                 then `reg unload {root}\\{key}`",
+                target,
+                root = kind.load_root(),
+                key = CHECK_KEY
+            )),
+        }
+    }
+
+    if kind.is_config_hive() {
+        if !in_place {
+            steps.push(format!(
+                "Put the repaired copy in place while Windows is not running, for example from the recovery \
+                 environment: `copy \"{}\" C:\\Windows\\System32\\config\\{}`",
+                target,
+                kind.label()
+            ));
+        }
+        if kind == HiveKind::Components {
+            steps.push("Check the component store: `DISM /Online /Cleanup-Image /ScanHealth`".to_string());
+        }
+        steps.push("Once Windows starts, run `DISM /Online /Cleanup-Image /RestoreHealth` and then `sfc /scannow`.".to_string());
+        steps.push(
+            "If Windows does not start, repair it offline from the recovery environment: \
+             `DISM /Image:C:\\ /Cleanup-Image /RestoreHealth` and then `sfc /scannow /offbootdir=C:\\ /offwindir=C:\\Windows`."
+                .to_string(),
+        );
+    }
+    match kind {
+        HiveKind::UserProfile | HiveKind::UserClasses => {
+            steps.push(format!(
+                "Only repair or replace the user's {} while they are signed out; Windows keeps it open during a session.",
+                kind.label()
+            ));
+            steps.push("If the profile still fails to load, create a new profile and copy the user's files across.".to_string());
+        }
+        HiveKind::Bcd => steps.push(
+            "If the store is still unusable, recreate it from the recovery environment with `bootrec /rebuildbcd` \
+             or `bcdboot C:\\Windows`."
+                .to_string(),
+        ),
+        _ => {}
+    }
+
+    if repaired && in_place {
+        steps.push(format!("Keep `{}.backup` until Windows has been running normally for a while.", original));
+    } else if repaired {
+        steps.push(format!("The original {} was not changed.", original));
+    }
+    steps
+}
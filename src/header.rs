@@ -0,0 +1,214 @@
+use crate::edit;
+use crate::hive::{self, BASE_BLOCK_SIZE, PAGE_SIZE};
+use crate::registry;
+use crate::types::AnalysisResult;
+use anyhow::{anyhow, bail, Result};
+
+/// Bytes of the base block read and written by the editor; the checksum at
+/// 508 covers everything before it
+pub const HEADER_LEN: usize = 512;
+pub const CHECKSUM_OFFSET: usize = 508;
+
+/// A field and the bytes to store in it
+pub type FieldChange = (HeaderField, Vec<u8>);
+
+/// A base block field that can be set by hand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeaderField {
+    Signature,
+    PrimarySequence,
+    SecondarySequence,
+    LastWritten,
+    MajorVersion,
+    MinorVersion,
+    FileType,
+    FileFormat,
+    RootCell,
+    HiveBinsSize,
+    ClusteringFactor,
+}
+
+impl HeaderField {
+    pub const ALL: [HeaderField; 11] = [
+        HeaderField::Signature,
+        HeaderField::PrimarySequence,
+        HeaderField::SecondarySequence,
+        HeaderField::LastWritten,
+        HeaderField::MajorVersion,
+        HeaderField::MinorVersion,
+        HeaderField::FileType,
+        HeaderField::FileFormat,
+        HeaderField::RootCell,
+        HeaderField::HiveBinsSize,
+        HeaderField::ClusteringFactor,
+    ];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeaderField::Signature => "signature",
+            HeaderField::PrimarySequence => "primary-sequence",
+            HeaderField::SecondarySequence => "secondary-sequence",
+            HeaderField::LastWritten => "last-written",
+            HeaderField::MajorVersion => "major-version",
+            HeaderField::MinorVersion => "minor-version",
+            HeaderField::FileType => "file-type",
+            HeaderField::FileFormat => "file-format",
+            HeaderField::RootCell => "root-cell",
+            HeaderField::HiveBinsSize => "hive-bins-size",
+            HeaderField::ClusteringFactor => "clustering-factor",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HeaderField::Signature => "Signature",
+            HeaderField::PrimarySequence => "Primary sequence number",
+            HeaderField::SecondarySequence => "Secondary sequence number",
+            HeaderField::LastWritten => "Last written (FILETIME)",
+            HeaderField::MajorVersion => "Major version",
+            HeaderField::MinorVersion => "Minor version",
+            HeaderField::FileType => "File type",
+            HeaderField::FileFormat => "File format",
+            HeaderField::RootCell => "Root cell offset",
+            HeaderField::HiveBinsSize => "Hive bins size",
+            HeaderField::ClusteringFactor => "Clustering factor",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn offset(&self) -> usize {
+        match self {
+            HeaderField::Signature => 0,
+            HeaderField::PrimarySequence => 4,
+            HeaderField::SecondarySequence => 8,
+            HeaderField::LastWritten => 12,
+            HeaderField::MajorVersion => 20,
+            HeaderField::MinorVersion => 24,
+            HeaderField::FileType => 28,
+            HeaderField::FileFormat => 32,
+            HeaderField::RootCell => 36,
+            HeaderField::HiveBinsSize => 40,
+            HeaderField::ClusteringFactor => 44,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            HeaderField::LastWritten => 8,
+            _ => 4,
+        }
+    }
+
+    /// The field's bytes within `header`
+    pub fn bytes<'a>(&self, header: &'a [u8]) -> &'a [u8] {
+        &header[self.offset()..self.offset() + self.len()]
+    }
+
+    /// Text for the field as stored, in the form `parse` accepts
+    pub fn format(&self, bytes: &[u8]) -> String {
+        match self {
+            HeaderField::Signature => String::from_utf8_lossy(bytes).to_string(),
+            HeaderField::LastWritten => format!("0x{:016X}", hive::read_u64(bytes, 0)),
+            HeaderField::RootCell | HeaderField::HiveBinsSize => format!("0x{:X}", hive::read_u32(bytes, 0)),
+            _ => hive::read_u32(bytes, 0).to_string(),
+        }
+    }
+
+    /// Parses typed text into the bytes stored for this field
+    pub fn parse(&self, text: &str) -> Result<Vec<u8>> {
+        let text = text.trim();
+        match self {
+            HeaderField::Signature => {
+                if text.len() != 4 || !text.is_ascii() {
+                    bail!("The signature must be exactly 4 ASCII characters");
+                }
+                Ok(text.as_bytes().to_vec())
+            }
+            HeaderField::LastWritten => Ok(edit::parse_number(text)?.to_le_bytes().to_vec()),
+            _ => {
+                let value = u32::try_from(edit::parse_number(text)?)
+                    .map_err(|_| anyhow!("'{}' does not fit in 32 bits", text))?;
+                Ok(value.to_le_bytes().to_vec())
+            }
+        }
+    }
+
+    /// What looks wrong about the field in an edited `header`, judged against
+    /// the other fields and the hive bins in `file`. Such values can still be
+    /// written.
+    pub fn warning(&self, header: &[u8], file: &[u8]) -> Option<String> {
+        let bytes = self.bytes(header);
+        let value = hive::read_u32(bytes, 0);
+        let bins_len = file.len().saturating_sub(BASE_BLOCK_SIZE);
+        match self {
+            HeaderField::Signature if bytes != b"regf" => Some("Windows only loads hives signed 'regf'".to_string()),
+            HeaderField::PrimarySequence | HeaderField::SecondarySequence => {
+                let other = match self {
+                    HeaderField::PrimarySequence => HeaderField::SecondarySequence,
+                    _ => HeaderField::PrimarySequence,
+                };
+                (other.bytes(header) != bytes)
+                    .then(|| "Differs from the other sequence number, which marks an incomplete write".to_string())
+            }
+            HeaderField::MajorVersion if value != 1 => Some("Every known hive has major version 1".to_string()),
+            HeaderField::MinorVersion if !(2..=6).contains(&value) => Some("Known minor versions are 2 to 6".to_string()),
+            HeaderField::FileType if ![0, 1, 2, 6].contains(&value) => {
+                Some("Known file types are 0 (primary), 1 and 2 (log) and 6 (new-format log)".to_string())
+            }
+            HeaderField::FileFormat if value != 1 => Some("Hives on disk use file format 1".to_string()),
+            HeaderField::ClusteringFactor if value != 1 => Some("The clustering factor is always 1".to_string()),
+            HeaderField::HiveBinsSize if !(value as usize).is_multiple_of(PAGE_SIZE) => {
+                Some(format!("Not a multiple of {} bytes", PAGE_SIZE))
+            }
+            HeaderField::HiveBinsSize if value as usize != bins_len => {
+                Some(format!("The file holds 0x{:X} bytes of hive bins", bins_len))
+            }
+            HeaderField::RootCell => {
+                let start = BASE_BLOCK_SIZE + value as usize;
+                if !value.is_multiple_of(8) {
+                    Some("Cell offsets are 8-byte aligned".to_string())
+                } else if start + 6 > file.len() {
+                    Some("Points past the end of the file".to_string())
+                } else if (hive::read_u32(file, start) as i32) >= 0 || &file[start + 4..start + 6] != b"nk" {
+                    Some("No allocated key node starts at this offset".to_string())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `header` with `changes` applied and the checksum recalculated
+pub fn edited_header(header: &[u8], changes: &[FieldChange]) -> Vec<u8> {
+    let mut edited = header[..HEADER_LEN].to_vec();
+    for (field, bytes) in changes {
+        edited[field.offset()..field.offset() + field.len()].copy_from_slice(bytes);
+    }
+    let checksum = registry::calculate_header_checksum(&edited);
+    edited[CHECKSUM_OFFSET..HEADER_LEN].copy_from_slice(&checksum.to_le_bytes());
+    edited
+}
+
+/// Writes `changes` into the base block and recalculates the checksum. As
+/// with the fixes, a `.backup` is made first unless `output_path` is given,
+/// in which case only the copy is changed. Returns the analysis of the result.
+pub fn set_fields(file_path: &str, output_path: Option<&str>, changes: &[FieldChange]) -> Result<AnalysisResult> {
+    for (field, bytes) in changes {
+        if bytes.len() != field.len() {
+            bail!("{} takes {} bytes, not {}", field.label(), field.len(), bytes.len());
+        }
+    }
+    let target = registry::prepare_target(file_path, output_path)?;
+    for (field, bytes) in changes {
+        registry::update_base_block(target, field.offset(), bytes)
+            .map_err(|e| anyhow!("Failed to write {}: {}", field.label(), e))?;
+    }
+    registry::refresh_checksum(target).map_err(|e| anyhow!("Failed to update the checksum: {}", e))?;
+    registry::check_registry_file(target).map_err(|e| anyhow!("Failed to validate {}: {}", target, e))
+}
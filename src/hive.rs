@@ -0,0 +1,1036 @@
+use anyhow::{anyhow, bail, Result};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+
+pub const BASE_BLOCK_SIZE: usize = 4096;
+pub const HBIN_HEADER_SIZE: usize = 32;
+pub const PAGE_SIZE: usize = 4096;
+/// Cell offset meaning "no cell"
+pub const NO_CELL: u32 = 0xFFFF_FFFF;
+
+// Key node flags
+pub const KEY_HIVE_ENTRY: u16 = 0x0004;
+pub const KEY_NO_DELETE: u16 = 0x0008;
+pub const KEY_SYM_LINK: u16 = 0x0010;
+pub const KEY_COMP_NAME: u16 = 0x0020;
+pub const KEY_PREDEF_HANDLE: u16 = 0x0040;
+// Value key flags
+pub const VALUE_COMP_NAME: u16 = 0x0001;
+
+// Value data types
+pub const REG_NONE: u32 = 0;
+pub const REG_SZ: u32 = 1;
+pub const REG_EXPAND_SZ: u32 = 2;
+pub const REG_BINARY: u32 = 3;
+pub const REG_DWORD: u32 = 4;
+pub const REG_DWORD_BIG_ENDIAN: u32 = 5;
+pub const REG_LINK: u32 = 6;
+pub const REG_MULTI_SZ: u32 = 7;
+pub const REG_RESOURCE_LIST: u32 = 8;
+pub const REG_FULL_RESOURCE_DESCRIPTOR: u32 = 9;
+pub const REG_RESOURCE_REQUIREMENTS_LIST: u32 = 10;
+pub const REG_QWORD: u32 = 11;
+
+/// Value data stored inside the vk cell when this bit of the size is set
+pub const DATA_INLINE_FLAG: u32 = 0x8000_0000;
+/// Largest payload of a single big data segment
+pub const BIG_DATA_SEGMENT_SIZE: usize = 16344;
+// Longest value data shown before it is truncated
+const MAX_DISPLAYED_BYTES: usize = 64;
+/// Guard against cyclic or absurdly deep trees in damaged hives
+pub const MAX_DEPTH: usize = 512;
+/// Largest number of entries written to one lh list before an ri index is used
+pub const MAX_LEAF_ENTRIES: usize = 1024;
+
+/// A whole hive file held in memory, with accessors for the regf structures
+#[derive(Debug, Clone)]
+pub struct Hive {
+    data: Vec<u8>,
+    // Built by the first allocation or free; see `FreeCells`
+    free_cells: Option<FreeCells>,
+}
+
+// Free cells of the bins whose cell chain is intact, so that allocating does
+// not rescan the hive. Kept up to date by `allocate_cell`, `append_bin` and
+// `free_cell`, the only code that changes cell sizes.
+#[derive(Debug, Clone, Default)]
+struct FreeCells {
+    // Indexed bins, in file order
+    bins: Vec<Bin>,
+    // Size of every free cell, by offset
+    cells: BTreeMap<u32, u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bin {
+    /// Offset relative to the start of the hive bins data
+    pub offset: u32,
+    pub size: u32,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    /// Offset relative to the start of the hive bins data
+    pub offset: u32,
+    /// Total cell size including the 4 byte size field
+    pub size: u32,
+    pub allocated: bool,
+}
+
+/// Result of walking the hbin chain from the end of the base block
+#[derive(Debug, Clone, Default)]
+pub struct BinWalk {
+    pub bins: Vec<Bin>,
+    /// Bytes covered by consecutive valid bins
+    pub valid_bytes: u32,
+    /// Description of the first problem that stopped the walk
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]  // Parsed in full; not every caller reads every field
+pub struct KeyNode {
+    pub offset: u32,
+    pub flags: u16,
+    pub last_written: u64,
+    pub parent: u32,
+    pub subkey_count: u32,
+    pub subkey_list: u32,
+    pub value_count: u32,
+    pub value_list: u32,
+    pub security: u32,
+    pub class_name: u32,
+    pub class_length: u16,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]  // Parsed in full; not every caller reads every field
+pub struct ValueKey {
+    pub offset: u32,
+    pub name: String,
+    pub raw_size: u32,
+    pub data_offset: u32,
+    pub data_type: u32,
+    pub flags: u16,
+}
+
+impl ValueKey {
+    pub fn is_inline(&self) -> bool {
+        self.raw_size & DATA_INLINE_FLAG != 0
+    }
+
+    pub fn data_size(&self) -> u32 {
+        self.raw_size & !DATA_INLINE_FLAG
+    }
+}
+
+/// Counts gathered while walking the key tree from the root cell
+#[derive(Debug, Clone, Default)]
+pub struct TreeWalk {
+    pub keys: usize,
+    pub values: usize,
+    pub newest_key_time: u64,
+    /// Problems found while following cell references
+    pub errors: Vec<TreeError>,
+    /// Every cell offset referenced from the reachable tree
+    pub referenced_cells: HashSet<u32>,
+}
+
+/// A broken reference found while walking the key tree
+#[derive(Debug, Clone)]
+pub struct TreeError {
+    /// The key whose reference is broken (its parent when the key itself is unreadable)
+    pub key: u32,
+    /// The cell that could not be used
+    pub cell: u32,
+    pub message: String,
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Decodes a key or value name stored either as compressed (Latin-1) or UTF-16LE
+pub fn decode_name(bytes: &[u8], compressed: bool) -> String {
+    if compressed {
+        bytes.iter().map(|&b| b as char).collect()
+    } else {
+        decode_utf16(bytes)
+    }
+}
+
+/// Formats a Windows FILETIME (100ns intervals since 1601) as a UTC timestamp
+pub fn format_filetime(filetime: u64) -> String {
+    const EPOCH_DIFFERENCE: i64 = 11_644_473_600;
+    if filetime == 0 {
+        return "never".to_string();
+    }
+    let seconds = (filetime / 10_000_000) as i64 - EPOCH_DIFFERENCE;
+    let nanos = (filetime % 10_000_000) as u32 * 100;
+    match chrono::DateTime::from_timestamp(seconds, nanos) {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        None => format!("0x{:016X}", filetime),
+    }
+}
+
+/// Current time as a Windows FILETIME
+pub fn filetime_now() -> u64 {
+    const EPOCH_DIFFERENCE: u64 = 11_644_473_600;
+    let now = chrono::Utc::now();
+    (now.timestamp() as u64 + EPOCH_DIFFERENCE) * 10_000_000 + now.timestamp_subsec_nanos() as u64 / 100
+}
+
+pub fn decode_utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+pub fn value_type_name(data_type: u32) -> String {
+    match data_type {
+        REG_NONE => "REG_NONE".to_string(),
+        REG_SZ => "REG_SZ".to_string(),
+        REG_EXPAND_SZ => "REG_EXPAND_SZ".to_string(),
+        REG_BINARY => "REG_BINARY".to_string(),
+        REG_DWORD => "REG_DWORD".to_string(),
+        REG_DWORD_BIG_ENDIAN => "REG_DWORD_BIG_ENDIAN".to_string(),
+        REG_LINK => "REG_LINK".to_string(),
+        REG_MULTI_SZ => "REG_MULTI_SZ".to_string(),
+        REG_RESOURCE_LIST => "REG_RESOURCE_LIST".to_string(),
+        REG_FULL_RESOURCE_DESCRIPTOR => "REG_FULL_RESOURCE_DESCRIPTOR".to_string(),
+        REG_RESOURCE_REQUIREMENTS_LIST => "REG_RESOURCE_REQUIREMENTS_LIST".to_string(),
+        REG_QWORD => "REG_QWORD".to_string(),
+        other => format!("0x{:X}", other),
+    }
+}
+
+/// Splits UTF-16LE string data at NUL terminators, dropping the trailing empty entries
+pub fn decode_multi_string(bytes: &[u8]) -> Vec<String> {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    let mut strings: Vec<String> = units.split(|&u| u == 0).map(String::from_utf16_lossy).collect();
+    while strings.last().map(|s| s.is_empty()).unwrap_or(false) {
+        strings.pop();
+    }
+    strings
+}
+
+/// Human readable rendering of value data, as shown by regedit
+pub fn format_value_data(data_type: u32, data: &[u8]) -> String {
+    let hex = |bytes: &[u8]| {
+        let shown: Vec<String> = bytes.iter().take(MAX_DISPLAYED_BYTES).map(|b| format!("{:02X}", b)).collect();
+        let more = if bytes.len() > MAX_DISPLAYED_BYTES { " ..." } else { "" };
+        format!("{}{}", shown.join(" "), more)
+    };
+    match data_type {
+        REG_SZ | REG_EXPAND_SZ | REG_LINK => decode_multi_string(data).into_iter().next().unwrap_or_default(),
+        REG_MULTI_SZ => decode_multi_string(data).join("; "),
+        REG_DWORD if data.len() >= 4 => {
+            let value = read_u32(data, 0);
+            format!("0x{:08X} ({})", value, value)
+        }
+        REG_DWORD_BIG_ENDIAN if data.len() >= 4 => {
+            let value = u32::from_be_bytes(data[0..4].try_into().unwrap());
+            format!("0x{:08X} ({})", value, value)
+        }
+        REG_QWORD if data.len() >= 8 => {
+            let value = read_u64(data, 0);
+            format!("0x{:016X} ({})", value, value)
+        }
+        _ => hex(data),
+    }
+}
+
+impl Hive {
+    pub fn open(file_path: &str) -> Result<Self> {
+        Self::from_bytes(fs::read(file_path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        if data.len() < BASE_BLOCK_SIZE {
+            bail!("File is too small to be a registry hive ({} bytes)", data.len());
+        }
+        Ok(Self { data, free_cells: None })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn primary_seq_num(&self) -> u32 {
+        read_u32(&self.data, 4)
+    }
+
+    pub fn last_written(&self) -> u64 {
+        read_u64(&self.data, 12)
+    }
+
+    pub fn minor_version(&self) -> u32 {
+        read_u32(&self.data, 24)
+    }
+
+    pub fn root_cell_offset(&self) -> u32 {
+        read_u32(&self.data, 36)
+    }
+
+    /// Length of the hive bins data actually present in the file
+    pub fn bins_len(&self) -> u32 {
+        (self.data.len() - BASE_BLOCK_SIZE) as u32
+    }
+
+    fn abs(offset: u32) -> usize {
+        BASE_BLOCK_SIZE + offset as usize
+    }
+
+    /// Parses the hbin header at `offset`, checking it against its own position
+    pub fn bin_at(&self, offset: u32) -> Result<Bin> {
+        let start = Self::abs(offset);
+        if start + HBIN_HEADER_SIZE > self.data.len() {
+            bail!("Hive bin at 0x{:X} is beyond the end of the file", offset);
+        }
+        let header = &self.data[start..start + HBIN_HEADER_SIZE];
+        if &header[0..4] != b"hbin" {
+            bail!("Missing hbin signature at 0x{:X}", offset);
+        }
+        let stored_offset = read_u32(header, 4);
+        let size = read_u32(header, 8);
+        if stored_offset != offset {
+            bail!("Hive bin at 0x{:X} claims to be at 0x{:X}", offset, stored_offset);
+        }
+        if size == 0 || !(size as usize).is_multiple_of(PAGE_SIZE) {
+            bail!("Hive bin at 0x{:X} has invalid size {}", offset, size);
+        }
+        if start + size as usize > self.data.len() {
+            bail!("Hive bin at 0x{:X} (size {}) runs past the end of the file", offset, size);
+        }
+        Ok(Bin { offset, size, timestamp: read_u64(header, 20) })
+    }
+
+    /// Walks consecutive hive bins until the end of the data or the first invalid header
+    pub fn walk_bins(&self) -> BinWalk {
+        let mut walk = BinWalk::default();
+        let mut offset = 0u32;
+        while (offset as usize) < self.bins_len() as usize {
+            match self.bin_at(offset) {
+                Ok(bin) => {
+                    walk.bins.push(bin);
+                    walk.valid_bytes += bin.size;
+                    offset += bin.size;
+                }
+                Err(e) => {
+                    walk.error = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+        walk
+    }
+
+    pub fn cell(&self, offset: u32) -> Result<Cell> {
+        if offset == NO_CELL {
+            bail!("Reference to a missing cell");
+        }
+        let start = Self::abs(offset);
+        if !offset.is_multiple_of(8) || start + 4 > self.data.len() {
+            bail!("Cell offset 0x{:X} is invalid", offset);
+        }
+        let raw = read_u32(&self.data, start) as i32;
+        let size = raw.unsigned_abs();
+        if size < 8 || start + size as usize > self.data.len() {
+            bail!("Cell at 0x{:X} has invalid size {}", offset, raw);
+        }
+        Ok(Cell { offset, size, allocated: raw < 0 })
+    }
+
+    /// Payload of an allocated cell (without the size field)
+    pub fn cell_data(&self, offset: u32) -> Result<&[u8]> {
+        if !self.cell(offset)?.allocated {
+            bail!("Cell at 0x{:X} is referenced but free", offset);
+        }
+        self.cell_payload(offset)
+    }
+
+    /// Payload of a cell whether it is allocated or free
+    pub fn cell_payload(&self, offset: u32) -> Result<&[u8]> {
+        let cell = self.cell(offset)?;
+        let start = Self::abs(offset) + 4;
+        Ok(&self.data[start..start + cell.size as usize - 4])
+    }
+
+    pub fn key(&self, offset: u32) -> Result<KeyNode> {
+        let data = self.cell_data(offset)?;
+        Self::parse_key(offset, data)
+    }
+
+    pub fn parse_key(offset: u32, data: &[u8]) -> Result<KeyNode> {
+        if data.len() < 76 || &data[0..2] != b"nk" {
+            bail!("Cell at 0x{:X} is not a key node", offset);
+        }
+        let flags = read_u16(data, 2);
+        let name_length = read_u16(data, 72) as usize;
+        if 76 + name_length > data.len() {
+            bail!("Key node at 0x{:X} has a name longer than its cell", offset);
+        }
+        Ok(KeyNode {
+            offset,
+            flags,
+            last_written: read_u64(data, 4),
+            parent: read_u32(data, 16),
+            subkey_count: read_u32(data, 20),
+            subkey_list: read_u32(data, 28),
+            value_count: read_u32(data, 36),
+            value_list: read_u32(data, 40),
+            security: read_u32(data, 44),
+            class_name: read_u32(data, 48),
+            class_length: read_u16(data, 74),
+            name: decode_name(&data[76..76 + name_length], flags & KEY_COMP_NAME != 0),
+        })
+    }
+
+    pub fn value(&self, offset: u32) -> Result<ValueKey> {
+        let data = self.cell_data(offset)?;
+        Self::parse_value(offset, data)
+    }
+
+    pub fn parse_value(offset: u32, data: &[u8]) -> Result<ValueKey> {
+        if data.len() < 20 || &data[0..2] != b"vk" {
+            bail!("Cell at 0x{:X} is not a value key", offset);
+        }
+        let name_length = read_u16(data, 2) as usize;
+        if 20 + name_length > data.len() {
+            bail!("Value key at 0x{:X} has a name longer than its cell", offset);
+        }
+        let flags = read_u16(data, 16);
+        Ok(ValueKey {
+            offset,
+            name: decode_name(&data[20..20 + name_length], flags & VALUE_COMP_NAME != 0),
+            raw_size: read_u32(data, 4),
+            data_offset: read_u32(data, 8),
+            data_type: read_u32(data, 12),
+            flags,
+        })
+    }
+
+    /// Resolves a subkey list (lf, lh, li or ri) to the key node offsets it references
+    pub fn subkey_offsets(&self, list_offset: u32) -> Result<Vec<u32>> {
+        self.list_offsets(list_offset, 0)
+    }
+
+    fn list_offsets(&self, list_offset: u32, depth: usize) -> Result<Vec<u32>> {
+        let data = self.cell_data(list_offset)?;
+        if data.len() < 4 {
+            bail!("Subkey list at 0x{:X} is truncated", list_offset);
+        }
+        let count = read_u16(data, 2) as usize;
+        let signature = &data[0..2];
+        let stride = match signature {
+            b"lf" | b"lh" => 8,
+            b"li" | b"ri" => 4,
+            _ => bail!("Cell at 0x{:X} is not a subkey list", list_offset),
+        };
+        if 4 + count * stride > data.len() {
+            bail!("Subkey list at 0x{:X} has more entries than fit in its cell", list_offset);
+        }
+        let entries = (0..count).map(|i| read_u32(data, 4 + i * stride));
+
+        if signature == b"ri" {
+            if depth > 0 {
+                bail!("Nested index root at 0x{:X}", list_offset);
+            }
+            let mut offsets = Vec::new();
+            for sublist in entries {
+                offsets.extend(self.list_offsets(sublist, depth + 1)?);
+            }
+            Ok(offsets)
+        } else {
+            Ok(entries.collect())
+        }
+    }
+
+    pub fn value_offsets(&self, key: &KeyNode) -> Result<Vec<u32>> {
+        if key.value_count == 0 || key.value_list == NO_CELL {
+            return Ok(Vec::new());
+        }
+        let data = self.cell_data(key.value_list)?;
+        let count = key.value_count as usize;
+        if count * 4 > data.len() {
+            bail!("Value list at 0x{:X} is shorter than the key's value count", key.value_list);
+        }
+        Ok((0..count).map(|i| read_u32(data, i * 4)).collect())
+    }
+
+    /// Cells holding the data of a value: the data cell itself, or the db
+    /// record, its segment list and every segment
+    pub fn value_data_cells(&self, value: &ValueKey) -> Result<Vec<u32>> {
+        if value.is_inline() || value.data_size() == 0 {
+            return Ok(Vec::new());
+        }
+        let data = self.cell_data(value.data_offset)?;
+        if data.len() >= 8 && &data[0..2] == b"db" && value.data_size() as usize > data.len() {
+            let segment_count = read_u16(data, 2) as usize;
+            let segment_list = read_u32(data, 4);
+            let list = self.cell_data(segment_list)?;
+            if segment_count * 4 > list.len() {
+                bail!("Big data segment list at 0x{:X} is truncated", segment_list);
+            }
+            let mut cells = vec![value.data_offset, segment_list];
+            cells.extend((0..segment_count).map(|i| read_u32(list, i * 4)));
+            return Ok(cells);
+        }
+        Ok(vec![value.data_offset])
+    }
+
+    /// Reads the data of a value, following big data records
+    pub fn value_data(&self, value: &ValueKey) -> Result<Vec<u8>> {
+        self.read_value_data(value, |offset| self.cell_data(offset))
+    }
+
+    /// Reads value data without requiring its cells to be allocated, for
+    /// values recovered from free space
+    pub fn deleted_value_data(&self, value: &ValueKey) -> Result<Vec<u8>> {
+        self.read_value_data(value, |offset| self.cell_payload(offset))
+    }
+
+    fn read_value_data<'a>(&'a self, value: &ValueKey, payload: impl Fn(u32) -> Result<&'a [u8]>) -> Result<Vec<u8>> {
+        let size = value.data_size() as usize;
+        if value.is_inline() {
+            return Ok(value.data_offset.to_le_bytes()[..size.min(4)].to_vec());
+        }
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let data = payload(value.data_offset)?;
+        if data.len() >= 8 && &data[0..2] == b"db" && size > data.len() {
+            let segment_count = read_u16(data, 2) as usize;
+            let list = payload(read_u32(data, 4))?;
+            if segment_count * 4 > list.len() {
+                bail!("Big data segment list of value '{}' is truncated", value.name);
+            }
+            // The size comes from the hive, so reserve no more than the segments can hold
+            let mut bytes = Vec::with_capacity(size.min(segment_count * BIG_DATA_SEGMENT_SIZE).min(self.data.len()));
+            for i in 0..segment_count {
+                let segment = payload(read_u32(list, i * 4))?;
+                let wanted = (size - bytes.len()).min(BIG_DATA_SEGMENT_SIZE).min(segment.len());
+                bytes.extend_from_slice(&segment[..wanted]);
+            }
+            if bytes.len() < size {
+                bail!("Big data of value '{}' is missing {} byte(s)", value.name, size - bytes.len());
+            }
+            return Ok(bytes);
+        }
+        if size > data.len() {
+            bail!("Data of value '{}' is larger than its cell", value.name);
+        }
+        Ok(data[..size].to_vec())
+    }
+
+    /// Paths of every reachable key relative to the root (the root itself
+    /// maps to an empty path), keyed by cell offset
+    pub fn key_paths(&self) -> HashMap<u32, String> {
+        let mut paths = HashMap::new();
+        let mut pending = vec![(self.root_cell_offset(), String::new(), 0)];
+        while let Some((offset, path, depth)) = pending.pop() {
+            if depth > MAX_DEPTH || paths.contains_key(&offset) {
+                continue;
+            }
+            let Ok(key) = self.key(offset) else {
+                continue;
+            };
+            if key.subkey_count > 0 && key.subkey_list != NO_CELL {
+                for child in self.subkey_offsets(key.subkey_list).unwrap_or_default() {
+                    if let Ok(child_key) = self.key(child) {
+                        pending.push((child, join_path(&path, &child_key.name), depth + 1));
+                    }
+                }
+            }
+            paths.insert(offset, path);
+        }
+        paths
+    }
+
+    /// Class name of a key, if it has one
+    pub fn class_name(&self, key: &KeyNode) -> Result<Option<String>> {
+        if key.class_name == NO_CELL || key.class_length == 0 {
+            return Ok(None);
+        }
+        let data = self.cell_data(key.class_name)?;
+        let length = key.class_length as usize;
+        if length > data.len() {
+            bail!("Class name of key '{}' is longer than its cell", key.name);
+        }
+        Ok(Some(decode_utf16(&data[..length])))
+    }
+
+    /// Subkeys of a key in list order, skipping unreadable entries
+    pub fn subkeys(&self, key: &KeyNode) -> Result<Vec<KeyNode>> {
+        if key.subkey_count == 0 || key.subkey_list == NO_CELL {
+            return Ok(Vec::new());
+        }
+        Ok(self.subkey_offsets(key.subkey_list)?
+            .into_iter()
+            .filter_map(|offset| self.key(offset).ok())
+            .collect())
+    }
+
+    /// Looks up a key by its path relative to the root, ignoring case
+    pub fn find_key(&self, path: &str) -> Result<KeyNode> {
+        let mut key = self.root_key()?;
+        for name in path.split('\\').filter(|n| !n.is_empty()) {
+            key = self.subkeys(&key)?
+                .into_iter()
+                .find(|k| compare_names(&k.name, name) == Ordering::Equal)
+                .ok_or_else(|| anyhow!("Key '{}' not found below '{}'", name, key.name))?;
+        }
+        Ok(key)
+    }
+
+    pub fn root_key(&self) -> Result<KeyNode> {
+        self.key(self.root_cell_offset())
+            .map_err(|e| anyhow!("Root key is unreadable: {}", e))
+    }
+
+    /// Follows every reference from the root key, tolerating damaged cells,
+    /// and records which cells the tree uses
+    pub fn walk_tree(&self) -> TreeWalk {
+        self.walk_tree_with(&mut |_| true).unwrap_or_default()
+    }
+
+    /// `walk_tree`, handing the walk so far to `visit` after each key read.
+    /// Returns None if `visit` stopped the walk by returning false.
+    pub fn walk_tree_with(&self, visit: &mut dyn FnMut(&TreeWalk) -> bool) -> Option<TreeWalk> {
+        let mut walk = TreeWalk::default();
+        let root = self.root_cell_offset();
+        self.walk_key(root, root, 0, &mut walk, visit).then_some(walk)
+    }
+
+    // Returns false once `visit` has asked to stop
+    fn walk_key(
+        &self,
+        offset: u32,
+        parent: u32,
+        depth: usize,
+        walk: &mut TreeWalk,
+        visit: &mut dyn FnMut(&TreeWalk) -> bool,
+    ) -> bool {
+        let error = |key: u32, cell: u32, message: String| TreeError { key, cell, message };
+        if depth > MAX_DEPTH {
+            walk.errors.push(error(parent, offset, format!("Key tree is deeper than {} levels at 0x{:X}", MAX_DEPTH, offset)));
+            return true;
+        }
+        if !walk.referenced_cells.insert(offset) {
+            walk.errors.push(error(parent, offset, format!("Key at 0x{:X} is referenced more than once", offset)));
+            return true;
+        }
+        let key = match self.key(offset) {
+            Ok(key) => key,
+            Err(e) => {
+                walk.errors.push(error(parent, offset, e.to_string()));
+                return true;
+            }
+        };
+        walk.keys += 1;
+        walk.newest_key_time = walk.newest_key_time.max(key.last_written);
+        if !visit(walk) {
+            return false;
+        }
+
+        for cell in [key.security, key.class_name] {
+            if cell != NO_CELL {
+                walk.referenced_cells.insert(cell);
+            }
+        }
+
+        if key.value_count > 0 && key.value_list != NO_CELL {
+            walk.referenced_cells.insert(key.value_list);
+            match self.value_offsets(&key) {
+                Ok(offsets) => {
+                    for value_offset in offsets {
+                        walk.referenced_cells.insert(value_offset);
+                        match self.value(value_offset) {
+                            Ok(value) => {
+                                walk.values += 1;
+                                match self.value_data_cells(&value) {
+                                    Ok(cells) => walk.referenced_cells.extend(cells),
+                                    Err(e) => walk.errors.push(error(offset, value_offset, e.to_string())),
+                                }
+                            }
+                            Err(e) => walk.errors.push(error(offset, value_offset, e.to_string())),
+                        }
+                    }
+                }
+                Err(e) => walk.errors.push(error(offset, key.value_list, e.to_string())),
+            }
+        }
+
+        if key.subkey_count > 0 && key.subkey_list != NO_CELL {
+            walk.referenced_cells.insert(key.subkey_list);
+            if let Ok(list) = self.cell_data(key.subkey_list) {
+                if list.len() >= 4 && &list[0..2] == b"ri" {
+                    let count = (read_u16(list, 2) as usize).min((list.len() - 4) / 4);
+                    walk.referenced_cells.extend((0..count).map(|i| read_u32(list, 4 + i * 4)));
+                }
+            }
+            match self.subkey_offsets(key.subkey_list) {
+                Ok(offsets) => {
+                    if offsets.len() != key.subkey_count as usize {
+                        walk.errors.push(error(offset, key.subkey_list, format!(
+                            "Key '{}' at 0x{:X} declares {} subkeys but its list holds {}",
+                            key.name, offset, key.subkey_count, offsets.len()
+                        )));
+                    }
+                    for child in offsets {
+                        if !self.walk_key(child, offset, depth + 1, walk, visit) {
+                            return false;
+                        }
+                    }
+                }
+                Err(e) => walk.errors.push(error(offset, key.subkey_list, e.to_string())),
+            }
+        }
+        true
+    }
+}
+
+/// Appends a key name to a path relative to the root
+pub fn join_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}\\{}", parent, name)
+    }
+}
+
+/// Hash stored next to each entry of an lh subkey list
+pub fn name_hash(name: &str) -> u32 {
+    name.to_uppercase()
+        .encode_utf16()
+        .fold(0u32, |hash, unit| hash.wrapping_mul(37).wrapping_add(unit as u32))
+}
+
+/// Order of entries in a subkey list: case-insensitive, by UTF-16 code unit
+pub fn compare_names(a: &str, b: &str) -> Ordering {
+    a.to_uppercase().encode_utf16().cmp(b.to_uppercase().encode_utf16())
+}
+
+/// Case-folded form of a name: two names fold alike exactly when
+/// `compare_names` finds them equal, and folded names sort the same way
+pub fn fold_name(name: &str) -> Vec<u16> {
+    name.to_uppercase().encode_utf16().collect()
+}
+
+/// Whether two key or value names are the same to Windows (case-insensitive)
+pub fn names_equal(a: &str, b: &str) -> bool {
+    compare_names(a, b) == Ordering::Equal
+}
+
+/// Length of a name in bytes once stored as UTF-16, as the largest-name
+/// fields of a key node count it
+pub fn utf16_len(name: &str) -> u32 {
+    (name.encode_utf16().count() * 2) as u32
+}
+
+/// Encodes a name compressed (Latin-1) when possible, UTF-16LE otherwise
+pub fn encode_name(name: &str) -> (Vec<u8>, bool) {
+    if name.chars().all(|c| (c as u32) <= 0xFF) {
+        (name.chars().map(|c| c as u8).collect(), true)
+    } else {
+        (name.encode_utf16().flat_map(|u| u.to_le_bytes()).collect(), false)
+    }
+}
+
+/// Builds the payload of a key node cell with no subkeys, values or class
+pub fn build_key_node(name: &str, parent: u32, security: u32, last_written: u64) -> Vec<u8> {
+    let (name_bytes, compressed) = encode_name(name);
+    let mut record = vec![0u8; 76];
+    record[0..2].copy_from_slice(b"nk");
+    record[2..4].copy_from_slice(&(if compressed { KEY_COMP_NAME } else { 0 }).to_le_bytes());
+    record[4..12].copy_from_slice(&last_written.to_le_bytes());
+    record[16..20].copy_from_slice(&parent.to_le_bytes());
+    for field in [28, 32, 40, 48] {
+        record[field..field + 4].copy_from_slice(&NO_CELL.to_le_bytes());
+    }
+    record[44..48].copy_from_slice(&security.to_le_bytes());
+    record[72..74].copy_from_slice(&(name_bytes.len() as u16).to_le_bytes());
+    record.extend_from_slice(&name_bytes);
+    record
+}
+
+/// Builds the payload of a value key cell. `raw_size` carries the inline
+/// flag; `data_offset` holds the data itself when it is inline.
+pub fn build_value_key(name: &str, data_type: u32, raw_size: u32, data_offset: u32) -> Vec<u8> {
+    let (name_bytes, compressed) = encode_name(name);
+    let mut record = vec![0u8; 20];
+    record[0..2].copy_from_slice(b"vk");
+    record[2..4].copy_from_slice(&(name_bytes.len() as u16).to_le_bytes());
+    record[4..8].copy_from_slice(&raw_size.to_le_bytes());
+    record[8..12].copy_from_slice(&data_offset.to_le_bytes());
+    record[12..16].copy_from_slice(&data_type.to_le_bytes());
+    record[16..18].copy_from_slice(&(if compressed { VALUE_COMP_NAME } else { 0 }).to_le_bytes());
+    record.extend_from_slice(&name_bytes);
+    record
+}
+
+/// Builds an lh subkey list, sorting the `(key offset, name)` entries
+pub fn build_subkey_list(entries: &[(u32, String)]) -> Vec<u8> {
+    let mut sorted: Vec<&(u32, String)> = entries.iter().collect();
+    sorted.sort_by(|a, b| compare_names(&a.1, &b.1));
+
+    let mut record = Vec::with_capacity(4 + sorted.len() * 8);
+    record.extend_from_slice(b"lh");
+    record.extend_from_slice(&(sorted.len() as u16).to_le_bytes());
+    for (offset, name) in sorted {
+        record.extend_from_slice(&offset.to_le_bytes());
+        record.extend_from_slice(&name_hash(name).to_le_bytes());
+    }
+    record
+}
+
+/// Writes a subkey list for `entries` through `allocate`, which stores a
+/// cell payload and returns its offset: one sorted lh list, or an ri index
+/// over several when there are too many for one leaf
+pub fn write_subkey_list<E>(
+    entries: &[(u32, String)],
+    mut allocate: impl FnMut(&[u8]) -> std::result::Result<u32, E>,
+) -> std::result::Result<u32, E> {
+    let mut sorted = entries.to_vec();
+    sorted.sort_by(|a, b| compare_names(&a.1, &b.1));
+    if sorted.len() <= MAX_LEAF_ENTRIES {
+        return allocate(&build_subkey_list(&sorted));
+    }
+    let leaves = sorted.chunks(MAX_LEAF_ENTRIES)
+        .map(|chunk| allocate(&build_subkey_list(chunk)))
+        .collect::<std::result::Result<Vec<u32>, E>>()?;
+    let mut record = Vec::with_capacity(4 + leaves.len() * 4);
+    record.extend_from_slice(b"ri");
+    record.extend_from_slice(&(leaves.len() as u16).to_le_bytes());
+    record.extend(leaves.iter().flat_map(|leaf| leaf.to_le_bytes()));
+    allocate(&record)
+}
+
+// In-memory modification. Callers write the result back through
+// `registry::commit_hive`.
+impl Hive {
+    pub fn set_cell_u32(&mut self, cell: u32, field: usize, value: u32) {
+        let start = Self::abs(cell) + 4 + field;
+        self.data[start..start + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn set_cell_u64(&mut self, cell: u32, field: usize, value: u64) {
+        let start = Self::abs(cell) + 4 + field;
+        self.data[start..start + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Lists the cells of a bin; stops at the first cell with an impossible size
+    pub fn cells_in_bin(&self, bin: &Bin) -> Result<Vec<Cell>> {
+        let mut cells = Vec::new();
+        let end = bin.offset + bin.size;
+        let mut offset = bin.offset + HBIN_HEADER_SIZE as u32;
+        while offset < end {
+            let raw = read_u32(&self.data, Self::abs(offset)) as i32;
+            let size = raw.unsigned_abs();
+            if size < 8 || !size.is_multiple_of(8) || offset + size > end {
+                bail!("Invalid cell size {} at 0x{:X}", raw, offset);
+            }
+            cells.push(Cell { offset, size, allocated: raw < 0 });
+            offset += size;
+        }
+        Ok(cells)
+    }
+
+    // Indexes the free cells on first use
+    fn free_cells(&mut self) -> &mut FreeCells {
+        if self.free_cells.is_none() {
+            let mut index = FreeCells::default();
+            for bin in self.walk_bins().bins {
+                if let Ok(cells) = self.cells_in_bin(&bin) {
+                    index.bins.push(bin);
+                    index.cells.extend(cells.iter().filter(|c| !c.allocated).map(|c| (c.offset, c.size)));
+                }
+            }
+            self.free_cells = Some(index);
+        }
+        self.free_cells.as_mut().unwrap()
+    }
+
+    /// Stores `payload` in a new allocated cell and returns its offset. The
+    /// first free cell that is large enough is used (and split when the
+    /// remainder can hold a cell); otherwise the hive grows by a new bin.
+    pub fn allocate_cell(&mut self, payload: &[u8]) -> Result<u32> {
+        let needed = (payload.len() as u32 + 4).next_multiple_of(8);
+        let free = self.free_cells().cells.iter()
+            .find(|(_, &size)| size >= needed)
+            .map(|(&offset, &size)| Cell { offset, size, allocated: false });
+        let cell = match free {
+            Some(cell) => cell,
+            None => self.append_bin(needed)?,
+        };
+        self.free_cells().cells.remove(&cell.offset);
+
+        let size = if cell.size - needed >= 8 {
+            let remainder = cell.offset + needed;
+            let start = Self::abs(remainder);
+            self.data[start..start + 4].copy_from_slice(&((cell.size - needed) as i32).to_le_bytes());
+            self.free_cells().cells.insert(remainder, cell.size - needed);
+            needed
+        } else {
+            cell.size
+        };
+
+        let start = Self::abs(cell.offset);
+        self.data[start..start + 4].copy_from_slice(&(-(size as i32)).to_le_bytes());
+        self.data[start + 4..start + size as usize].fill(0);
+        self.data[start + 4..start + 4 + payload.len()].copy_from_slice(payload);
+        Ok(cell.offset)
+    }
+
+    /// Appends a bin holding one free cell of at least `cell_size` bytes
+    fn append_bin(&mut self, cell_size: u32) -> Result<Cell> {
+        let walk = self.walk_bins();
+        if walk.error.is_some() || walk.valid_bytes != self.bins_len() {
+            bail!("Cannot grow a hive whose bin chain is damaged");
+        }
+        let offset = walk.valid_bytes;
+        let size = (cell_size + HBIN_HEADER_SIZE as u32).next_multiple_of(PAGE_SIZE as u32);
+
+        let mut bin = vec![0u8; size as usize];
+        bin[0..4].copy_from_slice(b"hbin");
+        bin[4..8].copy_from_slice(&offset.to_le_bytes());
+        bin[8..12].copy_from_slice(&size.to_le_bytes());
+        let free = size - HBIN_HEADER_SIZE as u32;
+        bin[HBIN_HEADER_SIZE..HBIN_HEADER_SIZE + 4].copy_from_slice(&(free as i32).to_le_bytes());
+        self.data.extend_from_slice(&bin);
+
+        let cell = Cell { offset: offset + HBIN_HEADER_SIZE as u32, size: free, allocated: false };
+        if let Some(index) = &mut self.free_cells {
+            index.bins.push(Bin { offset, size, timestamp: 0 });
+            index.cells.insert(cell.offset, cell.size);
+        }
+        Ok(cell)
+    }
+
+    /// Whether an allocated cell starts at `offset`, found by walking the
+    /// cell chain of its bin. Offsets read from damaged records can land
+    /// inside a live cell, where the bytes merely look like a cell size.
+    pub fn is_allocated_cell(&mut self, offset: u32) -> bool {
+        let index = self.free_cells();
+        let bin = index.bins.partition_point(|b| b.offset + b.size <= offset);
+        let Some(bin) = index.bins.get(bin).filter(|b| b.offset <= offset).copied() else {
+            return false;
+        };
+        self.cells_in_bin(&bin).is_ok_and(|cells| cells.iter().any(|c| c.offset == offset && c.allocated))
+    }
+
+    /// Marks an allocated cell as free and merges it with free neighbours.
+    /// Refuses offsets that are not the start of a cell in an intact bin.
+    pub fn free_cell(&mut self, offset: u32) -> Result<()> {
+        let cell = self.cell(offset)?;
+        if !cell.allocated {
+            return Ok(());
+        }
+        if !self.is_allocated_cell(offset) {
+            bail!("0x{:X} is not the start of a cell in an intact hive bin", offset);
+        }
+        let index = self.free_cells();
+        // Free cells never span bins, so adjacent ones share this cell's bin
+        let (mut start, mut size) = (offset, cell.size);
+        if let Some((&previous, &previous_size)) = index.cells.range(..offset).next_back() {
+            if previous + previous_size == offset {
+                start = previous;
+                size += previous_size;
+            }
+        }
+        if let Some(next_size) = index.cells.remove(&(offset + cell.size)) {
+            size += next_size;
+        }
+        index.cells.insert(start, size);
+        let at = Self::abs(start);
+        self.data[at..at + 4].copy_from_slice(&(size as i32).to_le_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rebuild::{self, SalvagedKey};
+
+    // Free cells of every bin, as found by parsing the cells rather than by the index
+    fn free_cells(hive: &Hive) -> Vec<(u32, u32)> {
+        hive.walk_bins().bins.iter()
+            .flat_map(|bin| hive.cells_in_bin(bin).unwrap())
+            .filter(|cell| !cell.allocated)
+            .map(|cell| (cell.offset, cell.size))
+            .collect()
+    }
+
+    #[test]
+    fn freed_cells_merge_with_free_neighbours() {
+        let mut hive = rebuild::build_test_hive(&SalvagedKey::named("ROOT"));
+        let (tail, tail_size) = *free_cells(&hive).last().unwrap();
+
+        let a = hive.allocate_cell(&[1; 12]).unwrap();
+        let b = hive.allocate_cell(&[2; 20]).unwrap();
+        let c = hive.allocate_cell(&[3; 12]).unwrap();
+        assert_eq!((a, b, c), (tail, tail + 16, tail + 40));
+        assert_eq!(free_cells(&hive), vec![(tail + 56, tail_size - 56)]);
+
+        // Freeing a cell between two allocated ones leaves a hole of its own size
+        hive.free_cell(b).unwrap();
+        assert_eq!(free_cells(&hive), vec![(b, 24), (tail + 56, tail_size - 56)]);
+        // The next cell merges into the following free space
+        hive.free_cell(c).unwrap();
+        assert_eq!(free_cells(&hive), vec![(b, tail_size - 16)]);
+        // And the first into the free space before and after it
+        hive.free_cell(a).unwrap();
+        assert_eq!(free_cells(&hive), vec![(tail, tail_size)]);
+        assert_eq!(hive.cell(tail).unwrap(), Cell { offset: tail, size: tail_size, allocated: false });
+    }
+
+    #[test]
+    fn allocation_reuses_the_first_free_cell_that_fits() {
+        let mut hive = rebuild::build_test_hive(&SalvagedKey::named("ROOT"));
+        let a = hive.allocate_cell(&[1; 12]).unwrap();
+        let b = hive.allocate_cell(&[2; 12]).unwrap();
+        hive.allocate_cell(&[3; 12]).unwrap();
+        hive.free_cell(a).unwrap();
+
+        // Too large for the hole at `a`, so it is carved from the free space after the cells
+        let large = hive.allocate_cell(&[4; 40]).unwrap();
+        assert!(large > b);
+        assert_eq!(hive.allocate_cell(&[5; 8]).unwrap(), a);
+        assert_eq!(hive.cell_payload(a).unwrap()[..8], [5; 8]);
+
+        // Larger than any free cell: the hive grows by a bin
+        let bins = hive.walk_bins().bins.len();
+        let huge = hive.allocate_cell(&vec![6; 3 * PAGE_SIZE]).unwrap();
+        assert_eq!(hive.walk_bins().bins.len(), bins + 1);
+        assert!(hive.cell(huge).unwrap().allocated);
+
+        // The index agrees with a fresh scan of the same data
+        let mut rescanned = Hive::from_bytes(hive.data().to_vec()).unwrap();
+        for size in [8, 100, 500, 3000] {
+            assert_eq!(hive.allocate_cell(&vec![7; size]).unwrap(), rescanned.allocate_cell(&vec![7; size]).unwrap());
+        }
+        assert_eq!(hive.data(), rescanned.data());
+    }
+}
@@ -0,0 +1,433 @@
+use crate::hive::{self, BASE_BLOCK_SIZE, HBIN_HEADER_SIZE, NO_CELL, PAGE_SIZE};
+use crate::types::*;
+
+const BYTES_PER_ROW: usize = 16;
+// Rows of hex dump printed around the offset by the CLI
+const DUMP_ROWS_BEFORE: usize = 2;
+const DUMP_ROWS_AFTER: usize = 6;
+// Entries of a subkey, value or segment list that are decoded one by one
+const MAX_LIST_ENTRIES: usize = 64;
+
+/// A labelled range of bytes. Offsets are file offsets.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub start: usize,
+    pub len: usize,
+    pub name: String,
+    pub value: String,
+    /// File offset of the cell this field refers to, if it is a cell reference
+    pub target: Option<usize>,
+}
+
+impl Field {
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.start + self.len
+    }
+}
+
+/// One structure around the inspected offset: the base block, an hbin
+/// header, a cell or the record inside it
+#[derive(Debug, Clone)]
+pub struct Structure {
+    pub kind: String,
+    pub start: usize,
+    pub len: usize,
+    pub fields: Vec<Field>,
+    /// Problems noticed while decoding
+    pub notes: Vec<String>,
+}
+
+impl Structure {
+    fn new(kind: impl Into<String>, start: usize, len: usize) -> Self {
+        Self { kind: kind.into(), start, len, fields: Vec::new(), notes: Vec::new() }
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.start + self.len
+    }
+}
+
+/// Everything known about the bytes at one offset, from the outermost
+/// structure to the innermost
+#[derive(Debug, Clone)]
+pub struct Inspection {
+    pub offset: usize,
+    pub structures: Vec<Structure>,
+}
+
+impl Inspection {
+    /// The innermost field covering `offset`
+    pub fn field_at(&self, offset: usize) -> Option<&Field> {
+        self.structures.iter().rev()
+            .flat_map(|s| s.fields.iter())
+            .find(|f| f.contains(offset))
+    }
+
+    /// The innermost structure, which the hex view highlights
+    pub fn innermost(&self) -> Option<&Structure> {
+        self.structures.last()
+    }
+}
+
+// Bounds-checked little-endian readers; damaged data must never panic
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    data.get(offset..offset + 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+}
+
+/// File offset of a cell offset, or None for NO_CELL
+pub fn cell_to_file(cell: u32) -> Option<usize> {
+    (cell != NO_CELL).then_some(BASE_BLOCK_SIZE + cell as usize)
+}
+
+fn cell_text(cell: u32) -> String {
+    if cell == NO_CELL { "none".to_string() } else { format!("cell 0x{:X}", cell) }
+}
+
+// Adds fields of a structure one after another
+struct Fields<'a> {
+    data: &'a [u8],
+    structure: Structure,
+}
+
+impl<'a> Fields<'a> {
+    fn start(data: &'a [u8], kind: impl Into<String>, start: usize, len: usize) -> Self {
+        Self { data, structure: Structure::new(kind, start, len) }
+    }
+
+    fn add(&mut self, at: usize, len: usize, name: &str, value: String, target: Option<usize>) {
+        let start = self.structure.start + at;
+        if start + len <= self.data.len() {
+            self.structure.fields.push(Field { start, len, name: name.to_string(), value, target });
+        }
+    }
+
+    fn u16(&mut self, at: usize, name: &str) -> u16 {
+        let value = u16_at(self.data, self.structure.start + at).unwrap_or(0);
+        self.add(at, 2, name, value.to_string(), None);
+        value
+    }
+
+    fn u32(&mut self, at: usize, name: &str) -> u32 {
+        let value = u32_at(self.data, self.structure.start + at).unwrap_or(0);
+        self.add(at, 4, name, format!("{} (0x{:X})", value, value), None);
+        value
+    }
+
+    fn flags16(&mut self, at: usize, name: &str) -> u16 {
+        let value = u16_at(self.data, self.structure.start + at).unwrap_or(0);
+        self.add(at, 2, name, format!("0x{:04X}", value), None);
+        value
+    }
+
+    fn time(&mut self, at: usize, name: &str) {
+        let value = u64_at(self.data, self.structure.start + at).unwrap_or(0);
+        self.add(at, 8, name, hive::format_filetime(value), None);
+    }
+
+    fn cell(&mut self, at: usize, name: &str) -> u32 {
+        let value = u32_at(self.data, self.structure.start + at).unwrap_or(NO_CELL);
+        self.add(at, 4, name, cell_text(value), cell_to_file(value));
+        value
+    }
+
+    fn signature(&mut self, at: usize, len: usize) {
+        let start = self.structure.start + at;
+        if let Some(bytes) = self.data.get(start..start + len) {
+            self.add(at, len, "Signature", format!("'{}'", String::from_utf8_lossy(bytes)), None);
+        }
+    }
+
+    fn note(&mut self, note: String) {
+        self.structure.notes.push(note);
+    }
+
+    fn finish(self) -> Structure {
+        self.structure
+    }
+}
+
+fn base_block(data: &[u8]) -> Structure {
+    let mut f = Fields::start(data, "Base block", 0, BASE_BLOCK_SIZE.min(data.len()));
+    f.signature(0, 4);
+    f.u32(4, "Primary sequence number");
+    f.u32(8, "Secondary sequence number");
+    f.time(12, "Last written");
+    f.u32(20, "Major version");
+    f.u32(24, "Minor version");
+    f.u32(28, "File type");
+    f.u32(32, "File format");
+    f.cell(36, "Root cell");
+    f.u32(40, "Hive bins data size");
+    f.u32(44, "Clustering factor");
+    if let Some(name) = data.get(48..112) {
+        f.add(48, 64, "File name", hive::decode_utf16(name).trim_end_matches('\0').to_string(), None);
+    }
+    f.u32(144, "Flags");
+    f.time(168, "Last reorganized");
+    let stored = f.u32(508, "Checksum");
+    if data.len() >= 512 {
+        let calculated = crate::registry::calculate_header_checksum(data);
+        if stored != calculated {
+            f.note(format!("Checksum should be 0x{:08X}", calculated));
+        }
+    }
+    f.finish()
+}
+
+fn bin_header(data: &[u8], start: usize) -> Structure {
+    let mut f = Fields::start(data, "hbin header", start, HBIN_HEADER_SIZE);
+    f.signature(0, 4);
+    let stored = f.u32(4, "Offset");
+    f.u32(8, "Size");
+    f.time(20, "Timestamp");
+    if stored as usize + BASE_BLOCK_SIZE != start {
+        f.note(format!("Claims to be at 0x{:X} but is at 0x{:X}", stored, start - BASE_BLOCK_SIZE));
+    }
+    f.finish()
+}
+
+/// The bin holding `offset`: follows the chain from the base block, then
+/// falls back to the nearest hbin signature on a page boundary
+fn find_bin(data: &[u8], offset: usize) -> Option<(usize, usize, bool)> {
+    let mut start = BASE_BLOCK_SIZE;
+    while data.get(start..start + 4) == Some(b"hbin") {
+        let size = u32_at(data, start + 8)? as usize;
+        if size == 0 || !size.is_multiple_of(PAGE_SIZE) {
+            break;
+        }
+        if offset < start + size {
+            return Some((start, size, true));
+        }
+        start += size;
+    }
+    let mut page = offset - offset % PAGE_SIZE;
+    while page >= BASE_BLOCK_SIZE {
+        if data.get(page..page + 4) == Some(b"hbin") {
+            let size = (u32_at(data, page + 8)? as usize).max(PAGE_SIZE);
+            return (offset < page + size).then_some((page, size, false));
+        }
+        page -= PAGE_SIZE;
+    }
+    None
+}
+
+/// Decodes the record in the cell at `start` (the cell size field)
+fn record(data: &[u8], start: usize, size: usize) -> Option<Structure> {
+    let payload = start + 4;
+    let len = size.saturating_sub(4);
+    let signature = data.get(payload..payload + 2)?;
+    let structure = match signature {
+        b"nk" => {
+            let mut f = Fields::start(data, "Key node (nk)", payload, len);
+            f.signature(0, 2);
+            let flags = f.flags16(2, "Flags");
+            f.time(4, "Last written");
+            f.cell(16, "Parent");
+            f.u32(20, "Subkey count");
+            f.u32(24, "Volatile subkey count");
+            f.cell(28, "Subkey list");
+            f.cell(32, "Volatile subkey list");
+            f.u32(36, "Value count");
+            f.cell(40, "Value list");
+            f.cell(44, "Security");
+            f.cell(48, "Class name");
+            f.u32(52, "Largest subkey name");
+            f.u32(56, "Largest class name");
+            f.u32(60, "Largest value name");
+            f.u32(64, "Largest value data");
+            let name_len = f.u16(72, "Name length") as usize;
+            f.u16(74, "Class name length");
+            if let Some(name) = data.get(payload + 76..payload + 76 + name_len) {
+                let name = hive::decode_name(name, flags & hive::KEY_COMP_NAME != 0);
+                f.add(76, name_len, "Name", name, None);
+            } else {
+                f.note("Name runs past the end of the file".to_string());
+            }
+            if 76 + name_len > len {
+                f.note("Name runs past the end of the cell".to_string());
+            }
+            f.finish()
+        }
+        b"vk" => {
+            let mut f = Fields::start(data, "Value (vk)", payload, len);
+            f.signature(0, 2);
+            let name_len = f.u16(2, "Name length") as usize;
+            let raw_size = u32_at(data, payload + 4).unwrap_or(0);
+            let inline = raw_size & hive::DATA_INLINE_FLAG != 0;
+            let size = raw_size & !hive::DATA_INLINE_FLAG;
+            f.add(4, 4, "Data size", format!("{}{}", size, if inline { " (inline)" } else { "" }), None);
+            if inline || size == 0 {
+                f.add(8, 4, "Data", "held in the record".to_string(), None);
+            } else {
+                f.cell(8, "Data");
+            }
+            let data_type = u32_at(data, payload + 12).unwrap_or(0);
+            f.add(12, 4, "Type", hive::value_type_name(data_type), None);
+            let flags = f.flags16(16, "Flags");
+            if let Some(name) = data.get(payload + 20..payload + 20 + name_len) {
+                let name = hive::decode_name(name, flags & hive::VALUE_COMP_NAME != 0);
+                f.add(20, name_len, "Name", if name.is_empty() { "(Default)".to_string() } else { name }, None);
+            }
+            f.finish()
+        }
+        b"sk" => {
+            let mut f = Fields::start(data, "Security (sk)", payload, len);
+            f.signature(0, 2);
+            f.cell(4, "Next (flink)");
+            f.cell(8, "Previous (blink)");
+            f.u32(12, "Reference count");
+            let descriptor = f.u32(16, "Descriptor size") as usize;
+            f.add(20, descriptor.min(len.saturating_sub(20)), "Descriptor", format!("{} bytes", descriptor), None);
+            f.finish()
+        }
+        b"lf" | b"lh" | b"li" | b"ri" => {
+            let kind = match signature {
+                b"lf" => "Fast leaf (lf)",
+                b"lh" => "Hash leaf (lh)",
+                b"li" => "Index leaf (li)",
+                _ => "Index root (ri)",
+            };
+            let stride = if matches!(signature, b"lf" | b"lh") { 8 } else { 4 };
+            let mut f = Fields::start(data, kind, payload, len);
+            f.signature(0, 2);
+            let count = f.u16(2, "Count") as usize;
+            let entry_name = if signature == b"ri" { "Sublist" } else { "Subkey" };
+            for i in 0..count.min(MAX_LIST_ENTRIES) {
+                f.cell(4 + i * stride, &format!("{} {}", entry_name, i));
+                if stride == 8 {
+                    let hash = u32_at(data, payload + 8 + i * stride).unwrap_or(0);
+                    let label = if signature == b"lf" { "Name hint" } else { "Name hash" };
+                    f.add(8 + i * stride, 4, &format!("{} {}", label, i), format!("0x{:08X}", hash), None);
+                }
+            }
+            if 4 + count * stride > len {
+                f.note(format!("{} entries do not fit in the cell", count));
+            } else if count > MAX_LIST_ENTRIES {
+                f.note(format!("Only the first {} of {} entries are shown", MAX_LIST_ENTRIES, count));
+            }
+            f.finish()
+        }
+        b"db" => {
+            let mut f = Fields::start(data, "Big data (db)", payload, len);
+            f.signature(0, 2);
+            f.u16(2, "Segment count");
+            f.cell(4, "Segment list");
+            f.finish()
+        }
+        _ => return None,
+    };
+    Some(structure)
+}
+
+/// Decodes the structures around `offset` in the raw bytes of a hive file.
+/// Works on damaged files: nothing is assumed beyond what the bytes say.
+pub fn inspect(data: &[u8], offset: usize) -> Inspection {
+    let mut structures = Vec::new();
+    if offset < BASE_BLOCK_SIZE {
+        structures.push(base_block(data));
+        return Inspection { offset, structures };
+    }
+    let Some((bin_start, bin_size, in_chain)) = find_bin(data, offset) else {
+        let mut outside = Structure::new("Unknown data", offset, 1.min(data.len().saturating_sub(offset)));
+        outside.notes.push("No hbin header precedes this offset".to_string());
+        structures.push(outside);
+        return Inspection { offset, structures };
+    };
+    let mut header = bin_header(data, bin_start);
+    if !in_chain {
+        header.notes.push("This bin is not reachable from the start of the bin chain".to_string());
+    }
+    let bin_end = (bin_start + bin_size).min(data.len());
+    if offset < bin_start + HBIN_HEADER_SIZE {
+        structures.push(header);
+        return Inspection { offset, structures };
+    }
+    structures.push(header);
+
+    // Walk the cells of the bin up to the offset
+    let mut start = bin_start + HBIN_HEADER_SIZE;
+    while start + 4 <= bin_end {
+        let raw = u32_at(data, start).unwrap_or(0) as i32;
+        let size = raw.unsigned_abs() as usize;
+        if size < 8 || !size.is_multiple_of(8) || start + size > bin_end {
+            let mut broken = Structure::new("Cell", start, 4);
+            broken.fields.push(Field {
+                start, len: 4, name: "Size".to_string(), value: raw.to_string(), target: None,
+            });
+            broken.notes.push(format!("Invalid cell size {}; the rest of the bin cannot be walked", raw));
+            structures.push(broken);
+            break;
+        }
+        if offset < start + size {
+            let state = if raw < 0 { "allocated" } else { "free" };
+            let mut cell = Structure::new(format!("Cell 0x{:X} ({})", start - BASE_BLOCK_SIZE, state), start, size);
+            cell.fields.push(Field {
+                start, len: 4, name: "Size".to_string(), value: format!("{} ({} bytes, {})", raw, size, state), target: None,
+            });
+            match record(data, start, size) {
+                Some(record) => {
+                    structures.push(cell);
+                    structures.push(record);
+                }
+                None => {
+                    if raw < 0 {
+                        cell.notes.push("No record signature: class name, value data, a value or segment list, or a damaged record".to_string());
+                    }
+                    structures.push(cell);
+                }
+            }
+            break;
+        }
+        start += size;
+    }
+    Inspection { offset, structures }
+}
+
+/// File offset of the bytes an issue concerns, if it can be told
+pub fn issue_offset(issue: &ValidationIssue) -> Option<usize> {
+    issue.location.as_ref().map(|l| l.offset as usize)
+}
+
+/// One line of a classic hex dump
+pub fn hex_row(data: &[u8], row_start: usize) -> String {
+    let end = (row_start + BYTES_PER_ROW).min(data.len());
+    let bytes = &data[row_start..end];
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    let ascii: String = bytes.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+    format!("{:08X}  {:<48} {}", row_start, hex.join(" "), ascii)
+}
+
+pub fn print_inspection(data: &[u8], inspection: &Inspection) {
+    let offset = inspection.offset;
+    println!("Offset 0x{:X} (file)", offset);
+    if offset >= BASE_BLOCK_SIZE {
+        println!("       0x{:X} (cell offset, relative to the hive bins)", offset - BASE_BLOCK_SIZE);
+    }
+
+    let first_row = (offset / BYTES_PER_ROW).saturating_sub(DUMP_ROWS_BEFORE) * BYTES_PER_ROW;
+    let last_row = (offset / BYTES_PER_ROW + DUMP_ROWS_AFTER) * BYTES_PER_ROW;
+    println!();
+    for row in (first_row..=last_row).step_by(BYTES_PER_ROW).filter(|&row| row < data.len()) {
+        let marker = if row == offset / BYTES_PER_ROW * BYTES_PER_ROW { '>' } else { ' ' };
+        println!("{} {}", marker, hex_row(data, row));
+    }
+
+    for structure in &inspection.structures {
+        println!("\n{} at 0x{:X} ({} bytes)", structure.kind, structure.start, structure.len);
+        for field in &structure.fields {
+            let marker = if field.contains(offset) { '>' } else { ' ' };
+            println!("{} 0x{:08X} +{:<4} {:<26} {}", marker, field.start, field.start - structure.start, field.name, field.value);
+        }
+        for note in &structure.notes {
+            println!("  ! {}", note);
+        }
+    }
+}
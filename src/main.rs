@@ -1,14 +1,37 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
 use eframe::{egui, epaint::Rounding};
 use gui::RegistryFixerApp;
-use image::io::Reader as ImageReader;
+use image::ImageReader;
 use std::io::Cursor;
+use audit::AuditLog;
 
+mod audit;
+mod browse;
+mod carve;
+mod compact;
+mod compare;
+mod diff;
+mod edit;
+mod export;
+mod graft;
 mod gui;
+mod guide;
+mod header;
+mod hive;
+mod inspect;
+mod merge;
+mod pagemap;
+mod preview;
+mod progress;
+mod rebuild;
+mod recent;
+mod regfile;
 mod registry;
+mod scan;
+mod tui;
 mod types;
 
 /// Windows Registry Fixer
@@ -18,6 +41,673 @@ struct Args {
     /// Path to the registry file to fix
     #[arg(short, long)]
     file: Option<PathBuf>,
+
+    /// Never write to any file (forensic mode); implies an audit log
+    #[arg(long, global = true)]
+    read_only: bool,
+
+    /// Append a JSON audit record of every analysis and fix to this file
+    #[arg(long, value_name = "PATH", global = true)]
+    audit_log: Option<PathBuf>,
+
+    /// Apply every available fix after the analysis
+    #[arg(long, requires = "file")]
+    fix: bool,
+
+    /// Write the repaired hive to this path instead of patching the file in place
+    #[arg(short, long, value_name = "PATH", requires = "fix")]
+    output: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check every hive of a mounted Windows installation in parallel
+    Scan {
+        /// Windows directory or root of the mounted Windows volume
+        root: PathBuf,
+    },
+    /// Score several copies of the same hive and recommend the healthiest
+    Compare {
+        /// Candidate copies (live hive, RegBack, .backup, restore point, ...)
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<PathBuf>,
+    },
+    /// Show the keys and values added, removed or changed between two hives
+    Diff {
+        /// The earlier hive, e.g. the RegBack copy
+        before: PathBuf,
+
+        /// The later hive, e.g. the repaired copy
+        after: PathBuf,
+
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Write the report to this file instead of printing it (JSON only)
+        #[arg(short, long, value_name = "PATH", requires = "json")]
+        output: Option<PathBuf>,
+    },
+    /// Merge several damaged copies of one hive page by page into a new file
+    Merge {
+        /// Copies of the same hive, aligned by hbin offset
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<PathBuf>,
+
+        /// Where to write the merged hive
+        #[arg(short, long, value_name = "PATH")]
+        output: PathBuf,
+    },
+    /// Write a brand-new, compact hive from everything readable in a damaged one
+    Rebuild {
+        /// Damaged hive; it is only read
+        file: PathBuf,
+
+        /// Where to write the rebuilt hive
+        #[arg(short, long, value_name = "PATH")]
+        output: PathBuf,
+
+        /// Also keep orphaned keys and values, below RegFix_Recovered
+        #[arg(long)]
+        recover: bool,
+    },
+    /// Defragment a hive: pack its cells together and drop trailing free bins
+    Compact {
+        /// Hive to compact; a .backup is made first unless --output is given
+        file: PathBuf,
+
+        /// Write the compacted hive to this path instead of changing the file in place
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Analyze, browse and repair a hive in the terminal, without a graphical display
+    Tui {
+        /// Hive to open; fixes are written in place after a .backup unless --output is given
+        file: PathBuf,
+
+        /// Write the repaired hive to this path instead of patching the file in place
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Copy a key and everything below it from a healthy donor hive
+    Graft {
+        /// Hive to repair; a .backup is made first unless --output is given
+        file: PathBuf,
+
+        /// Hive to copy the key from; it is only read
+        donor: PathBuf,
+
+        /// Key to copy, relative to the hive root
+        key: String,
+
+        /// Write the grafted hive to this path instead of changing the file in place
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Merge into an existing key instead of replacing it
+        #[arg(long)]
+        merge: bool,
+    },
+    /// Recover deleted keys, values and security descriptors from free space
+    Carve {
+        /// Hive to carve; it is only read
+        file: PathBuf,
+
+        /// Export the recovered records as JSON lines to this file
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Label the structures at one offset of a hive and decode the record there
+    Inspect {
+        /// Hive to inspect; it is only read
+        file: PathBuf,
+
+        /// File offset to inspect, decimal or 0x-prefixed hex
+        #[arg(long, value_name = "OFFSET", value_parser = parse_offset)]
+        offset: u64,
+
+        /// Treat the offset as a cell offset, relative to the start of the hive bins
+        #[arg(long)]
+        cell: bool,
+    },
+    /// Export a hive or one of its subtrees to a .reg file
+    ExportReg {
+        /// Hive to export; it is only read
+        file: PathBuf,
+
+        /// Where to write the .reg file
+        #[arg(short, long, value_name = "PATH")]
+        output: PathBuf,
+
+        /// Key to export, relative to the hive root (default: the whole hive)
+        #[arg(long, value_name = "PATH", default_value = "")]
+        key: String,
+
+        /// Written in place of the hive root, e.g. HKEY_LOCAL_MACHINE\SYSTEM
+        /// (default: the root key's name)
+        #[arg(long, value_name = "PREFIX", default_value = "")]
+        root_prefix: String,
+    },
+    /// Change a key or value of an offline hive
+    Edit {
+        /// Hive to edit; a .backup is made first unless --output is given
+        file: PathBuf,
+
+        /// Write the edited hive to this path instead of changing the file in place
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        #[command(subcommand)]
+        action: EditAction,
+    },
+    /// Set one base block field by hand; the checksum is recalculated
+    SetField {
+        /// Hive to change; a .backup is made first unless --output is given
+        file: PathBuf,
+
+        /// signature, primary-sequence, secondary-sequence, last-written, major-version,
+        /// minor-version, file-type, file-format, root-cell, hive-bins-size or clustering-factor
+        #[arg(value_parser = parse_header_field)]
+        field: header::HeaderField,
+
+        /// New value: four characters for the signature, otherwise decimal or 0x-prefixed hex
+        value: String,
+
+        /// Write the changed hive to this path instead of changing the file in place
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Apply a .reg file (REGEDIT4 or version 5.00) to an offline hive
+    Import {
+        /// Hive to change; a .backup is made first unless --output is given
+        file: PathBuf,
+
+        /// The .reg file to apply
+        reg: PathBuf,
+
+        /// Write the changed hive to this path instead of changing the file in place
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Key in the .reg file that corresponds to the hive root, e.g.
+        /// HKEY_LOCAL_MACHINE\SYSTEM (default: the root key's name)
+        #[arg(long, value_name = "PREFIX", default_value = "")]
+        root_prefix: String,
+
+        /// Only show what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Flatten the key tree and the analysis issues into JSON lines or SQLite
+    Export {
+        /// Hive to export; it is only read
+        file: PathBuf,
+
+        /// Where to write the export
+        #[arg(short, long, value_name = "PATH")]
+        output: PathBuf,
+
+        /// Output format (default: sqlite for .db/.sqlite files, jsonl otherwise)
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+
+        /// Prefix of every key path instead of the root key's name
+        #[arg(long, value_name = "PREFIX", default_value = "")]
+        root_prefix: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum EditAction {
+    /// Create a key and any missing parents
+    CreateKey {
+        /// Key path relative to the hive root
+        path: String,
+    },
+    /// Delete a key and everything below it
+    DeleteKey {
+        /// Key path relative to the hive root
+        path: String,
+    },
+    /// Give a key a new name
+    RenameKey {
+        /// Key path relative to the hive root
+        path: String,
+        new_name: String,
+    },
+    /// Create or replace a value
+    SetValue {
+        /// Key path relative to the hive root
+        key: String,
+        /// Value name; @ is the default value
+        name: String,
+        /// Data type, e.g. REG_SZ, REG_DWORD, REG_MULTI_SZ or a number
+        #[arg(long = "type", value_name = "TYPE", default_value = "REG_SZ")]
+        data_type: String,
+        /// Data: text, a decimal or 0x number, one string per REG_MULTI_SZ
+        /// entry, or hex bytes for the other types
+        data: Vec<String>,
+    },
+    /// Delete a value
+    DeleteValue {
+        /// Key path relative to the hive root
+        key: String,
+        /// Value name; @ is the default value
+        name: String,
+    },
+}
+
+impl EditAction {
+    fn into_edit(self) -> anyhow::Result<edit::Edit> {
+        let value_name = |name: String| if name == "@" { String::new() } else { name };
+        Ok(match self {
+            EditAction::CreateKey { path } => edit::Edit::CreateKey(path),
+            EditAction::DeleteKey { path } => edit::Edit::DeleteKey(path),
+            EditAction::RenameKey { path, new_name } => edit::Edit::RenameKey { path, new_name },
+            EditAction::SetValue { key, name, data_type, data } => {
+                let data_type = edit::parse_value_type(&data_type)?;
+                edit::Edit::SetValue {
+                    key,
+                    name: value_name(name),
+                    data: edit::parse_value_data(data_type, &data)?,
+                    data_type,
+                }
+            }
+            EditAction::DeleteValue { key, name } => edit::Edit::DeleteValue { key, name: value_name(name) },
+        })
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum ExportFormat {
+    Jsonl,
+    Sqlite,
+}
+
+fn parse_offset(text: &str) -> Result<u64, String> {
+    edit::parse_number(text).map_err(|e| e.to_string())
+}
+
+fn parse_header_field(text: &str) -> Result<header::HeaderField, String> {
+    header::HeaderField::from_name(text).ok_or_else(|| {
+        let names: Vec<&str> = header::HeaderField::ALL.iter().map(|f| f.name()).collect();
+        format!("unknown field; expected one of {}", names.join(", "))
+    })
+}
+
+/// Refuses exports that would overwrite the hive they are read from
+fn ensure_distinct_output(input: &Path, output: &Path) -> anyhow::Result<()> {
+    if let (Ok(source), Ok(target)) = (std::fs::canonicalize(input), std::fs::canonicalize(output)) {
+        if source == target {
+            anyhow::bail!("The output path must differ from the hive being read");
+        }
+    }
+    Ok(())
+}
+
+/// Runs `change`, which modifies `path` or writes the result to `output`,
+/// and records it in the audit log with the input's hash before and the
+/// target's hash after. Failures are recorded too; the after hash is null
+/// when the target was never written.
+fn audited_change<T>(
+    audit_log: Option<&AuditLog>,
+    action: &str,
+    path: &str,
+    output: Option<&str>,
+    change: impl FnOnce() -> anyhow::Result<T>,
+    describe: impl FnOnce(&T) -> String,
+) -> anyhow::Result<T> {
+    let before = audit_log.map(|_| audit::hash_file(path)).transpose()?;
+    let result = change();
+    if let Some(log) = audit_log {
+        let outcome = match &result {
+            Ok(value) => describe(value),
+            Err(e) => e.to_string(),
+        };
+        let target = output.unwrap_or(path);
+        let hashes = audit::FileHashes { before: before.unwrap_or_default(), after: audit::hash_file(target).ok() };
+        log.record_change(action, path, output, &hashes, &outcome)?;
+    }
+    result
+}
+
+fn run_command(command: Command, audit_log: Option<&AuditLog>) -> anyhow::Result<()> {
+    match command {
+        Command::Scan { root } => {
+            let hives = scan::discover_hives(&root)?;
+            if hives.is_empty() {
+                println!("No hives found below {}", root.display());
+                return Ok(());
+            }
+            println!("Scanning {} hive(s) below {}\n", hives.len(), root.display());
+            scan::print_summary(&scan::scan_hives(hives, audit_log));
+        }
+        Command::Compare { files } => {
+            let paths: Vec<String> = files.iter().map(|f| f.to_string_lossy().to_string()).collect();
+            compare::print_comparison(&compare::compare(&paths, audit_log));
+        }
+        Command::Diff { before, after, json, output } => {
+            if let Some(output) = &output {
+                ensure_distinct_output(&before, output)?;
+                ensure_distinct_output(&after, output)?;
+            }
+            let paths = [before.to_string_lossy().to_string(), after.to_string_lossy().to_string()];
+            let diff = diff::diff_hives(&paths[0], &paths[1])?;
+            let output = output.map(|o| o.to_string_lossy().to_string());
+            match &output {
+                Some(output) => {
+                    registry::create_output_file(output, format!("{:#}\n", diff.to_json()).as_bytes())?;
+                    println!("Differences written to {}", output);
+                }
+                None if json => println!("{:#}", diff.to_json()),
+                None => diff::print_diff(&diff),
+            }
+            if let Some(log) = audit_log {
+                let summary = diff.summary();
+                let outcome = format!("{} key(s) and {} value(s) differ",
+                    summary.keys.iter().sum::<usize>(), summary.values.iter().sum::<usize>());
+                log.record_operation("diff", &paths, output.as_deref(), &outcome)?;
+            }
+        }
+        Command::Merge { files, output } => {
+            for file in &files {
+                ensure_distinct_output(file, &output)?;
+            }
+            let paths: Vec<String> = files.iter().map(|f| f.to_string_lossy().to_string()).collect();
+            let output = output.to_string_lossy().to_string();
+            let result = merge::merge_copies(&paths, &output);
+            if let Some(log) = audit_log {
+                let outcome = match &result {
+                    Ok(report) => format!("merged {} page(s)", report.pages.len()),
+                    Err(e) => e.to_string(),
+                };
+                log.record_operation("merge", &paths, result.is_ok().then_some(output.as_str()), &outcome)?;
+            }
+            merge::print_report(&result?);
+        }
+        Command::Rebuild { file, output, recover } => {
+            ensure_distinct_output(&file, &output)?;
+            let path = file.to_string_lossy().to_string();
+            let output = output.to_string_lossy().to_string();
+            let result = rebuild::rebuild_hive(&path, &output, recover);
+            if let Some(log) = audit_log {
+                let outcome = match &result {
+                    Ok(report) => format!("rebuilt {} key(s) and {} value(s), dropped {}",
+                        report.keys, report.values, report.dropped.len()),
+                    Err(e) => e.to_string(),
+                };
+                log.record_operation("rebuild", &[path], result.is_ok().then_some(output.as_str()), &outcome)?;
+            }
+            rebuild::print_report(&result?);
+        }
+        Command::Tui { file, output } => {
+            let path = file.to_string_lossy().to_string();
+            let output = output.map(|o| o.to_string_lossy().to_string());
+            if let Some(output) = &output {
+                ensure_distinct_output(&file, Path::new(output))?;
+            }
+            tui::run(&path, output.as_deref(), audit_log)?;
+        }
+        Command::Compact { file, output } => {
+            let path = file.to_string_lossy().to_string();
+            let output = output.map(|o| o.to_string_lossy().to_string());
+            let report = audited_change(audit_log, "compact", &path, output.as_deref(),
+                || compact::compact_hive(&path, output.as_deref()),
+                |report| format!("compacted {} cell(s), saved {} bytes", report.cells, report.saved()))?;
+            let target = output.as_deref().unwrap_or(&path);
+            compact::print_report(&report);
+            println!("Written to {}", target);
+        }
+        Command::Graft { file, donor, key, output, merge } => {
+            let path = file.to_string_lossy().to_string();
+            let donor = donor.to_string_lossy().to_string();
+            let output = output.map(|o| o.to_string_lossy().to_string());
+            let report = audited_change(audit_log, "graft", &path, output.as_deref(),
+                || graft::graft(&path, &donor, &key, output.as_deref(), merge),
+                |report| format!("grafted {} from {}: {} key(s) added, {} value(s) written",
+                    report.key_path, donor, report.keys_added, report.values_written))?;
+            let target = output.as_deref().unwrap_or(&path);
+            graft::print_report(&report, merge);
+            if let Ok(validation) = &report.validation {
+                print_issues(&validation.issues);
+            }
+            println!("Written to {}", target);
+        }
+        Command::Carve { file, output } => {
+            let path = file.to_string_lossy().to_string();
+            if let Some(output) = &output {
+                ensure_distinct_output(&file, output)?;
+            }
+            let records = carve::carve(&hive::Hive::open(&path)?);
+            carve::print_records(&records);
+            let output = output.map(|o| o.to_string_lossy().to_string());
+            if let Some(output) = &output {
+                carve::export_json_lines(&records, output)?;
+                println!("Exported to {}", output);
+            }
+            if let Some(log) = audit_log {
+                let outcome = format!("carved {} record(s)", records.len());
+                log.record_operation("carve", &[path], output.as_deref(), &outcome)?;
+            }
+        }
+        Command::Inspect { file, offset, cell } => {
+            let path = file.to_string_lossy().to_string();
+            let data = std::fs::read(&path)?;
+            let offset = offset as usize + if cell { hive::BASE_BLOCK_SIZE } else { 0 };
+            if offset >= data.len() {
+                anyhow::bail!("Offset 0x{:X} is beyond the end of the file ({} bytes)", offset, data.len());
+            }
+            let inspection = inspect::inspect(&data, offset);
+            inspect::print_inspection(&data, &inspection);
+            if let Some(log) = audit_log {
+                let outcome = format!("inspected offset 0x{:X}", offset);
+                log.record_operation("inspect", &[path], None, &outcome)?;
+            }
+        }
+        Command::ExportReg { file, output, key, root_prefix } => {
+            ensure_distinct_output(&file, &output)?;
+            let path = file.to_string_lossy().to_string();
+            let output = output.to_string_lossy().to_string();
+            let export = regfile::export_reg(&hive::Hive::open(&path)?, &key, &root_prefix)?;
+            regfile::write_reg_file(&output, &export.text)?;
+
+            println!("Exported {} key(s) and {} value(s) to {}", export.keys, export.values, output);
+            if !export.skipped.is_empty() {
+                println!("\n{} item(s) could not be read and were left out:", export.skipped.len());
+                for item in &export.skipped {
+                    println!("  {}", item);
+                }
+            }
+            if let Some(log) = audit_log {
+                let outcome = format!("exported {} key(s), {} value(s), skipped {}",
+                    export.keys, export.values, export.skipped.len());
+                log.record_operation("export-reg", &[path], Some(&output), &outcome)?;
+            }
+        }
+        Command::Edit { file, output, action } => {
+            let path = file.to_string_lossy().to_string();
+            let output = output.map(|o| o.to_string_lossy().to_string());
+            let edit = action.into_edit()?;
+
+            println!("{}", edit);
+            let analysis = audited_change(audit_log, "edit", &path, output.as_deref(),
+                || edit::apply_edits(&path, output.as_deref(), std::slice::from_ref(&edit)),
+                |_| format!("applied: {}", edit))?;
+            let target = output.as_deref().unwrap_or(&path);
+            println!("Written to {}", target);
+            println!("Validation of {}:", target);
+            print_issues(&analysis.issues);
+        }
+        Command::SetField { file, field, value, output } => {
+            let path = file.to_string_lossy().to_string();
+            let output = output.map(|o| o.to_string_lossy().to_string());
+            let bytes = field.parse(&value)?;
+            let data = std::fs::read(&path)?;
+            if data.len() < header::HEADER_LEN {
+                anyhow::bail!("{} is too small to hold a base block", path);
+            }
+            let edited = header::edited_header(&data, &[(field, bytes.clone())]);
+
+            println!("{}: {} -> {}", field.label(), field.format(field.bytes(&data)), field.format(&bytes));
+            if let Some(warning) = field.warning(&edited, &data) {
+                println!("Warning: {}", warning);
+            }
+            println!("Checksum: 0x{:08X} -> 0x{:08X}",
+                hive::read_u32(&data, header::CHECKSUM_OFFSET), hive::read_u32(&edited, header::CHECKSUM_OFFSET));
+
+            let analysis = audited_change(audit_log, "set-field", &path, output.as_deref(),
+                || header::set_fields(&path, output.as_deref(), &[(field, bytes)]),
+                |_| format!("set {} to {}", field.name(), value))?;
+            let target = output.as_deref().unwrap_or(&path);
+            println!("Written to {}", target);
+            println!("Validation of {}:", target);
+            print_issues(&analysis.issues);
+        }
+        Command::Import { file, reg, output, root_prefix, dry_run } => {
+            let path = file.to_string_lossy().to_string();
+            let reg_path = reg.to_string_lossy().to_string();
+            let output = output.map(|o| o.to_string_lossy().to_string());
+            let parsed = regfile::parse_reg(&regfile::read_reg_file(&reg_path)?)?;
+            let hive = hive::Hive::open(&path)?;
+            let (edits, skipped) = regfile::map_to_hive(&hive, &parsed, &root_prefix)?;
+
+            let preview = edit::preview_edits(&hive, &edits);
+            println!("{} ({}):", reg_path, if parsed.ansi { "REGEDIT4" } else { "version 5.00" });
+            for (i, entry) in preview.iter().enumerate() {
+                println!("  {:>4}. {}", i + 1, entry);
+            }
+            if !skipped.is_empty() {
+                println!("\n{} key(s) lie outside the hive root and were skipped:", skipped.len());
+                for key in &skipped {
+                    println!("  {}", key);
+                }
+            }
+
+            let changes: Vec<edit::Edit> = preview.iter()
+                .filter(|entry| matches!(entry.effect, edit::Effect::Change(_)))
+                .map(|entry| entry.edit.clone())
+                .collect();
+            let failures = preview.iter().filter(|entry| matches!(entry.effect, edit::Effect::Fails(_))).count();
+            println!("\n{} change(s), {} already in place, {} failing",
+                changes.len(), preview.len() - changes.len() - failures, failures);
+            if dry_run {
+                println!("Dry run: nothing was written");
+                return Ok(());
+            }
+            if failures > 0 {
+                anyhow::bail!("Nothing was written because {} entr(ies) would fail", failures);
+            }
+            if changes.is_empty() {
+                println!("Nothing to write");
+                return Ok(());
+            }
+
+            let analysis = audited_change(audit_log, "import", &path, output.as_deref(),
+                || edit::apply_edits(&path, output.as_deref(), &changes),
+                |_| format!("imported {} change(s) from {}", changes.len(), reg_path))?;
+            let target = output.as_deref().unwrap_or(&path);
+            println!("Written to {}", target);
+            println!("Validation of {}:", target);
+            print_issues(&analysis.issues);
+        }
+        Command::Export { file, output, format, root_prefix } => {
+            ensure_distinct_output(&file, &output)?;
+            let format = format.unwrap_or_else(|| {
+                let extension = output.extension().map(|e| e.to_string_lossy().to_lowercase());
+                match extension.as_deref() {
+                    Some("db" | "sqlite" | "sqlite3") => ExportFormat::Sqlite,
+                    _ => ExportFormat::Jsonl,
+                }
+            });
+            let path = file.to_string_lossy().to_string();
+            let output = output.to_string_lossy().to_string();
+
+            let (analysis, _) = audit::analyze(&path, audit_log)?;
+            let export = export::flatten(&hive::Hive::open(&path)?, &analysis, &root_prefix)?;
+            match format {
+                ExportFormat::Jsonl => export::write_json_lines(&export, &output)?,
+                ExportFormat::Sqlite => export::write_sqlite(&export, &analysis, &output)?,
+            }
+
+            let values: usize = export.keys.iter().map(|k| k.values.len()).sum();
+            println!("Exported {} key(s), {} value(s) and {} issue(s) to {}",
+                export.keys.len(), values, export.issues.len(), output);
+            if !export.errors.is_empty() {
+                println!("\n{} part(s) of the tree could not be read:", export.errors.len());
+                for error in &export.errors {
+                    println!("  {}", error);
+                }
+            }
+            if let Some(log) = audit_log {
+                let outcome = format!("exported {} key(s), {} value(s)", export.keys.len(), values);
+                log.record_operation("export", &[path], Some(&output), &outcome)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_issues(issues: &[types::ValidationIssue]) {
+    if issues.is_empty() {
+        println!("\nNo issues found.");
+    } else {
+        println!("\nIssues found:");
+        for issue in issues {
+            match issue.severity {
+                types::IssueSeverity::Critical => print!("CRITICAL: "),
+                types::IssueSeverity::Warning => print!("WARNING: "),
+            }
+            println!("{} [{}]", issue.message, issue.code);
+            if let Some(location) = &issue.location {
+                println!("  At {}", location);
+            }
+            if let Some(details) = &issue.details {
+                println!("  {}", details);
+            }
+            if issue.fix_type.is_none() {
+                println!("  No automatic fix: {}", issue.code.guidance());
+            }
+        }
+    }
+}
+
+fn repair_cli(file_path: &str, output_path: Option<&str>, analysis: &types::AnalysisResult, audit_log: Option<&AuditLog>) {
+    let fixes = registry::fix_types(analysis);
+    if fixes.is_empty() {
+        println!("\nNothing to fix.");
+        return;
+    }
+
+    let target = output_path.unwrap_or(file_path);
+    let before = audit_log.map(|_| audit::hash_file(file_path));
+    let result = registry::apply_fixes(file_path, output_path, analysis, &fixes);
+    let outcome = match &result {
+        Ok(_) => "All fixes applied successfully.".to_string(),
+        Err(e) => e.to_string(),
+    };
+
+    println!("\n{}", outcome);
+    if let Ok(repaired) = &result {
+        println!("Validation of {}:", target);
+        print_issues(&repaired.issues);
+        println!("\nNext steps:");
+        for (i, step) in guide::next_steps(file_path, repaired, true).iter().enumerate() {
+            println!("  {}. {}", i + 1, step);
+        }
+    }
+
+    if let (Some(log), Some(before)) = (audit_log, before) {
+        let logged = before.and_then(|before| {
+            let hashes = audit::FileHashes { before, after: audit::hash_file(target).ok() };
+            log.record_fix(file_path, output_path, &fixes, &hashes, &outcome)
+        });
+        if let Err(e) = logged {
+            println!("Error: failed to write audit log: {}", e);
+        }
+    }
 }
 
 fn load_icon() -> eframe::IconData {
@@ -37,13 +727,50 @@ fn load_icon() -> eframe::IconData {
     }
 }
 
+/// Release builds use the Windows GUI subsystem, so no console opens when the
+/// program is double-clicked. The command line modes need one: attach to the
+/// console of the shell that started the program, or open a new one. The
+/// terminal UI always gets its own, because an interactive cmd.exe does not
+/// wait for a GUI program and would read the same keystrokes.
+#[cfg(windows)]
+fn attach_console(own: bool) {
+    use winapi::um::consoleapi::AllocConsole;
+    use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
+    // Both calls fail harmlessly when there is a console already, as in debug builds
+    unsafe {
+        if own || AttachConsole(ATTACH_PARENT_PROCESS) == 0 {
+            AllocConsole();
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console(_own: bool) {}
+
 fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
-    
-    if let Some(file_path) = args.file {
+    let args = Args::try_parse().unwrap_or_else(|e| {
+        // --help, --version and usage errors
+        attach_console(false);
+        e.exit()
+    });
+    if args.command.is_some() || args.file.is_some() {
+        attach_console(matches!(args.command, Some(Command::Tui { .. })));
+    }
+
+    registry::set_read_only(args.read_only);
+    let audit_log = args.audit_log
+        .or_else(|| args.read_only.then(|| PathBuf::from(audit::DEFAULT_AUDIT_LOG)))
+        .map(AuditLog::new);
+
+    if let Some(command) = args.command {
+        if let Err(e) = run_command(command, audit_log.as_ref()) {
+            println!("Error: {}", e);
+        }
+        Ok(())
+    } else if let Some(file_path) = args.file {
         // CLI mode
-        match registry::check_registry_file(&file_path.to_string_lossy()) {
-            Ok(result) => {
+        match audit::analyze(&file_path.to_string_lossy(), audit_log.as_ref()) {
+            Ok((result, hashes)) => {
                 println!("File: {}", file_path.display());
                 println!("Size: {} bytes", result.file_info.size);
                 println!("Signature: {}", result.file_info.signature);
@@ -55,22 +782,23 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
                     result.file_info.hive_bins_size, result.file_info.measured_hive_bins_size);
                 println!("Checksum: 0x{:08X} (stored) vs 0x{:08X} (calculated)",
                     result.file_info.stored_checksum, result.file_info.calculated_checksum);
-                
-                if result.issues.is_empty() {
-                    println!("\nNo issues found.");
-                } else {
-                    println!("\nIssues found:");
-                    for issue in result.issues {
-                        match issue.severity {
-                            types::IssueSeverity::Critical => print!("CRITICAL: "),
-                            types::IssueSeverity::Warning => print!("WARNING: "),
-                        }
-                        println!("{}", issue.message);
-                        if let Some(details) = issue.details {
-                            println!("  {}", details);
-                        }
+                if let Some(hashes) = &hashes {
+                    println!("SHA-256 (before analysis): {}", hashes.before);
+                    println!("SHA-256 (after analysis): {}", hashes.after.as_deref().unwrap_or("-"));
+                    if !hashes.unchanged() {
+                        println!("WARNING: the file changed while it was being analyzed");
                     }
                 }
+                if let Some(log) = &audit_log {
+                    println!("Audit Log: {}", log.path().display());
+                }
+                
+                print_issues(&result.issues);
+
+                if args.fix {
+                    let output_path = args.output.map(|p| p.to_string_lossy().to_string());
+                    repair_cli(&file_path.to_string_lossy(), output_path.as_deref(), &result, audit_log.as_ref());
+                }
             }
             Err(e) => {
                 println!("Error: {}", e);
@@ -100,7 +828,7 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
                 style.visuals.window_rounding = Rounding::same(50.0);
                 cc.egui_ctx.set_style(style);
                 
-                Box::new(RegistryFixerApp::new(cc))
+                Box::new(RegistryFixerApp::new(cc, audit_log))
             }),
         ) {
             eprintln!("Error running application: {}", e);
@@ -109,3 +837,38 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rebuild::SalvagedKey;
+
+    #[test]
+    fn diff_refuses_to_overwrite_an_input() {
+        let before = rebuild::build_test_hive(&SalvagedKey::named("ROOT"));
+        let mut root = SalvagedKey::named("ROOT");
+        root.subkeys.push(SalvagedKey::named("Added"));
+        let after = rebuild::build_test_hive(&root);
+        let path = |name: &str| std::env::temp_dir().join(format!("regfix_diff_test_{}_{}", std::process::id(), name));
+        let (before_path, after_path) = (path("before"), path("after"));
+        std::fs::write(&before_path, before.data()).unwrap();
+        std::fs::write(&after_path, after.data()).unwrap();
+
+        let results: Vec<_> = [&before_path, &after_path].into_iter()
+            .map(|output| run_command(Command::Diff {
+                before: before_path.clone(),
+                after: after_path.clone(),
+                json: true,
+                output: Some(output.clone()),
+            }, None))
+            .collect();
+        let unchanged = [std::fs::read(&before_path).unwrap(), std::fs::read(&after_path).unwrap()];
+        let _ = std::fs::remove_file(&before_path);
+        let _ = std::fs::remove_file(&after_path);
+
+        for result in results {
+            assert!(result.unwrap_err().to_string().contains("must differ"));
+        }
+        assert_eq!(unchanged, [before.data().to_vec(), after.data().to_vec()]);
+    }
+}
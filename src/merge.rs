@@ -0,0 +1,295 @@
+use crate::hive::{self, Bin, Hive, BASE_BLOCK_SIZE, HBIN_HEADER_SIZE, PAGE_SIZE};
+use crate::registry;
+use crate::types::*;
+use anyhow::{bail, Result};
+
+/// Where a page of the merged hive came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageOrigin {
+    /// Bins and cells of this copy validated for the page
+    Verified(usize),
+    /// No copy validated; the page was taken from this copy as-is
+    Unverified(usize),
+    /// Nothing usable; replaced by an empty bin
+    Blank,
+}
+
+#[derive(Debug)]
+pub struct MergeReport {
+    pub sources: Vec<String>,
+    /// Copy whose base block was used
+    pub base_block_source: usize,
+    /// Origin of every 4 KB page, indexed from the start of the hive bins data
+    pub pages: Vec<PageOrigin>,
+    /// Bins that failed validation after merging and were taken whole from one copy
+    pub rebased_bins: Vec<u32>,
+    pub validation: Result<AnalysisResult>,
+}
+
+// Bin found in one copy together with how far its cells validate
+#[derive(Debug, Clone, Copy)]
+struct BinInfo {
+    bin: Bin,
+    extent: u32,
+}
+
+// Bin currently being assembled: its layout, the copy supplying the hbin
+// header and how far each copy's cells validate under that layout
+struct OpenBin {
+    bin: Bin,
+    header_source: usize,
+    extents: Vec<Option<u32>>,
+}
+
+/// Offset up to which the cells of a bin form a valid chain of plausible records
+fn validated_extent(hive: &Hive, bin: &Bin) -> u32 {
+    let end = bin.offset + bin.size;
+    let mut offset = bin.offset + HBIN_HEADER_SIZE as u32;
+    while offset < end {
+        let Ok(cell) = hive.cell(offset) else {
+            return offset;
+        };
+        if !cell.size.is_multiple_of(8) || offset + cell.size > end {
+            return offset;
+        }
+        if cell.allocated {
+            let payload = hive.cell_data(offset).unwrap_or(&[]);
+            let record_ok = match payload.get(0..2) {
+                Some(b"nk") => Hive::parse_key(offset, payload).is_ok(),
+                Some(b"vk") => Hive::parse_value(offset, payload).is_ok(),
+                _ => true,
+            };
+            if !record_ok {
+                return offset;
+            }
+        }
+        offset += cell.size;
+    }
+    end
+}
+
+/// Finds every hbin header that sits at the offset it claims, keyed by page,
+/// so damaged copies still align by offset
+fn bin_layout(hive: &Hive) -> Vec<Option<BinInfo>> {
+    let page_count = hive.bins_len() as usize / PAGE_SIZE;
+    let mut bins: Vec<Option<BinInfo>> = vec![None; page_count];
+    let mut page = 0;
+    while page < page_count {
+        let offset = (page * PAGE_SIZE) as u32;
+        match hive.bin_at(offset) {
+            Ok(bin) => {
+                bins[page] = Some(BinInfo { bin, extent: validated_extent(hive, &bin) });
+                page += bin.size as usize / PAGE_SIZE;
+            }
+            Err(_) => page += 1,
+        }
+    }
+    bins
+}
+
+fn empty_bin(offset: u32) -> Vec<u8> {
+    let mut page = vec![0u8; PAGE_SIZE];
+    page[0..4].copy_from_slice(b"hbin");
+    page[4..8].copy_from_slice(&offset.to_le_bytes());
+    page[8..12].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+    let free = (PAGE_SIZE - HBIN_HEADER_SIZE) as i32;
+    page[HBIN_HEADER_SIZE..HBIN_HEADER_SIZE + 4].copy_from_slice(&free.to_le_bytes());
+    page
+}
+
+fn page_bytes(hive: &Hive, page: usize) -> &[u8] {
+    let start = BASE_BLOCK_SIZE + page * PAGE_SIZE;
+    &hive.data()[start..start + PAGE_SIZE]
+}
+
+/// Builds one hive out of several damaged copies of it. Copies are aligned
+/// by hbin offset and every 4 KB page is taken from a copy whose bin header
+/// and cells validate, preferring the newest hbin timestamp.
+pub fn merge_copies(paths: &[String], output_path: &str) -> Result<MergeReport> {
+    if paths.len() < 2 {
+        bail!("At least two copies are needed for a merge");
+    }
+    let hives = paths.iter().map(|p| Hive::open(p)).collect::<Result<Vec<_>>>()?;
+    let layouts: Vec<Vec<Option<BinInfo>>> = hives.iter().map(bin_layout).collect();
+    let page_count = layouts.iter()
+        .flat_map(|layout| layout.iter().flatten())
+        .map(|info| (info.bin.offset + info.bin.size) as usize / PAGE_SIZE)
+        .max()
+        .unwrap_or(0);
+    if page_count == 0 {
+        bail!("None of the copies contains a recognisable hive bin");
+    }
+
+    // Newest hbin timestamp first, then the newest base block, then argument order
+    let rank = |copy: usize, timestamp: u64| (timestamp, hives[copy].last_written(), std::cmp::Reverse(copy));
+
+    // How far a copy's cells validate when its bytes are read with the given
+    // layout; copies whose own hbin header is damaged adopt the layout found
+    // in the other copies
+    let extent_under = |copy: usize, bin: &Bin| -> Option<u32> {
+        let first_page = bin.offset as usize / PAGE_SIZE;
+        match layouts[copy].get(first_page).copied().flatten() {
+            Some(info) if info.bin.size == bin.size => Some(info.extent),
+            Some(_) => None,
+            None if (bin.offset + bin.size) <= hives[copy].bins_len() => Some(validated_extent(&hives[copy], bin)),
+            None => None,
+        }
+    };
+    let own_timestamp = |copy: usize, bin: &Bin| -> u64 {
+        layouts[copy].get(bin.offset as usize / PAGE_SIZE).copied().flatten()
+            .filter(|info| info.bin.size == bin.size)
+            .map(|info| info.bin.timestamp)
+            .unwrap_or(0)
+    };
+
+    let mut pages: Vec<PageOrigin> = Vec::with_capacity(page_count);
+    let mut merged: Vec<u8> = Vec::with_capacity(page_count * PAGE_SIZE);
+    let mut open_bin: Option<OpenBin> = None;
+
+    for page in 0..page_count {
+        let offset = (page * PAGE_SIZE) as u32;
+        if open_bin.as_ref().map(|open| offset >= open.bin.offset + open.bin.size).unwrap_or(false) {
+            open_bin = None;
+        }
+
+        if open_bin.is_none() {
+            // A new bin starts here: take the header whose cells validate furthest
+            let header = layouts.iter()
+                .enumerate()
+                .filter_map(|(copy, layout)| layout.get(page).copied().flatten().map(|info| (copy, info)))
+                .max_by_key(|(copy, info)| (info.extent, rank(*copy, info.bin.timestamp)));
+            let Some((header_source, info)) = header else {
+                merged.extend_from_slice(&empty_bin(offset));
+                pages.push(PageOrigin::Blank);
+                continue;
+            };
+            open_bin = Some(OpenBin {
+                bin: info.bin,
+                header_source,
+                extents: (0..hives.len()).map(|copy| extent_under(copy, &info.bin)).collect(),
+            });
+        }
+        let open = open_bin.as_ref().unwrap();
+
+        let page_end = (offset + PAGE_SIZE as u32).min(open.bin.offset + open.bin.size);
+        let verified = open.extents.iter()
+            .enumerate()
+            .filter(|(_, extent)| extent.map(|e| e >= page_end).unwrap_or(false))
+            .map(|(copy, _)| copy)
+            .max_by_key(|&copy| rank(copy, own_timestamp(copy, &open.bin)));
+        let (copy, origin) = match verified {
+            Some(copy) => (copy, PageOrigin::Verified(copy)),
+            None => (open.header_source, PageOrigin::Unverified(open.header_source)),
+        };
+
+        let start = merged.len();
+        merged.extend_from_slice(page_bytes(&hives[copy], page));
+        if offset == open.bin.offset {
+            let header = page_bytes(&hives[open.header_source], page);
+            merged[start..start + HBIN_HEADER_SIZE].copy_from_slice(&header[..HBIN_HEADER_SIZE]);
+        }
+        pages.push(origin);
+    }
+
+    // Pages taken from different copies can leave a bin with a broken cell
+    // chain; fall back to the best copy of the whole bin in that case
+    let mut rebased_bins = Vec::new();
+    let mut data = hives[0].data()[..BASE_BLOCK_SIZE].to_vec();
+    data.extend_from_slice(&merged);
+    let assembled = Hive::from_bytes(data)?;
+    let mut offset = 0u32;
+    while (offset as usize) < merged.len() {
+        let Ok(bin) = assembled.bin_at(offset) else {
+            offset += PAGE_SIZE as u32;
+            continue;
+        };
+        let bin_end = bin.offset + bin.size;
+        if validated_extent(&assembled, &bin) < bin_end {
+            let whole = (0..hives.len())
+                .filter(|&copy| extent_under(copy, &bin) == Some(bin_end))
+                .max_by_key(|&copy| rank(copy, own_timestamp(copy, &bin)));
+            if let Some(copy) = whole {
+                // Keep the merged hbin header, replace the cells
+                let range = (offset as usize + HBIN_HEADER_SIZE)..bin_end as usize;
+                let source = BASE_BLOCK_SIZE + range.start..BASE_BLOCK_SIZE + range.end;
+                merged[range].copy_from_slice(&hives[copy].data()[source]);
+                for page in &mut pages[offset as usize / PAGE_SIZE..bin_end as usize / PAGE_SIZE] {
+                    *page = PageOrigin::Verified(copy);
+                }
+                rebased_bins.push(offset);
+            }
+        }
+        offset = bin_end;
+    }
+
+    // Base block from the copy whose header is most trustworthy
+    let base_block_source = (0..hives.len())
+        .max_by_key(|&copy| {
+            let data = hives[copy].data();
+            let checksum_ok = registry::calculate_header_checksum(data) == hive::read_u32(data, 508);
+            let seq_ok = hives[copy].primary_seq_num() == hive::read_u32(data, 8);
+            (&data[0..4] == b"regf", checksum_ok && seq_ok, hives[copy].last_written(), std::cmp::Reverse(copy))
+        })
+        .unwrap_or(0);
+    let base = &hives[base_block_source];
+
+    let mut output = base.data()[..BASE_BLOCK_SIZE].to_vec();
+    output.extend_from_slice(&merged);
+    registry::create_output_file(output_path, &output)?;
+
+    let sequence = base.primary_seq_num();
+    registry::update_hive_bins_size(output_path, merged.len() as u32)?;
+    registry::update_sequence_numbers(output_path, sequence, sequence)?;
+    registry::refresh_checksum(output_path)?;
+
+    Ok(MergeReport {
+        sources: paths.to_vec(),
+        base_block_source,
+        pages,
+        rebased_bins,
+        validation: registry::check_registry_file(output_path),
+    })
+}
+
+pub fn print_report(report: &MergeReport) {
+    println!("Base block taken from: {}", report.sources[report.base_block_source]);
+    println!("\nPage sources:");
+
+    // Collapse consecutive pages with the same origin into ranges
+    let mut start = 0;
+    for page in 1..=report.pages.len() {
+        if page < report.pages.len() && report.pages[page] == report.pages[start] {
+            continue;
+        }
+        let first = start * PAGE_SIZE;
+        let last = page * PAGE_SIZE - 1;
+        let origin = match report.pages[start] {
+            PageOrigin::Verified(copy) => report.sources[copy].clone(),
+            PageOrigin::Unverified(copy) => format!("{} (UNVERIFIED)", report.sources[copy]),
+            PageOrigin::Blank => "empty bin (no usable copy)".to_string(),
+        };
+        println!("  0x{:08X}-0x{:08X}  {}", first, last, origin);
+        start = page;
+    }
+
+    for offset in &report.rebased_bins {
+        println!("\nWARNING: bin at 0x{:X} did not validate after merging pages; taken whole from one copy", offset);
+    }
+
+    for (copy, source) in report.sources.iter().enumerate() {
+        let count = report.pages.iter()
+            .filter(|p| matches!(p, PageOrigin::Verified(c) | PageOrigin::Unverified(c) if *c == copy))
+            .count();
+        println!("{} page(s) from {}", count, source);
+    }
+    let blank = report.pages.iter().filter(|p| **p == PageOrigin::Blank).count();
+    if blank > 0 {
+        println!("{} page(s) could not be recovered from any copy", blank);
+    }
+
+    match &report.validation {
+        Ok(result) if result.issues.is_empty() => println!("\nMerged hive validated with no issues."),
+        Ok(result) => println!("\nMerged hive validated with {} issue(s).", result.issues.len()),
+        Err(e) => println!("\nMerged hive failed validation: {}", e),
+    }
+}
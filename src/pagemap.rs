@@ -0,0 +1,212 @@
+use crate::hive::{Bin, Hive, BASE_BLOCK_SIZE, HBIN_HEADER_SIZE, PAGE_SIZE};
+use crate::types::ValidationIssue;
+
+/// Bins with more free cell space than this share are shown as mostly free
+const MOSTLY_FREE_RATIO: f32 = 0.75;
+/// Pages outside any bin with at least this many bits of entropy per byte
+/// look compressed, encrypted or random rather than like hive data
+const HIGH_ENTROPY_BITS: f32 = 7.0;
+
+/// How a 4 KB page of the hive bins looks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageStatus {
+    /// Part of a bin whose header and cells check out
+    ValidBin,
+    /// Part of a valid bin that is mostly free cells
+    MostlyFree,
+    /// Part of a bin with a valid header whose cell chain breaks
+    DamagedCells,
+    /// Starts with an hbin signature but the header does not hold up
+    BadBinHeader,
+    /// Nothing but zero bytes
+    Zero,
+    /// Random-looking data outside any bin
+    HighEntropy,
+    /// Data outside any bin that fits none of the above
+    Unrecognized,
+}
+
+impl PageStatus {
+    pub const ALL: [PageStatus; 7] = [
+        PageStatus::ValidBin,
+        PageStatus::MostlyFree,
+        PageStatus::DamagedCells,
+        PageStatus::BadBinHeader,
+        PageStatus::Zero,
+        PageStatus::HighEntropy,
+        PageStatus::Unrecognized,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PageStatus::ValidBin => "Valid bin",
+            PageStatus::MostlyFree => "Mostly free",
+            PageStatus::DamagedCells => "Damaged cells",
+            PageStatus::BadBinHeader => "Bad bin header",
+            PageStatus::Zero => "All zero",
+            PageStatus::HighEntropy => "High entropy",
+            PageStatus::Unrecognized => "Unrecognized",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PageInfo {
+    pub status: PageStatus,
+    /// The bin covering the page
+    pub bin: Option<Bin>,
+    /// Bytes of allocated and free cells within the page
+    pub allocated: u32,
+    pub free: u32,
+    /// Shannon entropy in bits per byte
+    pub entropy: f32,
+    /// Issues located in the page's bin, or in the page itself outside any
+    /// bin, as indices into the analysis issues
+    pub issues: Vec<usize>,
+}
+
+impl PageInfo {
+    /// Share of the page's cell space that is allocated
+    pub fn density(&self) -> Option<f32> {
+        let total = self.allocated + self.free;
+        (total > 0).then(|| self.allocated as f32 / total as f32)
+    }
+}
+
+/// Every 4 KB page of the hive bins, in file order
+#[derive(Debug, Clone, Default)]
+pub struct PageMap {
+    pub pages: Vec<PageInfo>,
+}
+
+impl PageMap {
+    /// File offset of a page
+    pub fn file_offset(page: usize) -> usize {
+        BASE_BLOCK_SIZE + page * PAGE_SIZE
+    }
+
+    pub fn count(&self, status: PageStatus) -> usize {
+        self.pages.iter().filter(|p| p.status == status).count()
+    }
+}
+
+fn entropy(bytes: &[u8]) -> f32 {
+    let mut counts = [0u32; 256];
+    for &b in bytes {
+        counts[b as usize] += 1;
+    }
+    let len = bytes.len() as f32;
+    counts.iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f32 / len;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+// Adds the bytes of a cell to the pages it spans
+fn add_cell(pages: &mut [PageInfo], offset: u32, size: u32, allocated: bool) {
+    let (start, end) = (offset as usize, (offset + size) as usize);
+    let mut page = start / PAGE_SIZE;
+    while page * PAGE_SIZE < end && page < pages.len() {
+        let overlap = (end.min((page + 1) * PAGE_SIZE) - start.max(page * PAGE_SIZE)) as u32;
+        if allocated {
+            pages[page].allocated += overlap;
+        } else {
+            pages[page].free += overlap;
+        }
+        page += 1;
+    }
+}
+
+// Walks the cells of a bin, returning the offset where the chain breaks
+fn walk_cells(hive: &Hive, bin: &Bin, pages: &mut [PageInfo]) -> Option<u32> {
+    let end = bin.offset + bin.size;
+    let mut offset = bin.offset + HBIN_HEADER_SIZE as u32;
+    while offset < end {
+        match hive.cell(offset) {
+            Ok(cell) if cell.size.is_multiple_of(8) && offset + cell.size <= end => {
+                add_cell(pages, offset, cell.size, cell.allocated);
+                offset += cell.size;
+            }
+            _ => return Some(offset),
+        }
+    }
+    None
+}
+
+/// Classifies every page of the hive bins and ties the located issues to them
+pub fn build(hive: &Hive, issues: &[ValidationIssue]) -> PageMap {
+    let data = &hive.data()[BASE_BLOCK_SIZE..];
+    let page_count = data.len() / PAGE_SIZE;
+    let mut pages: Vec<PageInfo> = (0..page_count)
+        .map(|page| {
+            let bytes = &data[page * PAGE_SIZE..(page + 1) * PAGE_SIZE];
+            PageInfo {
+                status: PageStatus::Unrecognized,
+                bin: None,
+                allocated: 0,
+                free: 0,
+                entropy: entropy(bytes),
+                issues: Vec::new(),
+            }
+        })
+        .collect();
+
+    for (index, issue) in issues.iter().enumerate() {
+        let Some(location) = &issue.location else {
+            continue;
+        };
+        if location.end() as usize <= BASE_BLOCK_SIZE {
+            continue;
+        }
+        let first = (location.offset as usize).saturating_sub(BASE_BLOCK_SIZE) / PAGE_SIZE;
+        let last = ((location.end() as usize - BASE_BLOCK_SIZE - 1) / PAGE_SIZE).max(first);
+        for page in pages.iter_mut().take(last + 1).skip(first) {
+            page.issues.push(index);
+        }
+    }
+
+    let mut page = 0;
+    while page < page_count {
+        let offset = (page * PAGE_SIZE) as u32;
+        match hive.bin_at(offset) {
+            Ok(bin) => {
+                let span = page..(page + bin.size as usize / PAGE_SIZE).min(page_count);
+                let broken = walk_cells(hive, &bin, &mut pages);
+                let mut bin_issues: Vec<usize> = pages[span.clone()].iter().flat_map(|p| p.issues.clone()).collect();
+                bin_issues.sort_unstable();
+                bin_issues.dedup();
+                let (allocated, free) = pages[span.clone()].iter().fold((0, 0), |(a, f), p| (a + p.allocated, f + p.free));
+                let mostly_free = free as f32 > (allocated + free) as f32 * MOSTLY_FREE_RATIO;
+                for index in span.clone() {
+                    let info = &mut pages[index];
+                    info.bin = Some(bin);
+                    info.issues = bin_issues.clone();
+                    info.status = match broken {
+                        Some(at) if (at as usize) < (index + 1) * PAGE_SIZE => PageStatus::DamagedCells,
+                        _ if mostly_free => PageStatus::MostlyFree,
+                        _ => PageStatus::ValidBin,
+                    };
+                }
+                page = span.end.max(page + 1);
+            }
+            Err(_) => {
+                let bytes = &data[page * PAGE_SIZE..(page + 1) * PAGE_SIZE];
+                let info = &mut pages[page];
+                info.status = if bytes.starts_with(b"hbin") {
+                    PageStatus::BadBinHeader
+                } else if bytes.iter().all(|&b| b == 0) {
+                    PageStatus::Zero
+                } else if info.entropy >= HIGH_ENTROPY_BITS {
+                    PageStatus::HighEntropy
+                } else {
+                    PageStatus::Unrecognized
+                };
+                page += 1;
+            }
+        }
+    }
+    PageMap { pages }
+}
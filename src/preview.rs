@@ -0,0 +1,95 @@
+use crate::header::{self, HEADER_LEN};
+use crate::hive::Hive;
+use crate::progress::Monitor;
+use crate::registry;
+use crate::types::{AnalysisResult, FixType};
+use anyhow::Result;
+
+/// Changed bytes closer together than this are shown as one range
+const MERGE_GAP: usize = 8;
+
+/// A run of bytes rewritten by a fix. `before` is empty for bytes appended
+/// to the file and `after` is empty for bytes cut from its end.
+#[derive(Debug, Clone)]
+pub struct ByteChange {
+    pub offset: usize,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+/// The bytes the fix for `analysis.issues[issue]` would change in `hive`,
+/// the file as analysed. Header fixes are worked out on the base block
+/// alone; orphan recovery runs on a copy of the hive in memory and can be
+/// cancelled through `monitor`. Follow-on updates, such as the header
+/// checksum after a header field changes, are included.
+pub fn preview_fix(hive: &Hive, analysis: &AnalysisResult, issue: usize, monitor: &Monitor) -> Result<Vec<ByteChange>> {
+    let selected = registry::select_issues(analysis, &[issue]);
+    let fixes = registry::fix_types(&selected);
+    let before = hive.data();
+    match fixes.first() {
+        None => Ok(Vec::new()),
+        Some(FixType::RecoverOrphans) => {
+            let mut recovered = hive.clone();
+            if registry::recover_orphans_in(&mut recovered, &registry::orphan_cells(&selected), monitor)?.is_empty() {
+                return Ok(Vec::new());
+            }
+            let header = registry::committed_header(&recovered);
+            let mut after = recovered.into_data();
+            after[..HEADER_LEN].copy_from_slice(&header);
+            Ok(changed_ranges(before, &after))
+        }
+        Some(_) => {
+            let after = header::edited_header(before, &registry::header_changes(&selected, &fixes));
+            Ok(changed_ranges(&before[..HEADER_LEN], &after))
+        }
+    }
+}
+
+/// Runs of differing bytes between two versions of a file
+pub fn changed_ranges(before: &[u8], after: &[u8]) -> Vec<ByteChange> {
+    let common = before.len().min(after.len());
+    let mut changes = Vec::new();
+    let mut i = 0;
+    while i < common {
+        if before[i] == after[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut end = i + 1;
+        let mut j = end;
+        while j < common && j - end < MERGE_GAP {
+            if before[j] != after[j] {
+                end = j + 1;
+            }
+            j += 1;
+        }
+        changes.push(ByteChange {
+            offset: start,
+            before: before[start..end].to_vec(),
+            after: after[start..end].to_vec(),
+        });
+        i = end;
+    }
+    if before.len() != after.len() {
+        changes.push(ByteChange {
+            offset: common,
+            before: before[common..].to_vec(),
+            after: after[common..].to_vec(),
+        });
+    }
+    changes
+}
+
+/// Bytes as hex, cut short after `limit` bytes
+pub fn hex_bytes(bytes: &[u8], limit: usize) -> String {
+    if bytes.is_empty() {
+        return "(none)".to_string();
+    }
+    let hex: Vec<String> = bytes.iter().take(limit).map(|b| format!("{:02X}", b)).collect();
+    if bytes.len() > limit {
+        format!("{} … ({} bytes)", hex.join(" "), bytes.len())
+    } else {
+        hex.join(" ")
+    }
+}
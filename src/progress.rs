@@ -0,0 +1,134 @@
+use anyhow::Result;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Keys read between two progress reports while walking the key tree
+pub const REPORT_EVERY_KEYS: usize = 4096;
+/// Bytes scanned between two progress reports while walking the bins
+pub const REPORT_EVERY_BYTES: u64 = 1 << 20;
+
+/// Step of an analysis or repair
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Header,
+    Bins,
+    KeyTree,
+    Orphans,
+    Repair,
+}
+
+impl Phase {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Phase::Header => "Checking the base block",
+            Phase::Bins => "Walking the hive bins",
+            Phase::KeyTree => "Reading the key tree",
+            Phase::Orphans => "Looking for orphaned cells",
+            Phase::Repair => "Applying fixes",
+        }
+    }
+}
+
+/// Where a running analysis or repair has got to
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub phase: Phase,
+    /// Bytes processed, or keys read while walking the key tree
+    pub done: u64,
+    /// Bytes to process; zero when not known up front
+    pub total: u64,
+    pub issues: usize,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> Option<f32> {
+        (self.total > 0).then(|| (self.done as f32 / self.total as f32).min(1.0))
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.phase {
+            Phase::KeyTree => write!(f, "{}: {} keys", self.phase.label(), self.done)?,
+            _ if self.total > 0 => write!(f, "{}: {} of {} bytes", self.phase.label(), self.done, self.total)?,
+            _ => write!(f, "{}", self.phase.label())?,
+        }
+        write!(f, ", {} issue(s) so far", self.issues)
+    }
+}
+
+/// Error returned by an operation stopped through its `Monitor`
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Whether `error`, possibly wrapped in context, comes from a cancellation
+pub fn is_cancelled(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Cancelled>().is_some()
+}
+
+type Reporter = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Passed to long-running operations so they can report progress and be
+/// cancelled. Operations only stop at points where nothing is half written.
+/// The default monitor reports nowhere and is never cancelled.
+#[derive(Clone, Default)]
+pub struct Monitor {
+    cancelled: Arc<AtomicBool>,
+    issues: Arc<AtomicUsize>,
+    reporter: Option<Reporter>,
+}
+
+impl fmt::Debug for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Monitor").field("cancelled", &self.is_cancelled()).finish()
+    }
+}
+
+impl Monitor {
+    pub fn new(report: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        Self { reporter: Some(Arc::new(report)), ..Default::default() }
+    }
+
+    /// Whether both are handles on the same operation
+    pub fn same(&self, other: &Monitor) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fails with `Cancelled` once `cancel` has been called
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+
+    /// Sets the number of issues found so far, shown with the next report
+    pub fn found(&self, issues: usize) {
+        self.issues.store(issues, Ordering::Relaxed);
+    }
+
+    pub fn report(&self, phase: Phase, done: u64, total: u64) {
+        if let Some(report) = &self.reporter {
+            if !self.is_cancelled() {
+                report(Progress { phase, done, total, issues: self.issues.load(Ordering::Relaxed) });
+            }
+        }
+    }
+}
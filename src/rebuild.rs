@@ -0,0 +1,669 @@
+use crate::hive::{self, Hive, KeyNode, BASE_BLOCK_SIZE, BIG_DATA_SEGMENT_SIZE, DATA_INLINE_FLAG, HBIN_HEADER_SIZE, NO_CELL, PAGE_SIZE};
+use crate::registry::{self, Orphans, RECOVERED_KEY_NAME};
+use crate::types::*;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::fs;
+
+// Key flags carried over from the damaged hive; the rest are recomputed
+const KEPT_KEY_FLAGS: u16 = hive::KEY_NO_DELETE | hive::KEY_SYM_LINK | hive::KEY_PREDEF_HANDLE;
+const DEFAULT_MINOR_VERSION: u32 = 5;
+const BIG_DATA_MINOR_VERSION: u32 = 4;
+
+/// A value read from the damaged hive
+#[derive(Debug, Clone)]
+pub struct SalvagedValue {
+    pub name: String,
+    pub data_type: u32,
+    pub data: Vec<u8>,
+}
+
+/// A key read from the damaged hive, with everything below it
+#[derive(Debug, Clone)]
+pub struct SalvagedKey {
+    pub name: String,
+    pub flags: u16,
+    pub last_written: u64,
+    pub class: Option<Vec<u8>>,
+    /// Index into the collected security descriptors
+    pub security: usize,
+    pub values: Vec<SalvagedValue>,
+    pub subkeys: Vec<SalvagedKey>,
+}
+
+/// A subtree read out of a hive together with the security descriptors its
+/// keys refer to and what could not be read
+#[derive(Debug)]
+pub struct Salvaged {
+    pub key: SalvagedKey,
+    pub descriptors: Vec<Vec<u8>>,
+    pub dropped: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct RebuildReport {
+    pub keys: usize,
+    pub values: usize,
+    pub security_descriptors: usize,
+    /// Orphaned keys and values attached below `RegFix_Recovered`
+    pub recovered: usize,
+    /// Everything that could not be carried over, and why
+    pub dropped: Vec<String>,
+    pub original_size: u64,
+    pub rebuilt_size: u64,
+    pub validation: Result<AnalysisResult>,
+}
+
+/// Self-relative descriptor giving SYSTEM and Administrators full control,
+/// used only when no descriptor in the hive can be read
+fn default_security_descriptor() -> Vec<u8> {
+    const SYSTEM: [u8; 12] = [1, 1, 0, 0, 0, 0, 0, 5, 18, 0, 0, 0];
+    const ADMINISTRATORS: [u8; 16] = [1, 2, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0, 32, 2, 0, 0];
+    const KEY_ALL_ACCESS: u32 = 0x000F_003F;
+    const CONTAINER_INHERIT_ACE: u8 = 0x02;
+
+    let mut acl = Vec::new();
+    for sid in [&SYSTEM[..], &ADMINISTRATORS[..]] {
+        acl.extend_from_slice(&[0, CONTAINER_INHERIT_ACE]);
+        acl.extend_from_slice(&(8 + sid.len() as u16).to_le_bytes());
+        acl.extend_from_slice(&KEY_ALL_ACCESS.to_le_bytes());
+        acl.extend_from_slice(sid);
+    }
+    let mut dacl = vec![2, 0];
+    dacl.extend_from_slice(&(8 + acl.len() as u16).to_le_bytes());
+    dacl.extend_from_slice(&2u16.to_le_bytes());
+    dacl.extend_from_slice(&[0, 0]);
+    dacl.extend_from_slice(&acl);
+
+    // Header, owner and group, then the DACL
+    let owner = 20u32;
+    let group = owner + ADMINISTRATORS.len() as u32;
+    let dacl_offset = group + SYSTEM.len() as u32;
+    let mut descriptor = vec![1, 0];
+    descriptor.extend_from_slice(&0x8004u16.to_le_bytes()); // self-relative, DACL present
+    for offset in [owner, group, 0, dacl_offset] {
+        descriptor.extend_from_slice(&offset.to_le_bytes());
+    }
+    descriptor.extend_from_slice(&ADMINISTRATORS);
+    descriptor.extend_from_slice(&SYSTEM);
+    descriptor.extend_from_slice(&dacl);
+    descriptor
+}
+
+/// Descriptor stored in a security cell, if the cell holds a plausible one
+fn read_descriptor(hive: &Hive, cell: u32) -> Option<Vec<u8>> {
+    let data = hive.cell_payload(cell).ok()?;
+    if data.get(0..2) != Some(b"sk") || data.len() < 20 {
+        return None;
+    }
+    let size = hive::read_u32(data, 16) as usize;
+    // A self-relative descriptor has a 20-byte header
+    if size < 20 || 20 + size > data.len() {
+        return None;
+    }
+    Some(data[20..20 + size].to_vec())
+}
+
+// Reads keys, values and descriptors out of a damaged hive, noting what is lost
+struct Salvager<'a> {
+    hive: &'a Hive,
+    orphans: Option<&'a Orphans>,
+    descriptors: Vec<Vec<u8>>,
+    descriptor_index: HashMap<Vec<u8>, usize>,
+    seen: HashSet<u32>,
+    unreadable_security: HashSet<u32>,
+    dropped: Vec<String>,
+}
+
+impl<'a> Salvager<'a> {
+    fn new(hive: &'a Hive, orphans: Option<&'a Orphans>) -> Self {
+        Self {
+            hive,
+            orphans,
+            descriptors: Vec::new(),
+            descriptor_index: HashMap::new(),
+            seen: HashSet::new(),
+            unreadable_security: HashSet::new(),
+            dropped: Vec::new(),
+        }
+    }
+
+    fn add_descriptor(&mut self, descriptor: Vec<u8>) -> usize {
+        if let Some(&index) = self.descriptor_index.get(&descriptor) {
+            return index;
+        }
+        self.descriptors.push(descriptor.clone());
+        self.descriptor_index.insert(descriptor, self.descriptors.len() - 1);
+        self.descriptors.len() - 1
+    }
+
+    fn security(&mut self, cell: u32) -> Option<usize> {
+        if cell == NO_CELL {
+            return None;
+        }
+        read_descriptor(self.hive, cell).map(|d| self.add_descriptor(d))
+    }
+
+    /// Descriptor for a root key whose own is unreadable: the first valid
+    /// security cell anywhere in the hive, or a built-in default
+    fn fallback_security(&mut self) -> usize {
+        let found = self.hive.walk_bins().bins.iter()
+            .filter_map(|bin| self.hive.cells_in_bin(bin).ok())
+            .flatten()
+            .filter(|cell| cell.allocated)
+            .find_map(|cell| read_descriptor(self.hive, cell.offset));
+        self.add_descriptor(found.unwrap_or_else(default_security_descriptor))
+    }
+
+    fn class(&mut self, key: &KeyNode, path: &str) -> Option<Vec<u8>> {
+        if key.class_name == NO_CELL || key.class_length == 0 {
+            return None;
+        }
+        match self.hive.cell_data(key.class_name) {
+            Ok(data) if data.len() >= key.class_length as usize => Some(data[..key.class_length as usize].to_vec()),
+            Ok(_) => {
+                self.dropped.push(format!("{}: class name is longer than its cell", path));
+                None
+            }
+            Err(e) => {
+                self.dropped.push(format!("{}: class name: {}", path, e));
+                None
+            }
+        }
+    }
+
+    fn values(&mut self, key: &KeyNode, path: &str) -> Vec<SalvagedValue> {
+        let mut values: Vec<SalvagedValue> = Vec::new();
+        if key.value_count == 0 || key.value_list == NO_CELL {
+            return values;
+        }
+        let offsets = match self.hive.value_offsets(key) {
+            Ok(offsets) => offsets,
+            Err(e) => {
+                self.dropped.push(format!("{}: value list: {}", path, e));
+                return values;
+            }
+        };
+        for offset in offsets {
+            let value = match self.hive.value(offset) {
+                Ok(value) => value,
+                Err(e) => {
+                    self.dropped.push(format!("{}: value at 0x{:X}: {}", path, offset, e));
+                    continue;
+                }
+            };
+            let label = format!("{}\\{}", path, if value.name.is_empty() { "(Default)" } else { &value.name });
+            if values.iter().any(|v| hive::names_equal(&v.name, &value.name)) {
+                self.dropped.push(format!("{}: duplicate value name", label));
+                continue;
+            }
+            match self.hive.value_data(&value) {
+                Ok(data) => values.push(SalvagedValue { name: value.name, data_type: value.data_type, data }),
+                Err(e) => self.dropped.push(format!("{}: {}", label, e)),
+            }
+        }
+        values
+    }
+
+    /// Reads the key at `offset` and its subtree. Orphaned keys take their
+    /// subkeys from parent pointers, as orphan recovery does.
+    fn key(&mut self, offset: u32, parent_path: &str, parent_security: Option<usize>, orphan: bool, depth: usize) -> Option<SalvagedKey> {
+        if depth > hive::MAX_DEPTH {
+            self.dropped.push(format!("{}: key tree is deeper than {} levels", parent_path, hive::MAX_DEPTH));
+            return None;
+        }
+        if !self.seen.insert(offset) {
+            self.dropped.push(format!("{}: key at 0x{:X} is referenced more than once", parent_path, offset));
+            return None;
+        }
+        let key = match self.hive.key(offset) {
+            Ok(key) => key,
+            Err(e) => {
+                self.dropped.push(format!("{}: subkey at 0x{:X}: {}", parent_path, offset, e));
+                return None;
+            }
+        };
+        let path = if offset == self.hive.root_cell_offset() { String::new() } else { hive::join_path(parent_path, &key.name) };
+        let label = if path.is_empty() { "(root)".to_string() } else { path.clone() };
+
+        let security = match self.security(key.security) {
+            Some(index) => index,
+            None => {
+                if self.unreadable_security.insert(key.security) {
+                    let replacement = if parent_security.is_some() { "their parent's descriptor" } else { "a replacement" };
+                    self.dropped.push(format!(
+                        "{}: security cell 0x{:X} is unreadable; keys using it get {}",
+                        label, key.security, replacement
+                    ));
+                }
+                match parent_security {
+                    Some(inherited) => inherited,
+                    None => self.fallback_security(),
+                }
+            }
+        };
+        let class = self.class(&key, &label);
+        let values = self.values(&key, &label);
+
+        let children: Vec<u32> = if orphan {
+            self.orphans.map(|o| o.children(offset).iter().map(|k| k.offset).collect()).unwrap_or_default()
+        } else if key.subkey_count == 0 || key.subkey_list == NO_CELL {
+            Vec::new()
+        } else {
+            match self.hive.subkey_offsets(key.subkey_list) {
+                Ok(offsets) => offsets,
+                Err(e) => {
+                    self.dropped.push(format!("{}: subkey list: {}", label, e));
+                    Vec::new()
+                }
+            }
+        };
+        let mut subkeys: Vec<SalvagedKey> = Vec::new();
+        for child in children {
+            let Some(subkey) = self.key(child, &path, Some(security), orphan, depth + 1) else {
+                continue;
+            };
+            if subkeys.iter().any(|k| hive::names_equal(&k.name, &subkey.name)) {
+                self.dropped.push(format!("{}: duplicate key name", hive::join_path(&path, &subkey.name)));
+                continue;
+            }
+            subkeys.push(subkey);
+        }
+
+        Some(SalvagedKey {
+            name: key.name,
+            flags: key.flags & KEPT_KEY_FLAGS,
+            last_written: key.last_written,
+            class,
+            security,
+            values,
+            subkeys,
+        })
+    }
+
+    /// Attaches the hive's orphans below `RegFix_Recovered`, returning how many
+    /// top-level items were attached
+    fn recover(&mut self, root: &mut SalvagedKey) -> usize {
+        let Some(orphans) = self.orphans else {
+            return 0;
+        };
+        let plan = registry::plan_recovery(self.hive, orphans);
+        if plan.is_empty() {
+            return 0;
+        }
+        let index = match root.subkeys.iter().position(|k| hive::names_equal(&k.name, RECOVERED_KEY_NAME)) {
+            Some(index) => index,
+            None => {
+                root.subkeys.push(SalvagedKey {
+                    name: RECOVERED_KEY_NAME.to_string(),
+                    flags: 0,
+                    last_written: hive::filetime_now(),
+                    class: None,
+                    security: root.security,
+                    values: Vec::new(),
+                    subkeys: Vec::new(),
+                });
+                root.subkeys.len() - 1
+            }
+        };
+
+        let mut attached = 0;
+        let mut recovered = std::mem::take(&mut root.subkeys[index].subkeys);
+        let mut values = std::mem::take(&mut root.subkeys[index].values);
+        for item in plan {
+            if item.is_key {
+                if let Some(mut key) = self.key(item.cell, RECOVERED_KEY_NAME, Some(root.security), true, 1) {
+                    key.name = item.name;
+                    recovered.push(key);
+                    attached += 1;
+                }
+            } else {
+                let label = format!("{}\\{}", RECOVERED_KEY_NAME, item.name);
+                match self.hive.value(item.cell).and_then(|v| Ok((self.hive.value_data(&v)?, v))) {
+                    Ok((data, value)) => {
+                        values.push(SalvagedValue { name: item.name, data_type: value.data_type, data });
+                        attached += 1;
+                    }
+                    Err(e) => self.dropped.push(format!("{}: {}", label, e)),
+                }
+            }
+        }
+        root.subkeys[index].subkeys = recovered;
+        root.subkeys[index].values = values;
+        attached
+    }
+}
+
+/// Reads the key at `path` and everything below it as far as it can be read
+pub fn salvage_subtree(hive: &Hive, path: &str) -> Result<Salvaged> {
+    let key = hive.find_key(path)?;
+    let parent = path.rsplit_once('\\').map(|(parent, _)| parent).unwrap_or("");
+    let mut salvager = Salvager::new(hive, None);
+    let key = salvager.key(key.offset, parent, None, false, 0)
+        .ok_or_else(|| anyhow::anyhow!("Key '{}' is unreadable", path))?;
+    Ok(Salvaged { key, descriptors: salvager.descriptors, dropped: salvager.dropped })
+}
+
+/// Lays cells out one after another in freshly made bins
+pub struct HiveWriter {
+    bins: Vec<u8>,
+    bin_end: usize,
+    minor_version: u32,
+}
+
+impl HiveWriter {
+    pub fn new(minor_version: u32) -> Self {
+        Self { bins: Vec::new(), bin_end: 0, minor_version }
+    }
+
+    /// Stores `payload` in a new allocated cell and returns its offset.
+    /// A cell that does not fit the current bin starts a new one.
+    pub fn allocate(&mut self, payload: &[u8]) -> u32 {
+        let size = (payload.len() + 4).next_multiple_of(8);
+        if self.bins.len() + size > self.bin_end {
+            self.close_bin();
+            let bin_size = (size + HBIN_HEADER_SIZE).next_multiple_of(PAGE_SIZE);
+            let mut header = vec![0u8; HBIN_HEADER_SIZE];
+            header[0..4].copy_from_slice(b"hbin");
+            header[4..8].copy_from_slice(&(self.bins.len() as u32).to_le_bytes());
+            header[8..12].copy_from_slice(&(bin_size as u32).to_le_bytes());
+            self.bin_end = self.bins.len() + bin_size;
+            self.bins.extend_from_slice(&header);
+        }
+        let offset = self.bins.len() as u32;
+        self.bins.extend_from_slice(&(-(size as i32)).to_le_bytes());
+        self.bins.extend_from_slice(payload);
+        self.bins.resize(offset as usize + size, 0);
+        offset
+    }
+
+    // Fills the rest of the current bin with one free cell
+    fn close_bin(&mut self) {
+        let remaining = self.bin_end - self.bins.len();
+        if remaining > 0 {
+            self.bins.extend_from_slice(&(remaining as i32).to_le_bytes());
+            self.bins.resize(self.bin_end, 0);
+        }
+    }
+
+    pub fn set_u32(&mut self, cell: u32, field: usize, value: u32) {
+        let start = cell as usize + 4 + field;
+        self.bins[start..start + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn get_u32(&self, cell: u32, field: usize) -> u32 {
+        hive::read_u32(&self.bins, cell as usize + 4 + field)
+    }
+
+    pub fn set_u16(&mut self, cell: u32, field: usize, value: u16) {
+        let start = cell as usize + 4 + field;
+        self.bins[start..start + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Stores value data inline, in one cell or as big data segments, and
+    /// returns the vk size and data offset fields
+    pub fn store_data(&mut self, data: &[u8]) -> (u32, u32) {
+        if data.len() <= 4 {
+            let mut inline = [0u8; 4];
+            inline[..data.len()].copy_from_slice(data);
+            return (data.len() as u32 | DATA_INLINE_FLAG, u32::from_le_bytes(inline));
+        }
+        if data.len() <= BIG_DATA_SEGMENT_SIZE || self.minor_version < BIG_DATA_MINOR_VERSION {
+            return (data.len() as u32, self.allocate(data));
+        }
+        let segments: Vec<u8> = data.chunks(BIG_DATA_SEGMENT_SIZE)
+            .flat_map(|chunk| self.allocate(chunk).to_le_bytes())
+            .collect();
+        let list = self.allocate(&segments);
+        let mut record = vec![0u8; 8];
+        record[0..2].copy_from_slice(b"db");
+        record[2..4].copy_from_slice(&((segments.len() / 4) as u16).to_le_bytes());
+        record[4..8].copy_from_slice(&list.to_le_bytes());
+        (data.len() as u32, self.allocate(&record))
+    }
+
+    /// Writes a subkey list for `entries`: one sorted lh list, or an ri
+    /// index over several when there are too many for one leaf
+    pub fn subkey_list(&mut self, entries: &[(u32, String)]) -> u32 {
+        let Ok(list) = hive::write_subkey_list(entries, |payload| Ok::<_, Infallible>(self.allocate(payload)));
+        list
+    }
+
+    /// Closes the last bin and returns the hive bins data
+    pub fn finish(mut self) -> Vec<u8> {
+        self.close_bin();
+        self.bins
+    }
+}
+
+/// Writes the referenced security cells as one circular chain, returning the
+/// cell of each descriptor (NO_CELL for descriptors no key ended up using)
+fn write_security(writer: &mut HiveWriter, descriptors: &[Vec<u8>], references: &[u32]) -> Vec<u32> {
+    let mut cells = vec![NO_CELL; descriptors.len()];
+    for (i, descriptor) in descriptors.iter().enumerate().filter(|(i, _)| references[*i] > 0) {
+        let mut record = vec![0u8; 20];
+        record[0..2].copy_from_slice(b"sk");
+        record[12..16].copy_from_slice(&references[i].to_le_bytes());
+        record[16..20].copy_from_slice(&(descriptor.len() as u32).to_le_bytes());
+        record.extend_from_slice(descriptor);
+        cells[i] = writer.allocate(&record);
+    }
+    let chain: Vec<u32> = cells.iter().copied().filter(|&c| c != NO_CELL).collect();
+    for (i, &cell) in chain.iter().enumerate() {
+        writer.set_u32(cell, 4, chain[(i + 1) % chain.len()]);
+        writer.set_u32(cell, 8, chain[(i + chain.len() - 1) % chain.len()]);
+    }
+    cells
+}
+
+fn count_references(key: &SalvagedKey, references: &mut [u32]) {
+    references[key.security] += 1;
+    for subkey in &key.subkeys {
+        count_references(subkey, references);
+    }
+}
+
+// Writes a key and its subtree, returning the offset of its nk cell
+fn write_key(writer: &mut HiveWriter, key: &SalvagedKey, parent: u32, security: &[u32], counts: &mut (usize, usize)) -> u32 {
+    let offset = writer.allocate(&hive::build_key_node(&key.name, parent, security[key.security], key.last_written));
+    counts.0 += 1;
+    let (_, compressed) = hive::encode_name(&key.name);
+    let flags = key.flags | if compressed { hive::KEY_COMP_NAME } else { 0 };
+    writer.set_u16(offset, 2, flags);
+
+    if let Some(class) = &key.class {
+        let cell = writer.allocate(class);
+        writer.set_u32(offset, 48, cell);
+        writer.set_u16(offset, 74, class.len() as u16);
+    }
+
+    if !key.values.is_empty() {
+        let mut list = Vec::with_capacity(key.values.len() * 4);
+        for value in &key.values {
+            let (raw_size, data_offset) = writer.store_data(&value.data);
+            let cell = writer.allocate(&hive::build_value_key(&value.name, value.data_type, raw_size, data_offset));
+            list.extend_from_slice(&cell.to_le_bytes());
+        }
+        let list = writer.allocate(&list);
+        writer.set_u32(offset, 36, key.values.len() as u32);
+        writer.set_u32(offset, 40, list);
+        writer.set_u32(offset, 60, key.values.iter().map(|v| hive::utf16_len(&v.name)).max().unwrap_or(0));
+        writer.set_u32(offset, 64, key.values.iter().map(|v| v.data.len() as u32).max().unwrap_or(0));
+    }
+
+    if !key.subkeys.is_empty() {
+        let entries: Vec<(u32, String)> = key.subkeys.iter()
+            .map(|subkey| (write_key(writer, subkey, offset, security, counts), subkey.name.clone()))
+            .collect();
+        let list = writer.subkey_list(&entries);
+        writer.set_u32(offset, 20, entries.len() as u32);
+        writer.set_u32(offset, 28, list);
+        writer.set_u32(offset, 52, key.subkeys.iter().map(|k| hive::utf16_len(&k.name)).max().unwrap_or(0));
+        writer.set_u32(offset, 56, key.subkeys.iter().map(|k| k.class.as_ref().map_or(0, |c| c.len() as u32)).max().unwrap_or(0));
+    }
+    counts.1 += key.values.len();
+    offset
+}
+
+/// Base block for a freshly written hive
+fn build_base_block(source: &Hive, minor_version: u32, root: u32, bins_size: u32) -> Vec<u8> {
+    let mut base = vec![0u8; BASE_BLOCK_SIZE];
+    base[0..4].copy_from_slice(b"regf");
+    base[4..8].copy_from_slice(&1u32.to_le_bytes());
+    base[8..12].copy_from_slice(&1u32.to_le_bytes());
+    base[12..20].copy_from_slice(&hive::filetime_now().to_le_bytes());
+    base[20..24].copy_from_slice(&1u32.to_le_bytes());
+    base[24..28].copy_from_slice(&minor_version.to_le_bytes());
+    base[32..36].copy_from_slice(&1u32.to_le_bytes()); // format: direct memory load
+    base[36..40].copy_from_slice(&root.to_le_bytes());
+    base[40..44].copy_from_slice(&bins_size.to_le_bytes());
+    base[44..48].copy_from_slice(&1u32.to_le_bytes()); // clustering factor
+    // Keep the embedded file name so the hive still says what it is
+    base[48..112].copy_from_slice(&source.data()[48..112]);
+    let checksum = registry::calculate_header_checksum(&base);
+    base[508..512].copy_from_slice(&checksum.to_le_bytes());
+    base
+}
+
+/// Reads every reachable key, value and security descriptor of a damaged
+/// hive (and its orphans when `recover` is set) and writes them to a
+/// brand-new hive at `output_path`
+pub fn rebuild_hive(file_path: &str, output_path: &str, recover: bool) -> Result<RebuildReport> {
+    let hive = Hive::open(file_path)?;
+    let orphans = registry::find_orphans(&hive);
+    let mut salvager = Salvager::new(&hive, recover.then_some(&orphans));
+
+    let mut root = salvager.key(hive.root_cell_offset(), "", None, false, 0)
+        .ok_or_else(|| anyhow::anyhow!("The root key is unreadable; nothing can be rebuilt"))?;
+    root.flags |= hive::KEY_HIVE_ENTRY | hive::KEY_NO_DELETE;
+    let recovered = salvager.recover(&mut root);
+    if !recover && (!orphans.top_level.is_empty() || !orphans.loose_values.is_empty()) {
+        salvager.dropped.push(format!(
+            "{} orphaned key(s) and {} loose value(s) not reachable from the root (use --recover to keep them)",
+            orphans.keys.len(), orphans.loose_values.len()
+        ));
+    }
+
+    let minor_version = match hive.minor_version() {
+        minor @ 3..=6 => minor,
+        _ => DEFAULT_MINOR_VERSION,
+    };
+    let mut writer = HiveWriter::new(minor_version);
+    let mut references = vec![0u32; salvager.descriptors.len()];
+    count_references(&root, &mut references);
+    let security = write_security(&mut writer, &salvager.descriptors, &references);
+    let mut counts = (0, 0);
+    let root_offset = write_key(&mut writer, &root, NO_CELL, &security, &mut counts);
+    let bins = writer.finish();
+
+    let mut output = build_base_block(&hive, minor_version, root_offset, bins.len() as u32);
+    output.extend_from_slice(&bins);
+    registry::create_output_file(output_path, &output)?;
+
+    Ok(RebuildReport {
+        keys: counts.0,
+        values: counts.1,
+        security_descriptors: security.iter().filter(|&&c| c != NO_CELL).count(),
+        recovered,
+        dropped: salvager.dropped,
+        original_size: fs::metadata(file_path)?.len(),
+        rebuilt_size: output.len() as u64,
+        validation: registry::check_registry_file(output_path),
+    })
+}
+
+/// Writes `root` and its subtree into a new in-memory hive whose keys all
+/// use the default security descriptor
+#[cfg(test)]
+pub fn build_test_hive(root: &SalvagedKey) -> Hive {
+    let mut writer = HiveWriter::new(DEFAULT_MINOR_VERSION);
+    let mut references = vec![0u32; 1];
+    count_references(root, &mut references);
+    let security = write_security(&mut writer, &[default_security_descriptor()], &references);
+    let root_offset = write_key(&mut writer, root, NO_CELL, &security, &mut (0, 0));
+    let bins = writer.finish();
+
+    let source = Hive::from_bytes(vec![0; BASE_BLOCK_SIZE]).unwrap();
+    let mut data = build_base_block(&source, DEFAULT_MINOR_VERSION, root_offset, bins.len() as u32);
+    data.extend_from_slice(&bins);
+    Hive::from_bytes(data).unwrap()
+}
+
+#[cfg(test)]
+impl SalvagedKey {
+    /// A key with no values, subkeys or class, using descriptor 0
+    pub fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            flags: 0,
+            last_written: 0,
+            class: None,
+            security: 0,
+            values: Vec::new(),
+            subkeys: Vec::new(),
+        }
+    }
+}
+
+pub fn print_report(report: &RebuildReport) {
+    println!("Rebuilt {} key(s), {} value(s) and {} security descriptor(s)",
+        report.keys, report.values, report.security_descriptors);
+    if report.recovered > 0 {
+        println!("{} orphaned item(s) attached below {}", report.recovered, RECOVERED_KEY_NAME);
+    }
+    println!("Size: {} bytes -> {} bytes", report.original_size, report.rebuilt_size);
+
+    if report.dropped.is_empty() {
+        println!("\nNothing readable was dropped.");
+    } else {
+        println!("\n{} item(s) were dropped:", report.dropped.len());
+        for item in &report.dropped {
+            println!("  {}", item);
+        }
+    }
+
+    match &report.validation {
+        Ok(result) if result.issues.is_empty() => println!("\nRebuilt hive validated with no issues."),
+        Ok(result) => println!("\nRebuilt hive validated with {} issue(s).", result.issues.len()),
+        Err(e) => println!("\nRebuilt hive failed validation: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_hive_passes_validation() {
+        let mut root = SalvagedKey::named("ROOT");
+        root.flags = hive::KEY_HIVE_ENTRY | hive::KEY_NO_DELETE;
+        root.values.push(SalvagedValue { name: String::new(), data_type: hive::REG_SZ, data: "x\0".encode_utf16().flat_map(|u| u.to_le_bytes()).collect() });
+        root.values.push(SalvagedValue { name: "Small".to_string(), data_type: hive::REG_DWORD, data: 7u32.to_le_bytes().to_vec() });
+        root.values.push(SalvagedValue { name: "Big".to_string(), data_type: hive::REG_BINARY, data: vec![0xAB; BIG_DATA_SEGMENT_SIZE * 2 + 100] });
+        let mut wide = SalvagedKey::named("Wide");
+        // Enough subkeys to need an ri index over several lh lists
+        wide.subkeys = (0..hive::MAX_LEAF_ENTRIES + 10).map(|i| SalvagedKey::named(&format!("Key{:04}", i))).collect();
+        root.subkeys.push(wide);
+        root.subkeys.push(SalvagedKey::named("Ünïcode\u{263A}"));
+        let hive = build_test_hive(&root);
+
+        let path = std::env::temp_dir().join(format!("regfix_rebuild_test_{}.hiv", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        fs::write(&path, hive.data()).unwrap();
+        let analysis = registry::check_registry_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        let analysis = analysis.unwrap();
+        assert!(analysis.issues.is_empty(), "{:?}", analysis.issues);
+        let wide = hive.find_key("Wide").unwrap();
+        assert_eq!(&hive.cell_payload(wide.subkey_list).unwrap()[0..2], b"ri");
+        assert_eq!(hive.subkeys(&wide).unwrap().len(), hive::MAX_LEAF_ENTRIES + 10);
+        let root = hive.root_key().unwrap();
+        let values: Vec<Vec<u8>> = hive.value_offsets(&root).unwrap().into_iter()
+            .map(|offset| hive.value_data(&hive.value(offset).unwrap()).unwrap())
+            .collect();
+        assert_eq!(values[2], vec![0xAB; BIG_DATA_SEGMENT_SIZE * 2 + 100]);
+        assert!(hive.find_key("ünïcode\u{263A}").is_ok());
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Hives kept in the list, most recent first
+pub const MAX_RECENT: usize = 10;
+
+const APP_DIR: &str = "MDC_RegFix";
+const RECENT_FILE: &str = "recent_hives.txt";
+
+/// Where the list is kept: the per-user application data folder on Windows,
+/// the XDG config folder elsewhere
+fn list_path() -> Option<PathBuf> {
+    let base = std::env::var_os("APPDATA")
+        .or_else(|| std::env::var_os("XDG_CONFIG_HOME"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join(APP_DIR).join(RECENT_FILE))
+}
+
+/// Recently opened hives, one path per line. A missing or unreadable list
+/// is treated as empty.
+pub fn load() -> Vec<PathBuf> {
+    let Some(path) = list_path() else {
+        return Vec::new();
+    };
+    fs::read_to_string(path)
+        .map(|text| text.lines().filter(|l| !l.trim().is_empty()).map(PathBuf::from).take(MAX_RECENT).collect())
+        .unwrap_or_default()
+}
+
+/// Moves `opened` to the front of `recent`, dropping the oldest entries
+pub fn add(recent: &mut Vec<PathBuf>, opened: &Path) {
+    recent.retain(|p| p != opened);
+    recent.insert(0, opened.to_path_buf());
+    recent.truncate(MAX_RECENT);
+}
+
+pub fn save(recent: &[PathBuf]) -> Result<()> {
+    let path = list_path().ok_or_else(|| anyhow!("No folder for application data is set"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let lines: Vec<String> = recent.iter().map(|p| p.to_string_lossy().to_string()).collect();
+    fs::write(path, lines.join("\n") + "\n")?;
+    Ok(())
+}
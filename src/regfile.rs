@@ -0,0 +1,427 @@
+use crate::edit::Edit;
+use crate::hive::{self, Hive, KeyNode, REG_BINARY, REG_DWORD, REG_EXPAND_SZ, REG_LINK, REG_MULTI_SZ, REG_SZ};
+use anyhow::{anyhow, bail, Result};
+use std::fs;
+
+pub const REG_HEADER_V5: &str = "Windows Registry Editor Version 5.00";
+pub const REG_HEADER_V4: &str = "REGEDIT4";
+
+// Abbreviations accepted in place of the predefined root keys
+const ROOT_ABBREVIATIONS: [(&str, &str); 5] = [
+    ("HKLM", "HKEY_LOCAL_MACHINE"),
+    ("HKCU", "HKEY_CURRENT_USER"),
+    ("HKCR", "HKEY_CLASSES_ROOT"),
+    ("HKU", "HKEY_USERS"),
+    ("HKCC", "HKEY_CURRENT_CONFIG"),
+];
+
+// regedit breaks hex data onto a new line once a line reaches this column
+const HEX_WRAP_COLUMN: usize = 77;
+
+/// A .reg document built from a hive, with everything that had to be left out
+#[derive(Debug, Default)]
+pub struct RegExport {
+    pub text: String,
+    pub keys: usize,
+    pub values: usize,
+    pub skipped: Vec<String>,
+}
+
+/// Quotes a value name or string the way regedit does
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Appends `hex:`/`hex(n):` data to `line`, continuing on indented lines with
+/// a trailing `\` once the line grows past 77 columns
+fn push_hex_data(line: &mut String, prefix: &str, data: &[u8]) {
+    line.push_str(prefix);
+    let mut column = line.chars().count();
+    for (i, byte) in data.iter().enumerate() {
+        line.push_str(&format!("{:02x}", byte));
+        if i + 1 == data.len() {
+            break;
+        }
+        line.push(',');
+        column += 3;
+        if column >= HEX_WRAP_COLUMN {
+            line.push_str("\\\r\n  ");
+            column = 2;
+        }
+    }
+}
+
+/// Encodes one value the way regedit writes it: strings quoted, DWORDs as
+/// `dword:`, binary as `hex:` and everything else as `hex(<type>):`
+pub fn encode_value(name: &str, data_type: u32, data: &[u8]) -> String {
+    let mut line = if name.is_empty() { "@=".to_string() } else { format!("{}=", quote(name)) };
+    match data_type {
+        REG_SZ if string_round_trips(data) => {
+            line.push_str(&quote(&hive::decode_multi_string(data).into_iter().next().unwrap_or_default()));
+        }
+        REG_DWORD if data.len() == 4 => line.push_str(&format!("dword:{:08x}", hive::read_u32(data, 0))),
+        REG_BINARY => push_hex_data(&mut line, "hex:", data),
+        other => push_hex_data(&mut line, &format!("hex({:x}):", other), data),
+    }
+    line
+}
+
+// A REG_SZ can be written as a quoted string only when that loses nothing:
+// well-formed UTF-16 ending in exactly one NUL (or no terminator at all)
+fn string_round_trips(data: &[u8]) -> bool {
+    if !data.len().is_multiple_of(2) {
+        return false;
+    }
+    let units: Vec<u16> = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    let body = units.strip_suffix(&[0]).unwrap_or(&units);
+    !body.contains(&0) && String::from_utf16(body).is_ok()
+}
+
+/// Path written in the key headers for a path relative to the hive root
+pub fn full_path(root_prefix: &str, path: &str) -> String {
+    match (root_prefix.is_empty(), path.is_empty()) {
+        (_, true) => root_prefix.to_string(),
+        (true, false) => path.to_string(),
+        (false, false) => format!("{}\\{}", root_prefix, path),
+    }
+}
+
+/// Builds a .reg document for the key at `key_path` (relative to the root;
+/// empty for the whole hive) and everything below it. `root_prefix` replaces
+/// the hive root in the key headers, e.g. `HKEY_LOCAL_MACHINE\SYSTEM`; the
+/// root key's own name is used when it is empty.
+pub fn export_reg(hive: &Hive, key_path: &str, root_prefix: &str) -> Result<RegExport> {
+    let start = hive.find_key(key_path)?;
+    let root_prefix = if root_prefix.is_empty() {
+        hive.root_key()?.name
+    } else {
+        root_prefix.trim_end_matches('\\').to_string()
+    };
+    // Use the names as stored rather than as typed
+    let key_path = hive.key_paths().remove(&start.offset).unwrap_or_else(|| {
+        key_path.split('\\').filter(|n| !n.is_empty()).collect::<Vec<_>>().join("\\")
+    });
+
+    let mut export = RegExport {
+        text: format!("{}\r\n\r\n", REG_HEADER_V5),
+        ..Default::default()
+    };
+    let mut pending = vec![(start, key_path)];
+    while let Some((key, path)) = pending.pop() {
+        write_key(hive, &key, &full_path(&root_prefix, &path), &mut export);
+        match hive.subkeys(&key) {
+            Ok(subkeys) => {
+                // Pushed in reverse so they are written in list (sorted) order
+                for subkey in subkeys.into_iter().rev() {
+                    let subkey_path = hive::join_path(&path, &subkey.name);
+                    pending.push((subkey, subkey_path));
+                }
+            }
+            Err(e) => export.skipped.push(format!("Subkeys of {}: {}", full_path(&root_prefix, &path), e)),
+        }
+    }
+    Ok(export)
+}
+
+fn write_key(hive: &Hive, key: &KeyNode, path: &str, export: &mut RegExport) {
+    export.text.push_str(&format!("[{}]\r\n", path));
+    export.keys += 1;
+
+    let offsets = match hive.value_offsets(key) {
+        Ok(offsets) => offsets,
+        Err(e) => {
+            export.skipped.push(format!("Values of {}: {}", path, e));
+            Vec::new()
+        }
+    };
+    for offset in offsets {
+        let value = match hive.value(offset) {
+            Ok(value) => value,
+            Err(e) => {
+                export.skipped.push(format!("Value in {}: {}", path, e));
+                continue;
+            }
+        };
+        let data = match hive.value_data(&value) {
+            Ok(data) => data,
+            Err(e) => {
+                export.skipped.push(format!("Value '{}' in {}: {}", value.name, path, e));
+                continue;
+            }
+        };
+        export.text.push_str(&encode_value(&value.name, value.data_type, &data));
+        export.text.push_str("\r\n");
+        export.values += 1;
+    }
+    export.text.push_str("\r\n");
+}
+
+/// Writes a .reg document as UTF-16LE with a byte order mark, as regedit does
+pub fn write_reg_file(output_path: &str, text: &str) -> Result<()> {
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(text.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+    fs::write(output_path, bytes)?;
+    Ok(())
+}
+
+/// A parsed .reg file. Key paths are as written in the file.
+#[derive(Debug)]
+pub struct RegFile {
+    /// REGEDIT4 files store text as ANSI rather than UTF-16
+    pub ansi: bool,
+    pub edits: Vec<Edit>,
+}
+
+/// Reads a .reg file as UTF-16LE when it starts with a byte order mark,
+/// otherwise as UTF-8 or, failing that, Latin-1
+pub fn read_reg_file(path: &str) -> Result<String> {
+    let bytes = fs::read(path)?;
+    if let Some(utf16) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return Ok(hive::decode_utf16(utf16));
+    }
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(&bytes);
+    Ok(match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    })
+}
+
+// Reads a quoted string starting at `text[0] == '"'`, returning it unescaped
+// together with the rest of the line
+fn parse_quoted(text: &str) -> Result<(String, &str)> {
+    let mut result = String::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, escaped)) => result.push(escaped),
+                None => bail!("Unterminated string: {}", text),
+            },
+            '"' => return Ok((result, &text[i + 1..])),
+            _ => result.push(c),
+        }
+    }
+    bail!("Unterminated string: {}", text)
+}
+
+// Splits `"name"=data` or `@=data` into the value name and the data text
+fn split_assignment(line: &str) -> Result<(String, &str)> {
+    let (name, rest) = if let Some(rest) = line.strip_prefix('@') {
+        (String::new(), rest)
+    } else if line.starts_with('"') {
+        parse_quoted(line)?
+    } else {
+        bail!("Unrecognised line: {}", line);
+    };
+    let data = rest.trim_start().strip_prefix('=').ok_or_else(|| anyhow!("Missing '=' in: {}", line))?;
+    Ok((name, data.trim()))
+}
+
+fn parse_hex_bytes(text: &str) -> Result<Vec<u8>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|b| !b.is_empty())
+        .map(|b| u8::from_str_radix(b, 16).map_err(|_| anyhow!("Invalid hex byte '{}'", b)))
+        .collect()
+}
+
+fn utf16_with_terminator(text: &str) -> Vec<u8> {
+    text.encode_utf16().chain([0]).flat_map(|u| u.to_le_bytes()).collect()
+}
+
+/// Parses the data side of a value line into its type and bytes, or None for `-`
+fn parse_data(data: &str, ansi: bool) -> Result<Option<(u32, Vec<u8>)>> {
+    if data == "-" {
+        return Ok(None);
+    }
+    if data.starts_with('"') {
+        let (text, rest) = parse_quoted(data)?;
+        if !rest.trim().is_empty() {
+            bail!("Unexpected text after string: {}", data);
+        }
+        return Ok(Some((REG_SZ, utf16_with_terminator(&text))));
+    }
+    if let Some(dword) = data.strip_prefix("dword:") {
+        let value = u32::from_str_radix(dword.trim(), 16).map_err(|_| anyhow!("Invalid dword '{}'", dword))?;
+        return Ok(Some((REG_DWORD, value.to_le_bytes().to_vec())));
+    }
+    let (data_type, bytes) = if let Some(bytes) = data.strip_prefix("hex:") {
+        (REG_BINARY, bytes)
+    } else if let Some(rest) = data.strip_prefix("hex(") {
+        let (number, bytes) = rest.split_once("):").ok_or_else(|| anyhow!("Invalid hex type in: {}", data))?;
+        let data_type = u32::from_str_radix(number, 16).map_err(|_| anyhow!("Invalid hex type '{}'", number))?;
+        (data_type, bytes)
+    } else {
+        bail!("Unrecognised value data: {}", data);
+    };
+
+    let mut bytes = parse_hex_bytes(bytes)?;
+    // REGEDIT4 writes string types as single-byte text; hives hold UTF-16
+    if ansi && matches!(data_type, REG_SZ | REG_EXPAND_SZ | REG_LINK | REG_MULTI_SZ) {
+        bytes = bytes.iter().flat_map(|&b| [b, 0]).collect();
+    }
+    Ok(Some((data_type, bytes)))
+}
+
+/// Parses REGEDIT4 and Windows Registry Editor Version 5.00 files, including
+/// `[-key]` deletions, `"name"=-` value deletions and continued hex lines
+pub fn parse_reg(text: &str) -> Result<RegFile> {
+    let mut lines = text.lines().map(str::trim);
+    let ansi = match lines.next() {
+        Some(REG_HEADER_V5) => false,
+        Some(REG_HEADER_V4) => true,
+        other => bail!("Not a .reg file (unexpected header {:?})", other.unwrap_or("")),
+    };
+
+    let mut edits = Vec::new();
+    let mut key: Option<String> = None;
+    let mut pending = String::new();
+    for (number, line) in lines.enumerate() {
+        // Hex data continues on the next line after a trailing backslash
+        if let Some(continued) = line.strip_suffix('\\') {
+            if !pending.is_empty() || split_assignment(line).map(|(_, d)| d.starts_with("hex")).unwrap_or(false) {
+                pending.push_str(continued);
+                continue;
+            }
+        }
+        let line = if pending.is_empty() { line.to_string() } else { std::mem::take(&mut pending) + line };
+        let context = |e: anyhow::Error| anyhow!("Line {}: {}", number + 2, e);
+
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if let Some(path) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            match path.strip_prefix('-') {
+                Some(path) => {
+                    edits.push(Edit::DeleteKey(path.trim().to_string()));
+                    key = None;
+                }
+                None => {
+                    edits.push(Edit::CreateKey(path.trim().to_string()));
+                    key = Some(path.trim().to_string());
+                }
+            }
+            continue;
+        }
+
+        let Some(key) = &key else {
+            return Err(context(anyhow!("Value outside of a key: {}", line)));
+        };
+        let (name, data) = split_assignment(&line).map_err(context)?;
+        edits.push(match parse_data(data, ansi).map_err(context)? {
+            Some((data_type, data)) => Edit::SetValue { key: key.clone(), name, data_type, data },
+            None => Edit::DeleteValue { key: key.clone(), name },
+        });
+    }
+    Ok(RegFile { ansi, edits })
+}
+
+fn expand_root(component: &str) -> String {
+    ROOT_ABBREVIATIONS.iter()
+        .find(|(short, _)| short.eq_ignore_ascii_case(component))
+        .map(|(_, long)| long.to_string())
+        .unwrap_or_else(|| component.to_string())
+}
+
+/// Maps a full path from a .reg file onto a path relative to the hive root,
+/// or None when it lies outside `root_prefix`
+fn relative_to(path: &str, root_prefix: &str) -> Option<String> {
+    let components = |p: &str| -> Vec<String> {
+        p.split('\\').filter(|c| !c.is_empty()).enumerate()
+            .map(|(i, c)| if i == 0 { expand_root(c) } else { c.to_string() })
+            .collect()
+    };
+    let prefix = components(root_prefix);
+    let path = components(path);
+    if path.len() < prefix.len() || !prefix.iter().zip(&path).all(|(a, b)| a.eq_ignore_ascii_case(b)) {
+        return None;
+    }
+    Some(path[prefix.len()..].join("\\"))
+}
+
+/// Rewrites the key paths of a parsed .reg file relative to the hive root.
+/// `root_prefix` is the path in the file that corresponds to the root
+/// (e.g. `HKEY_LOCAL_MACHINE\SYSTEM`); the root key's name when empty.
+/// Returns the edits and the keys skipped for lying outside the prefix.
+pub fn map_to_hive(hive: &Hive, reg: &RegFile, root_prefix: &str) -> Result<(Vec<Edit>, Vec<String>)> {
+    let root_prefix = if root_prefix.is_empty() { hive.root_key()?.name } else { root_prefix.to_string() };
+    let mut edits = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+    for edit in &reg.edits {
+        let path = match edit {
+            Edit::CreateKey(path) | Edit::DeleteKey(path) | Edit::RenameKey { path, .. } => path,
+            Edit::SetValue { key, .. } | Edit::DeleteValue { key, .. } => key,
+        };
+        let Some(relative) = relative_to(path, &root_prefix) else {
+            if !skipped.contains(path) {
+                skipped.push(path.clone());
+            }
+            continue;
+        };
+        edits.push(match edit.clone() {
+            Edit::CreateKey(_) => Edit::CreateKey(relative),
+            Edit::DeleteKey(_) => Edit::DeleteKey(relative),
+            Edit::RenameKey { new_name, .. } => Edit::RenameKey { path: relative, new_name },
+            Edit::SetValue { name, data_type, data, .. } => Edit::SetValue { key: relative, name, data_type, data },
+            Edit::DeleteValue { name, .. } => Edit::DeleteValue { key: relative, name },
+        });
+    }
+    Ok((edits, skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit;
+    use crate::rebuild::{self, SalvagedKey, SalvagedValue};
+
+    fn value(name: &str, data_type: u32, data: Vec<u8>) -> SalvagedValue {
+        SalvagedValue { name: name.to_string(), data_type, data }
+    }
+
+    fn utf16z(text: &str) -> Vec<u8> {
+        text.encode_utf16().chain([0]).flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn export_then_import_rebuilds_the_same_tree() {
+        let mut software = SalvagedKey::named("Software");
+        software.values = vec![
+            value("", REG_SZ, utf16z("default")),
+            value("Quoted \"name\" with \\", REG_SZ, utf16z("C:\\Program Files\\\"x\"")),
+            value("Path", REG_EXPAND_SZ, utf16z("%SystemRoot%\\system32")),
+            value("Count", REG_DWORD, 0xDEADBEEFu32.to_le_bytes().to_vec()),
+            value("Big", hive::REG_QWORD, 1u64.to_le_bytes().to_vec()),
+            value("List", REG_MULTI_SZ, [utf16z("one"), utf16z("twö"), vec![0, 0]].concat()),
+            value("Blob", REG_BINARY, (0..=255).collect()),
+            value("Empty", REG_BINARY, Vec::new()),
+            value("None", hive::REG_NONE, vec![1, 2, 3]),
+        ];
+        let mut nested = SalvagedKey::named("Ünïcode \u{263A}");
+        nested.subkeys.push(SalvagedKey::named("Leaf"));
+        software.subkeys.push(nested);
+        let mut root = SalvagedKey::named("ROOT");
+        root.subkeys = vec![software, SalvagedKey::named("System")];
+        let original = rebuild::build_test_hive(&root);
+        let exported = export_reg(&original, "", "HKEY_LOCAL_MACHINE\\TEST").unwrap();
+        assert!(exported.skipped.is_empty(), "{:?}", exported.skipped);
+        assert_eq!((exported.keys, exported.values), (5, 9));
+
+        let mut imported = rebuild::build_test_hive(&SalvagedKey::named("ROOT"));
+        let parsed = parse_reg(&exported.text).unwrap();
+        let (edits, skipped) = map_to_hive(&imported, &parsed, "HKEY_LOCAL_MACHINE\\TEST").unwrap();
+        assert!(skipped.is_empty(), "{:?}", skipped);
+        for edit in &edits {
+            edit::apply_edit(&mut imported, edit).unwrap();
+        }
+
+        let reexported = export_reg(&imported, "", "HKEY_LOCAL_MACHINE\\TEST").unwrap();
+        assert_eq!(reexported.text, exported.text);
+        let key = imported.find_key("Software").unwrap();
+        let list = imported.value_offsets(&key).unwrap().into_iter()
+            .map(|offset| imported.value(offset).unwrap())
+            .find(|value| value.name == "List")
+            .unwrap();
+        assert_eq!(hive::decode_multi_string(&imported.value_data(&list).unwrap()), vec!["one", "twö"]);
+    }
+}
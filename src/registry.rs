@@ -1,10 +1,43 @@
+use crate::edit;
+use crate::header::{self, FieldChange, HeaderField};
+use crate::hive::{self, Hive, KeyNode, TreeWalk, ValueKey, BASE_BLOCK_SIZE, NO_CELL};
+use crate::progress::{self, Monitor, Phase, REPORT_EVERY_BYTES, REPORT_EVERY_KEYS};
 use crate::types::*;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::fs;
-use std::io::{Write, Seek, SeekFrom};
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use memmap::MmapOptions;
 
+/// Key created below the root to hold recovered orphans
+pub const RECOVERED_KEY_NAME: &str = "RegFix_Recovered";
+
+// Caps the number of tree errors and orphans listed as separate issues
+const MAX_LISTED_ERRORS: usize = 10;
+
+// Process-wide read-only switch shared by the CLI and the GUI
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables read-only mode. While enabled, every `update_*`
+/// function and `backup_file` refuse to open anything for writing.
+pub fn set_read_only(enabled: bool) {
+    READ_ONLY.store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::SeqCst)
+}
+
+fn ensure_writable() -> Result<()> {
+    if is_read_only() {
+        bail!("Read-only mode is enabled; refusing to modify any file");
+    }
+    Ok(())
+}
+
 pub fn calculate_header_checksum(data: &[u8]) -> u32 {
     let mut checksum: u32 = 0;
     
@@ -24,12 +57,42 @@ pub fn calculate_header_checksum(data: &[u8]) -> u32 {
 }
 
 pub fn backup_file(file_path: &str) -> Result<String> {
+    ensure_writable()?;
     let backup_path = format!("{}.backup", file_path);
     fs::copy(file_path, &backup_path)?;
     Ok(backup_path)
 }
 
+/// Copies a hive to `output_path` so repairs can be applied to the copy while
+/// the original (possibly on read-only media) stays untouched.
+pub fn copy_for_repair(file_path: &str, output_path: &str) -> Result<()> {
+    ensure_writable()?;
+    if let (Ok(source), Ok(target)) = (fs::canonicalize(file_path), fs::canonicalize(output_path)) {
+        if source == target {
+            bail!("The output path must differ from the file being repaired");
+        }
+    }
+
+    // Stream the bytes rather than using fs::copy so the copy does not inherit
+    // the read-only attribute of files taken from read-only media
+    let mut source = File::open(file_path)?;
+    let mut target = File::create(output_path)?;
+    std::io::copy(&mut source, &mut target)?;
+    target.sync_all()?;
+    Ok(())
+}
+
+/// Writes a newly built hive (merge, rebuild, ...) to `output_path`
+pub fn create_output_file(output_path: &str, data: &[u8]) -> Result<()> {
+    ensure_writable()?;
+    let mut file = File::create(output_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    Ok(())
+}
+
 pub fn update_hive_bins_size(file_path: &str, new_size: u32) -> Result<()> {
+    ensure_writable()?;
     let mut file = fs::OpenOptions::new().write(true).open(file_path)?;
     let mut buffer = [0u8; 4];
     buffer.copy_from_slice(&new_size.to_le_bytes());
@@ -39,6 +102,7 @@ pub fn update_hive_bins_size(file_path: &str, new_size: u32) -> Result<()> {
 }
 
 pub fn update_sequence_numbers(file_path: &str, primary: u32, secondary: u32) -> Result<()> {
+    ensure_writable()?;
     let mut file = fs::OpenOptions::new().write(true).open(file_path)?;
     let mut buffer = [0u8; 4];
     
@@ -53,7 +117,55 @@ pub fn update_sequence_numbers(file_path: &str, primary: u32, secondary: u32) ->
     Ok(())
 }
 
+/// Overwrites bytes of the base block; the checksum is left to the caller
+pub fn update_base_block(file_path: &str, offset: usize, bytes: &[u8]) -> Result<()> {
+    ensure_writable()?;
+    if offset + bytes.len() > BASE_BLOCK_SIZE {
+        bail!("Bytes 0x{:X}..0x{:X} lie outside the base block", offset, offset + bytes.len());
+    }
+    let mut file = fs::OpenOptions::new().write(true).open(file_path)?;
+    file.seek(SeekFrom::Start(offset as u64))?;
+    file.write_all(bytes)?;
+    Ok(())
+}
+
+/// Replaces everything after the base block, growing or truncating the file
+pub fn update_hive_data(file_path: &str, bins: &[u8]) -> Result<()> {
+    ensure_writable()?;
+    let mut file = fs::OpenOptions::new().write(true).open(file_path)?;
+    file.seek(SeekFrom::Start(BASE_BLOCK_SIZE as u64))?;
+    file.write_all(bins)?;
+    file.set_len((BASE_BLOCK_SIZE + bins.len()) as u64)?;
+    Ok(())
+}
+
+/// The base block `commit_hive` leaves on disk for a hive modified in
+/// memory: its hive bins size, both sequence numbers bumped and a fresh
+/// checksum
+pub fn committed_header(hive: &Hive) -> Vec<u8> {
+    let bins = (hive.data().len() - BASE_BLOCK_SIZE) as u32;
+    let sequence = hive.primary_seq_num().wrapping_add(1).to_le_bytes().to_vec();
+    header::edited_header(hive.data(), &[
+        (HeaderField::HiveBinsSize, bins.to_le_bytes().to_vec()),
+        (HeaderField::PrimarySequence, sequence.clone()),
+        (HeaderField::SecondarySequence, sequence),
+    ])
+}
+
+/// Writes a hive modified in memory back to `file_path`: the bins data, its
+/// size, bumped sequence numbers and a fresh checksum
+pub fn commit_hive(file_path: &str, hive: &Hive) -> Result<()> {
+    let bins = &hive.data()[BASE_BLOCK_SIZE..];
+    let sequence = hive.primary_seq_num().wrapping_add(1);
+    update_hive_data(file_path, bins)?;
+    update_hive_bins_size(file_path, bins.len() as u32)?;
+    update_sequence_numbers(file_path, sequence, sequence)?;
+    refresh_checksum(file_path)?;
+    Ok(())
+}
+
 pub fn update_checksum(file_path: &str, new_checksum: u32) -> Result<()> {
+    ensure_writable()?;
     let mut file = fs::OpenOptions::new().write(true).open(file_path)?;
     let mut buffer = [0u8; 4];
     buffer.copy_from_slice(&new_checksum.to_le_bytes());
@@ -62,16 +174,668 @@ pub fn update_checksum(file_path: &str, new_checksum: u32) -> Result<()> {
     Ok(())
 }
 
+/// Recalculates the header checksum from the bytes currently on disk and stores it
+pub fn refresh_checksum(file_path: &str) -> Result<u32> {
+    let mut header = [0u8; 512];
+    File::open(file_path)?.read_exact(&mut header)?;
+    let checksum = calculate_header_checksum(&header);
+    update_checksum(file_path, checksum)?;
+    Ok(checksum)
+}
+
+/// Decides which file a repair writes to: a fresh copy at `output_path`, or
+/// `file_path` itself after a `.backup` has been made
+pub fn prepare_target<'a>(file_path: &'a str, output_path: Option<&'a str>) -> Result<&'a str> {
+    match output_path {
+        Some(output_path) => {
+            copy_for_repair(file_path, output_path)
+                .map_err(|e| anyhow!("Failed to create repaired copy: {}", e))?;
+            Ok(output_path)
+        }
+        None => {
+            backup_file(file_path).map_err(|e| anyhow!("Failed to create backup: {}", e))?;
+            Ok(file_path)
+        }
+    }
+}
+
+/// Pairs of fixable issues, as indices into `issues`, whose fixes would
+/// rewrite the same bytes. Applying both would let one fix undo the other.
+pub fn conflicting_fixes(issues: &[ValidationIssue], fixes: &[FixType]) -> Vec<(usize, usize)> {
+    let selected: Vec<(usize, &IssueLocation)> = issues.iter().enumerate()
+        .filter(|(_, i)| i.fix_type.as_ref().is_some_and(|f| fixes.contains(f)))
+        .filter_map(|(index, i)| i.location.as_ref().map(|l| (index, l)))
+        .collect();
+    let mut conflicts = Vec::new();
+    for (n, (a, first)) in selected.iter().enumerate() {
+        for (b, second) in &selected[n + 1..] {
+            if first.overlaps(second) {
+                conflicts.push((*a, *b));
+            }
+        }
+    }
+    conflicts
+}
+
+/// The analysis narrowed to the issues at `selected`, indices into
+/// `analysis.issues`, so only their fixes are applied
+pub fn select_issues(analysis: &AnalysisResult, selected: &[usize]) -> AnalysisResult {
+    AnalysisResult {
+        issues: selected.iter().filter_map(|&i| analysis.issues.get(i).cloned()).collect(),
+        file_info: analysis.file_info.clone(),
+    }
+}
+
+/// Fix types of the fixable issues in `analysis`, each listed once
+pub fn fix_types(analysis: &AnalysisResult) -> Vec<FixType> {
+    let mut fixes = Vec::new();
+    for fix in analysis.issues.iter().filter_map(|i| i.fix_type.clone()) {
+        if !fixes.contains(&fix) {
+            fixes.push(fix);
+        }
+    }
+    fixes
+}
+
+/// Base block fields rewritten by the header fixes among `fixes`. The
+/// checksum is not listed; it is recalculated after any of them.
+pub fn header_changes(analysis: &AnalysisResult, fixes: &[FixType]) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    for fix_type in fixes {
+        let Some(issue) = analysis.issues.iter().find(|i| i.fix_type.as_ref() == Some(fix_type)) else {
+            continue;
+        };
+        match (fix_type, &issue.fix_data) {
+            (FixType::HiveBinsSize, Some(FixData::HiveBinsSize(new_size))) => {
+                changes.push((HeaderField::HiveBinsSize, new_size.to_le_bytes().to_vec()));
+            }
+            (FixType::SequenceNumbers, Some(FixData::SequenceNumbers(primary, secondary))) => {
+                changes.push((HeaderField::PrimarySequence, primary.to_le_bytes().to_vec()));
+                changes.push((HeaderField::SecondarySequence, secondary.to_le_bytes().to_vec()));
+            }
+            _ => {}
+        }
+    }
+    changes
+}
+
+/// Cells of the orphans whose recovery is selected in `analysis`
+pub fn orphan_cells(analysis: &AnalysisResult) -> Vec<u32> {
+    analysis.issues.iter()
+        .filter(|i| i.fix_type == Some(FixType::RecoverOrphans))
+        .flat_map(|i| match &i.fix_data {
+            Some(FixData::OrphanedCell(cell)) => vec![*cell],
+            Some(FixData::OrphanedCells(cells)) => cells.clone(),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// Applies the selected fixes and re-validates the result.
+///
+/// With an `output_path` the original is copied there first and every fix is
+/// written to the copy; otherwise a `.backup` is made and the file is patched
+/// in place. Returns the analysis of the repaired file.
+pub fn apply_fixes(
+    file_path: &str,
+    output_path: Option<&str>,
+    analysis: &AnalysisResult,
+    fixes: &[FixType],
+) -> Result<AnalysisResult> {
+    apply_fixes_with(file_path, output_path, analysis, fixes, &Monitor::default())
+}
+
+/// `apply_fixes` reporting to `monitor`. Cancelling stops before the backup,
+/// before the recovered orphans are committed or during the final validation;
+/// the error then says which fixes had already been written.
+pub fn apply_fixes_with(
+    file_path: &str,
+    output_path: Option<&str>,
+    analysis: &AnalysisResult,
+    fixes: &[FixType],
+    monitor: &Monitor,
+) -> Result<AnalysisResult> {
+    if let Some(&(a, b)) = conflicting_fixes(&analysis.issues, fixes).first() {
+        let (first, second) = (&analysis.issues[a], &analysis.issues[b]);
+        bail!(
+            "Fixes for '{}' and '{}' both touch {}; apply them separately",
+            first.message, second.message, first.location.as_ref().unwrap()
+        );
+    }
+    monitor.check().map_err(|e| e.context("Repair cancelled; nothing was written"))?;
+    let target = prepare_target(file_path, output_path)?;
+    monitor.report(Phase::Repair, 0, 0);
+
+    let orphan_cells = if fixes.contains(&FixType::RecoverOrphans) { orphan_cells(analysis) } else { Vec::new() };
+
+    let changes = header_changes(analysis, fixes);
+    for (field, bytes) in &changes {
+        update_base_block(target, field.offset(), bytes)
+            .map_err(|e| anyhow!("Failed to update {}: {}", field.label().to_lowercase(), e))?;
+    }
+    let checksum = analysis.issues.iter()
+        .filter(|_| fixes.contains(&FixType::Checksum))
+        .find_map(|i| match i.fix_data {
+            Some(FixData::Checksum(checksum)) => Some(checksum),
+            _ => None,
+        });
+    if !changes.is_empty() {
+        refresh_checksum(target).map_err(|e| anyhow!("Failed to update checksum: {}", e))?;
+    } else if let Some(checksum) = checksum {
+        update_checksum(target, checksum).map_err(|e| anyhow!("Failed to update checksum: {}", e))?;
+    }
+    let header_fixed = !changes.is_empty() || checksum.is_some();
+
+    if !orphan_cells.is_empty() {
+        match recover_orphans(target, &orphan_cells, monitor) {
+            Err(e) if progress::is_cancelled(&e) => {
+                let written = if header_fixed {
+                    format!("the header fixes were already written to {}", target)
+                } else {
+                    format!("{} was not changed", target)
+                };
+                return Err(e.context(format!("Repair cancelled before orphans were recovered; {}", written)));
+            }
+            Err(e) => bail!("Failed to recover orphaned cells: {}", e),
+            Ok(_) => {}
+        }
+    }
+
+    check_registry_file_with(target, monitor).map_err(|e| {
+        if progress::is_cancelled(&e) {
+            e.context(format!("The fixes were written to {} but validating it was cancelled", target))
+        } else {
+            anyhow!("Failed to validate {}: {}", target, e)
+        }
+    })
+}
+
+/// Allocated key and value cells that nothing reachable from the root references
+#[derive(Debug, Default)]
+pub struct Orphans {
+    pub keys: Vec<KeyNode>,
+    /// Orphaned keys whose parent is not itself an orphan
+    pub top_level: Vec<u32>,
+    /// Orphaned values not listed by any orphaned key
+    pub loose_values: Vec<ValueKey>,
+    // Index into `keys` by cell offset
+    by_offset: HashMap<u32, usize>,
+    // Indices into `keys` by the offset their parent pointer names
+    by_parent: HashMap<u32, Vec<usize>>,
+}
+
+impl Orphans {
+    fn new(keys: Vec<KeyNode>, top_level: Vec<u32>, loose_values: Vec<ValueKey>) -> Self {
+        let by_offset = keys.iter().enumerate().map(|(i, k)| (k.offset, i)).collect();
+        let mut by_parent: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, key) in keys.iter().enumerate().filter(|(_, k)| k.parent != k.offset) {
+            by_parent.entry(key.parent).or_default().push(i);
+        }
+        Self { keys, top_level, loose_values, by_offset, by_parent }
+    }
+
+    /// The orphaned key at `offset`
+    pub fn key(&self, offset: u32) -> Option<&KeyNode> {
+        self.by_offset.get(&offset).map(|&i| &self.keys[i])
+    }
+
+    /// Orphaned keys whose parent pointer names `parent`
+    pub fn children(&self, parent: u32) -> Vec<&KeyNode> {
+        self.by_parent.get(&parent)
+            .map(|indices| indices.iter().map(|&i| &self.keys[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Keys and values in the subtree rebuilt from parent pointers
+    pub fn subtree_size(&self, hive: &Hive, offset: u32) -> (usize, usize) {
+        let mut keys = 0;
+        let mut values = 0;
+        let mut pending = vec![offset];
+        let mut seen = HashSet::new();
+        while let Some(current) = pending.pop() {
+            if !seen.insert(current) {
+                continue;
+            }
+            keys += 1;
+            if let Some(key) = self.key(current) {
+                values += hive.value_offsets(key).map(|v| v.len()).unwrap_or(0);
+            }
+            pending.extend(self.children(current).iter().map(|k| k.offset));
+        }
+        (keys, values)
+    }
+}
+
+/// A top-level orphan and the name it gets below `RegFix_Recovered`
+#[derive(Debug, Clone)]
+pub struct RecoveryItem {
+    pub cell: u32,
+    pub name: String,
+    pub is_key: bool,
+}
+
+/// Finds allocated nk and vk cells that are not reachable from the root key
+pub fn find_orphans(hive: &Hive) -> Orphans {
+    find_orphans_with(hive, &hive.walk_tree(), &Monitor::default()).unwrap_or_default()
+}
+
+/// `find_orphans` for a tree already walked, reporting the bins scanned
+pub fn find_orphans_with(hive: &Hive, walk: &TreeWalk, monitor: &Monitor) -> Result<Orphans> {
+    let mut keys = Vec::new();
+    let mut values = Vec::new();
+
+    let total = (hive.data().len() - BASE_BLOCK_SIZE) as u64;
+    let mut next_report = 0;
+    for bin in &hive.walk_bins().bins {
+        if bin.offset as u64 >= next_report {
+            monitor.check()?;
+            monitor.report(Phase::Orphans, bin.offset as u64, total);
+            next_report = bin.offset as u64 + REPORT_EVERY_BYTES;
+        }
+        let Ok(cells) = hive.cells_in_bin(bin) else {
+            continue;
+        };
+        for cell in cells.iter().filter(|c| c.allocated && !walk.referenced_cells.contains(&c.offset)) {
+            let Ok(data) = hive.cell_data(cell.offset) else {
+                continue;
+            };
+            match data.get(0..2) {
+                Some(b"nk") => keys.extend(Hive::parse_key(cell.offset, data).ok()),
+                Some(b"vk") => values.extend(Hive::parse_value(cell.offset, data).ok()),
+                _ => {}
+            }
+        }
+    }
+
+    let key_offsets: HashSet<u32> = keys.iter().map(|k| k.offset).collect();
+    let owned_values: HashSet<u32> = keys.iter()
+        .filter_map(|k| hive.value_offsets(k).ok())
+        .flatten()
+        .collect();
+
+    let top_level = keys.iter()
+        .filter(|k| !key_offsets.contains(&k.parent) || k.parent == k.offset)
+        .map(|k| k.offset)
+        .collect();
+    let loose_values = values.into_iter().filter(|v| !owned_values.contains(&v.offset)).collect();
+    Ok(Orphans::new(keys, top_level, loose_values))
+}
+
+fn unique_name(name: &str, cell: u32, taken: &mut HashSet<String>) -> String {
+    let mut candidate = if name.is_empty() { format!("Key_0x{:X}", cell) } else { name.to_string() };
+    if taken.contains(&candidate.to_uppercase()) {
+        candidate = format!("{}_0x{:X}", candidate, cell);
+    }
+    taken.insert(candidate.to_uppercase());
+    candidate
+}
+
+fn recovered_key(hive: &Hive) -> Option<KeyNode> {
+    let root = hive.root_key().ok()?;
+    hive.subkey_offsets(root.subkey_list).ok()?
+        .into_iter()
+        .filter_map(|offset| hive.key(offset).ok())
+        .find(|k| k.name.eq_ignore_ascii_case(RECOVERED_KEY_NAME))
+}
+
+/// Decides the name each top-level orphan gets below `RegFix_Recovered`,
+/// keeping names unique alongside anything recovered earlier
+pub fn plan_recovery(hive: &Hive, orphans: &Orphans) -> Vec<RecoveryItem> {
+    let existing = recovered_key(hive);
+    let mut key_names: HashSet<String> = existing.as_ref()
+        .and_then(|k| hive.subkey_offsets(k.subkey_list).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|offset| hive.key(offset).ok())
+        .map(|k| k.name.to_uppercase())
+        .collect();
+    let mut value_names: HashSet<String> = existing.as_ref()
+        .and_then(|k| hive.value_offsets(k).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|offset| hive.value(offset).ok())
+        .map(|v| v.name.to_uppercase())
+        .collect();
+
+    let mut plan = Vec::new();
+    for &offset in &orphans.top_level {
+        let key = orphans.key(offset).unwrap();
+        plan.push(RecoveryItem { cell: offset, name: unique_name(&key.name, offset, &mut key_names), is_key: true });
+    }
+    for value in &orphans.loose_values {
+        let name = if value.name.is_empty() {
+            format!("Value_0x{:X}", value.offset)
+        } else {
+            value.name.clone()
+        };
+        plan.push(RecoveryItem { cell: value.offset, name: unique_name(&name, value.offset, &mut value_names), is_key: false });
+    }
+    plan
+}
+
+fn orphan_issues(hive: &Hive, walk: &TreeWalk, monitor: &Monitor) -> Result<Vec<ValidationIssue>> {
+    let orphans = find_orphans_with(hive, walk, monitor)?;
+    let paths = hive.key_paths();
+    let plan = plan_recovery(hive, &orphans);
+    let mut issues: Vec<ValidationIssue> = plan.iter().take(MAX_LISTED_ERRORS)
+        .map(|item| {
+            let size = hive.cell(item.cell).map(|c| c.size).unwrap_or(0);
+            let (code, key_path, message, details) = if item.is_key {
+                let (keys, values) = orphans.subtree_size(hive, item.cell);
+                let key = orphans.key(item.cell).unwrap();
+                let original = &key.name;
+                (
+                    IssueCode::OrphanedKey,
+                    paths.get(&key.parent).map(|parent| hive::join_path(parent, original)),
+                    format!("Orphaned key '{}' can be recovered", original),
+                    format!(
+                        "Unreferenced key at 0x{:X} ({} key(s), {} value(s)) will be reattached as {}\\{}",
+                        item.cell, keys, values, RECOVERED_KEY_NAME, item.name
+                    ),
+                )
+            } else {
+                (
+                    IssueCode::OrphanedValue,
+                    None,
+                    format!("Orphaned value '{}' can be recovered", item.name),
+                    format!(
+                        "Unreferenced value at 0x{:X} will be attached to {} as '{}'",
+                        item.cell, RECOVERED_KEY_NAME, item.name
+                    ),
+                )
+            };
+            ValidationIssue {
+                code,
+                severity: IssueSeverity::Warning,
+                message,
+                details: Some(details),
+                location: Some(IssueLocation::cell(item.cell, size, key_path)),
+                fix_type: Some(FixType::RecoverOrphans),
+                fix_data: Some(FixData::OrphanedCell(item.cell)),
+            }
+        })
+        .collect();
+    if plan.len() > MAX_LISTED_ERRORS {
+        let rest = &plan[MAX_LISTED_ERRORS..];
+        let keys = rest.iter().filter(|item| item.is_key).count();
+        issues.push(ValidationIssue {
+            code: if keys > 0 { IssueCode::OrphanedKey } else { IssueCode::OrphanedValue },
+            severity: IssueSeverity::Warning,
+            message: format!("{} more orphaned key(s) and value(s) can be recovered", rest.len()),
+            details: Some(format!(
+                "{} unreferenced key(s) and {} unreferenced value(s) will be reattached below {}",
+                keys, rest.len() - keys, RECOVERED_KEY_NAME
+            )),
+            location: None,
+            fix_type: Some(FixType::RecoverOrphans),
+            fix_data: Some(FixData::OrphanedCells(rest.iter().map(|item| item.cell).collect())),
+        });
+    }
+    Ok(issues)
+}
+
+/// Reattaches orphaned keys and values below a `RegFix_Recovered` key under
+/// the root. Relationships inside each orphaned subtree are rebuilt from the
+/// keys' parent pointers. Only orphans whose cell is listed in `cells` are
+/// recovered. Returns the paths that were attached. Nothing is written if
+/// `monitor` cancels the search for orphans.
+pub fn recover_orphans(file_path: &str, cells: &[u32], monitor: &Monitor) -> Result<Vec<String>> {
+    ensure_writable()?;
+    let mut hive = Hive::open(file_path)?;
+    let attached = recover_orphans_in(&mut hive, cells, monitor)?;
+    if !attached.is_empty() {
+        commit_hive(file_path, &hive)?;
+    }
+    Ok(attached)
+}
+
+/// `recover_orphans` on a hive in memory; the base block is left for
+/// `commit_hive` or `committed_header` to update
+pub fn recover_orphans_in(hive: &mut Hive, cells: &[u32], monitor: &Monitor) -> Result<Vec<String>> {
+    let walk = hive.walk_tree_with(&mut |_| !monitor.is_cancelled()).ok_or(progress::Cancelled)?;
+    let orphans = find_orphans_with(hive, &walk, monitor)?;
+    let cells: HashSet<u32> = cells.iter().copied().collect();
+    let plan: Vec<RecoveryItem> = plan_recovery(hive, &orphans).into_iter()
+        .filter(|item| cells.contains(&item.cell))
+        .collect();
+    if plan.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let root = hive.root_key()?;
+    let loose_values: HashMap<u32, &ValueKey> = orphans.loose_values.iter().map(|v| (v.offset, v)).collect();
+
+    // Rebuild each recovered subtree from parent pointers. Replaced subkey
+    // lists are freed afterwards unless a live key or a kept list uses them.
+    let mut pending: Vec<u32> = plan.iter().filter(|i| i.is_key).map(|i| i.cell).collect();
+    let mut seen = HashSet::new();
+    let mut replaced_lists = Vec::new();
+    let mut kept_lists = HashSet::new();
+    while let Some(offset) = pending.pop() {
+        if !seen.insert(offset) {
+            continue;
+        }
+        let key = orphans.key(offset).unwrap();
+        let children: Vec<(u32, String)> = orphans.children(offset).iter()
+            .map(|k| (k.offset, k.name.clone()))
+            .collect();
+        let listed: HashSet<u32> = hive.subkey_offsets(key.subkey_list).unwrap_or_default().into_iter().collect();
+        let wanted: HashSet<u32> = children.iter().map(|(o, _)| *o).collect();
+
+        if listed != wanted || key.subkey_count as usize != wanted.len() {
+            replaced_lists.extend(edit::subkey_list_cells(hive, key));
+            if children.is_empty() {
+                hive.set_cell_u32(offset, 20, 0);
+                hive.set_cell_u32(offset, 28, NO_CELL);
+            } else {
+                let list = hive::write_subkey_list(&children, |payload| hive.allocate_cell(payload))?;
+                hive.set_cell_u32(offset, 20, children.len() as u32);
+                hive.set_cell_u32(offset, 28, list);
+            }
+        } else {
+            kept_lists.extend(edit::subkey_list_cells(hive, key));
+        }
+        // Volatile subkeys never survive on disk
+        hive.set_cell_u32(offset, 24, 0);
+        hive.set_cell_u32(offset, 32, NO_CELL);
+
+        let values_ok = hive.value_offsets(key)
+            .map(|offsets| offsets.iter().all(|v| hive.value(*v).is_ok()))
+            .unwrap_or(false);
+        if key.value_count > 0 && !values_ok {
+            hive.set_cell_u32(offset, 36, 0);
+            hive.set_cell_u32(offset, 40, NO_CELL);
+        }
+        if key.security == NO_CELL || hive.cell_data(key.security).map(|d| d.get(0..2) != Some(b"sk")).unwrap_or(true) {
+            hive.set_cell_u32(offset, 44, root.security);
+            edit::add_security_reference(hive, root.security)?;
+        }
+        pending.extend(wanted);
+    }
+    for list in replaced_lists {
+        if !kept_lists.contains(&list) && !walk.referenced_cells.contains(&list) {
+            edit::free_if_cell(hive, list)?;
+        }
+    }
+
+    // Find or create RegFix_Recovered below the root
+    let (recovered, mut subkeys, mut values, created) = match recovered_key(hive) {
+        Some(existing) => {
+            let subkeys: Vec<(u32, String)> = hive.subkey_offsets(existing.subkey_list).unwrap_or_default()
+                .into_iter()
+                .filter_map(|o| hive.key(o).ok().map(|k| (o, k.name)))
+                .collect();
+            let values = hive.value_offsets(&existing).unwrap_or_default();
+            (existing.offset, subkeys, values, false)
+        }
+        None => {
+            let record = hive::build_key_node(RECOVERED_KEY_NAME, root.offset, root.security, root.last_written);
+            let offset = hive.allocate_cell(&record)?;
+            edit::add_security_reference(hive, root.security)?;
+            (offset, Vec::new(), Vec::new(), true)
+        }
+    };
+
+    let mut attached = Vec::new();
+    for item in &plan {
+        if item.is_key {
+            let original = orphans.key(item.cell).unwrap();
+            let mut cell = item.cell;
+            if original.name != item.name {
+                cell = edit::rename_record(hive, item.cell, &item.name, true)?;
+                for child in orphans.children(item.cell) {
+                    hive.set_cell_u32(child.offset, 16, cell);
+                }
+            }
+            hive.set_cell_u32(cell, 16, recovered);
+            edit::widen_max_name(hive, recovered, &item.name)?;
+            subkeys.push((cell, item.name.clone()));
+            attached.push(format!("{}\\{}", RECOVERED_KEY_NAME, item.name));
+        } else {
+            let original = loose_values[&item.cell];
+            let cell = if original.name != item.name {
+                edit::rename_record(hive, item.cell, &item.name, false)?
+            } else {
+                item.cell
+            };
+            values.push(cell);
+            attached.push(format!("{}\\@{}", RECOVERED_KEY_NAME, item.name));
+        }
+    }
+
+    let recovered_node = hive.key(recovered)?;
+    if !subkeys.is_empty() {
+        let list = hive::write_subkey_list(&subkeys, |payload| hive.allocate_cell(payload))?;
+        for old in edit::subkey_list_cells(hive, &recovered_node) {
+            edit::free_if_cell(hive, old)?;
+        }
+        hive.set_cell_u32(recovered, 20, subkeys.len() as u32);
+        hive.set_cell_u32(recovered, 28, list);
+    }
+    if !values.is_empty() {
+        let list: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let list = hive.allocate_cell(&list)?;
+        if recovered_node.value_list != NO_CELL {
+            edit::free_if_cell(hive, recovered_node.value_list)?;
+        }
+        hive.set_cell_u32(recovered, 36, values.len() as u32);
+        hive.set_cell_u32(recovered, 40, list);
+    }
+
+    if created {
+        // Insert RegFix_Recovered into the root's subkey list
+        let mut entries: Vec<(u32, String)> = hive.subkey_offsets(root.subkey_list).unwrap_or_default()
+            .into_iter()
+            .filter_map(|o| hive.key(o).ok().map(|k| (o, k.name)))
+            .collect();
+        entries.push((recovered, RECOVERED_KEY_NAME.to_string()));
+
+        let old_lists = edit::subkey_list_cells(hive, &root);
+        let list = hive::write_subkey_list(&entries, |payload| hive.allocate_cell(payload))?;
+        for old in old_lists {
+            edit::free_if_cell(hive, old)?;
+        }
+        hive.set_cell_u32(root.offset, 20, entries.len() as u32);
+        hive.set_cell_u32(root.offset, 28, list);
+        edit::widen_max_name(hive, root.offset, RECOVERED_KEY_NAME)?;
+    }
+    Ok(attached)
+}
+
+/// Suggested file name for a repaired copy of `file_path`
+pub fn default_repaired_path(file_path: &str) -> String {
+    let path = Path::new(file_path);
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}.repaired", name)).to_string_lossy().to_string()
+}
+
+// Adds the bin, key tree and orphan issues to those found in the header
+fn structure_issues(hive: &Hive, issues: &mut Vec<ValidationIssue>, monitor: &Monitor) -> Result<()> {
+    let total = (hive.data().len() - BASE_BLOCK_SIZE) as u64;
+    monitor.check()?;
+    let bins = hive.walk_bins();
+    if let Some(error) = bins.error {
+        issues.push(ValidationIssue {
+            code: IssueCode::DamagedBinChain,
+            severity: IssueSeverity::Critical,
+            message: "Hive bin chain is damaged".to_string(),
+            details: Some(format!("{}. Data after this point cannot be walked.", error)),
+            location: Some(IssueLocation::header(BASE_BLOCK_SIZE as u64 + bins.valid_bytes as u64, 32)),
+            fix_type: None,
+            fix_data: None,
+        });
+    }
+
+    monitor.found(issues.len());
+    monitor.report(Phase::Bins, bins.valid_bytes as u64, total);
+
+    let earlier = issues.len();
+    let tree = hive.walk_tree_with(&mut |walk| {
+        if walk.keys % REPORT_EVERY_KEYS == 0 {
+            monitor.found(earlier + walk.errors.len());
+            monitor.report(Phase::KeyTree, walk.keys as u64, 0);
+        }
+        !monitor.is_cancelled()
+    });
+    let tree = tree.ok_or(progress::Cancelled)?;
+    if !tree.errors.is_empty() {
+        let paths = hive.key_paths();
+        for error in tree.errors.iter().take(MAX_LISTED_ERRORS) {
+            let key_path = paths.get(&error.key).cloned();
+            // Point at the broken cell if it can be sized, otherwise at the key referencing it
+            let location = match hive.cell(error.cell) {
+                Ok(cell) => Some(IssueLocation::cell(error.cell, cell.size, key_path)),
+                Err(_) => hive.cell(error.key).ok().map(|cell| IssueLocation::cell(error.key, cell.size, key_path)),
+            };
+            issues.push(ValidationIssue {
+                code: IssueCode::BrokenReference,
+                severity: IssueSeverity::Critical,
+                message: "Key tree contains a broken reference".to_string(),
+                details: Some(error.to_string()),
+                location,
+                fix_type: None,
+                fix_data: None,
+            });
+        }
+        if tree.errors.len() > MAX_LISTED_ERRORS {
+            let rest: Vec<String> = tree.errors[MAX_LISTED_ERRORS..].iter().map(|e| e.to_string()).collect();
+            issues.push(ValidationIssue {
+                code: IssueCode::BrokenReference,
+                severity: IssueSeverity::Critical,
+                message: format!("Key tree contains {} more broken reference(s)", rest.len()),
+                details: Some(rest.join("\n")),
+                location: None,
+                fix_type: None,
+                fix_data: None,
+            });
+        }
+    }
+
+    monitor.found(issues.len());
+    issues.extend(orphan_issues(hive, &tree, monitor)?);
+    Ok(())
+}
+
 pub fn check_registry_file(file_path: &str) -> Result<AnalysisResult> {
+    check_registry_file_with(file_path, &Monitor::default())
+}
+
+/// `check_registry_file` reporting each phase to `monitor`, which can cancel
+/// it between steps
+pub fn check_registry_file_with(file_path: &str, monitor: &Monitor) -> Result<AnalysisResult> {
+    monitor.check()?;
     let file = File::open(file_path)?;
     let file_size = file.metadata()?.len() as u32;
+    let base_offset = 4096; // 0x1000
+    if file_size < base_offset {
+        bail!("File is too small to be a registry hive ({} bytes)", file_size);
+    }
     let mmap = unsafe { MmapOptions::new().map(&file)? };
 
     let mut issues = Vec::new();
-    let base_offset = 4096; // 0x1000
 
     // Extract all header fields
-    let signature = std::str::from_utf8(&mmap[0..4])?.to_string();
+    let signature = String::from_utf8_lossy(&mmap[0..4]).to_string();
     let primary_seq_num = u32::from_le_bytes(mmap[4..8].try_into()?);
     let secondary_seq_num = u32::from_le_bytes(mmap[8..12].try_into()?);
     let last_written = u64::from_le_bytes(mmap[12..20].try_into()?);
@@ -89,9 +853,11 @@ pub fn check_registry_file(file_path: &str) -> Result<AnalysisResult> {
     // Validate signature
     if signature != "regf" {
         issues.push(ValidationIssue {
+            code: IssueCode::InvalidSignature,
             severity: IssueSeverity::Critical,
             message: format!("Invalid signature: expected 'regf', found '{}'", signature),
             details: Some("The registry file signature is invalid, indicating severe corruption".to_string()),
+            location: Some(IssueLocation::header(0, 4)),
             fix_type: None,
             fix_data: None,
         });
@@ -100,12 +866,14 @@ pub fn check_registry_file(file_path: &str) -> Result<AnalysisResult> {
     // Validate checksum
     if stored_checksum != calculated_checksum {
         issues.push(ValidationIssue {
+            code: IssueCode::ChecksumMismatch,
             severity: IssueSeverity::Critical,
             message: "Header checksum mismatch".to_string(),
             details: Some(format!(
                 "Stored: 0x{:08X}, Calculated: 0x{:08X}",
                 stored_checksum, calculated_checksum
             )),
+            location: Some(IssueLocation::header(508, 4)),
             fix_type: Some(FixType::Checksum),
             fix_data: Some(FixData::Checksum(calculated_checksum)),
         });
@@ -114,12 +882,14 @@ pub fn check_registry_file(file_path: &str) -> Result<AnalysisResult> {
     // Validate hive bins size
     if hive_bins_size != measured_hive_bins_size {
         issues.push(ValidationIssue {
+            code: IssueCode::HiveBinsSizeMismatch,
             severity: IssueSeverity::Warning,
             message: "Hive bins size mismatch".to_string(),
             details: Some(format!(
                 "Stored: {} bytes, Measured: {} bytes",
                 hive_bins_size, measured_hive_bins_size
             )),
+            location: Some(IssueLocation::header(40, 4)),
             fix_type: Some(FixType::HiveBinsSize),
             fix_data: Some(FixData::HiveBinsSize(measured_hive_bins_size)),
         });
@@ -128,17 +898,29 @@ pub fn check_registry_file(file_path: &str) -> Result<AnalysisResult> {
     // Validate sequence numbers
     if primary_seq_num != secondary_seq_num {
         issues.push(ValidationIssue {
+            code: IssueCode::SequenceMismatch,
             severity: IssueSeverity::Warning,
             message: "Sequence numbers do not match".to_string(),
             details: Some(format!(
                 "Primary: {}, Secondary: {}. This may indicate an incomplete write operation.",
                 primary_seq_num, secondary_seq_num
             )),
+            location: Some(IssueLocation::header(4, 8)),
             fix_type: Some(FixType::SequenceNumbers),
             fix_data: Some(FixData::SequenceNumbers(primary_seq_num, primary_seq_num)),
         });
     }
 
+    monitor.found(issues.len());
+    monitor.report(Phase::Header, base_offset as u64, file_size as u64);
+
+    // Structural checks need a readable base block
+    if signature == "regf" {
+        if let Ok(hive) = Hive::open(file_path) {
+            structure_issues(&hive, &mut issues, monitor)?;
+        }
+    }
+
     // Create FileInfo structure
     let file_info = FileInfo {
         path: file_path.to_string(),
@@ -164,3 +946,43 @@ pub fn check_registry_file(file_path: &str) -> Result<AnalysisResult> {
         file_info,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rebuild::{self, SalvagedKey};
+
+    #[test]
+    fn recovered_orphans_get_fresh_lists_and_the_old_ones_are_freed() {
+        let mut lost = SalvagedKey::named("Lost");
+        lost.subkeys = vec![SalvagedKey::named("A"), SalvagedKey::named("B")];
+        let mut root = SalvagedKey::named("ROOT");
+        root.subkeys.push(lost);
+        let mut hive = rebuild::build_test_hive(&root);
+        let root = hive.root_cell_offset();
+        let lost = hive.find_key("Lost").unwrap();
+        let b = hive.find_key("Lost\\B").unwrap();
+
+        // Lost drops out of the tree and B's parent pointer no longer names it
+        edit::replace_subkey_list(&mut hive, root, &[]).unwrap();
+        hive.set_cell_u32(b.offset, 16, root);
+
+        let orphans = find_orphans(&hive);
+        assert_eq!(orphans.children(lost.offset).len(), 1);
+        let cells: Vec<u32> = plan_recovery(&hive, &orphans).iter().map(|item| item.cell).collect();
+        recover_orphans_in(&mut hive, &cells, &Monitor::default()).unwrap();
+
+        assert!(hive.walk_tree().errors.is_empty());
+        let recovered = hive.find_key(&format!("{}\\Lost", RECOVERED_KEY_NAME)).unwrap();
+        let names: Vec<String> = hive.subkeys(&recovered).unwrap().into_iter().map(|k| k.name).collect();
+        assert_eq!(names, vec!["A"]);
+        assert!(hive.find_key(&format!("{}\\B", RECOVERED_KEY_NAME)).is_ok());
+        // Nothing is left allocated that the tree does not reach
+        let walk = hive.walk_tree();
+        for bin in &hive.walk_bins().bins {
+            for cell in hive.cells_in_bin(bin).unwrap().iter().filter(|c| c.allocated) {
+                assert!(walk.referenced_cells.contains(&cell.offset), "cell 0x{:X} leaked", cell.offset);
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use crate::audit::{self, AuditLog};
+use crate::types::*;
+use anyhow::{bail, Result};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const CONFIG_HIVES: [&str; 5] = ["SYSTEM", "SOFTWARE", "SAM", "SECURITY", "DEFAULT"];
+const LOG_EXTENSIONS: [&str; 3] = ["LOG", "LOG1", "LOG2"];
+
+/// A hive found below a Windows root together with its transaction logs
+#[derive(Debug, Clone)]
+pub struct DiscoveredHive {
+    pub name: String,
+    pub path: PathBuf,
+    pub logs: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct ScanEntry {
+    pub hive: DiscoveredHive,
+    pub result: Result<AnalysisResult>,
+}
+
+/// Finds `name` inside `dir` ignoring case, as NTFS lookups do on Windows
+fn find_entry(dir: &Path, name: &str) -> Option<PathBuf> {
+    let exact = dir.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| entry.file_name().to_string_lossy().eq_ignore_ascii_case(name))
+        .map(|entry| entry.path())
+}
+
+fn find_path(base: &Path, components: &[&str]) -> Option<PathBuf> {
+    components.iter().try_fold(base.to_path_buf(), |dir, name| find_entry(&dir, name))
+}
+
+fn list_dirs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+fn is_log_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| LOG_EXTENSIONS.iter().any(|log| ext.to_string_lossy().eq_ignore_ascii_case(log)))
+        .unwrap_or(false)
+}
+
+/// Returns true when the file starts with a "regf" base block describing a
+/// primary hive (transaction logs share the signature but not the file type)
+pub fn is_primary_hive(path: &Path) -> bool {
+    let mut header = [0u8; 32];
+    let read_ok = File::open(path).and_then(|mut file| file.read_exact(&mut header)).is_ok();
+    read_ok
+        && &header[0..4] == b"regf"
+        && u32::from_le_bytes(header[28..32].try_into().unwrap()) == 0
+        && !is_log_file(path)
+}
+
+/// Transaction logs stored next to a hive (`SYSTEM.LOG1`, `NTUSER.DAT.LOG2`, ...)
+pub fn find_logs(hive_path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (hive_path.parent(), hive_path.file_name()) else {
+        return Vec::new();
+    };
+    let name = name.to_string_lossy();
+    let mut logs: Vec<PathBuf> = LOG_EXTENSIONS.iter()
+        .filter_map(|ext| find_entry(dir, &format!("{}.{}", name, ext)))
+        .collect();
+    logs.dedup();
+    logs
+}
+
+/// Accepts either the Windows directory or the root of the mounted volume and
+/// returns `(windows_dir, volume_root)`
+fn resolve_roots(root: &Path) -> Result<(PathBuf, PathBuf)> {
+    if let Some(windows) = find_entry(root, "Windows").filter(|p| find_path(p, &["System32", "config"]).is_some()) {
+        return Ok((windows, root.to_path_buf()));
+    }
+    if find_path(root, &["System32", "config"]).is_some() {
+        let volume = root.parent().map(Path::to_path_buf).unwrap_or_else(|| root.to_path_buf());
+        return Ok((root.to_path_buf(), volume));
+    }
+    bail!("{} does not look like a Windows installation (no System32/config found)", root.display());
+}
+
+/// Discovers every hive of a mounted Windows installation, first by well
+/// known path and then by sniffing the "regf" signature of the remaining
+/// files in the hive directories.
+pub fn discover_hives(root: &Path) -> Result<Vec<DiscoveredHive>> {
+    let (windows, volume) = resolve_roots(root)?;
+    let mut candidates: Vec<(String, PathBuf)> = Vec::new();
+    let mut sniff_dirs: Vec<PathBuf> = Vec::new();
+
+    if let Some(config) = find_path(&windows, &["System32", "config"]) {
+        for hive in CONFIG_HIVES {
+            if let Some(path) = find_entry(&config, hive) {
+                candidates.push((hive.to_string(), path));
+            }
+        }
+        if let Some(regback) = find_entry(&config, "RegBack") {
+            for hive in CONFIG_HIVES {
+                if let Some(path) = find_entry(&regback, hive) {
+                    candidates.push((format!("RegBack\\{}", hive), path));
+                }
+            }
+            sniff_dirs.push(regback);
+        }
+        sniff_dirs.push(config);
+    }
+
+    if let Some(amcache) = find_path(&windows, &["appcompat", "Programs", "Amcache.hve"]) {
+        candidates.push(("Amcache.hve".to_string(), amcache));
+    }
+
+    let mut profile_dirs: Vec<PathBuf> = Vec::new();
+    if let Some(users) = find_entry(&volume, "Users") {
+        profile_dirs.extend(list_dirs(&users));
+    }
+    if let Some(service_profiles) = find_entry(&windows, "ServiceProfiles") {
+        profile_dirs.extend(list_dirs(&service_profiles));
+    }
+    for profile in profile_dirs {
+        let user = profile.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if let Some(ntuser) = find_entry(&profile, "NTUSER.DAT") {
+            candidates.push((format!("{}\\NTUSER.DAT", user), ntuser));
+        }
+        if let Some(usrclass) = find_path(&profile, &["AppData", "Local", "Microsoft", "Windows", "UsrClass.dat"]) {
+            candidates.push((format!("{}\\UsrClass.dat", user), usrclass));
+        }
+    }
+
+    // Anything else in the hive directories that carries a primary hive header
+    for dir in sniff_dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect();
+        files.sort();
+        for path in files {
+            if candidates.iter().any(|(_, known)| known == &path) || !is_primary_hive(&path) {
+                continue;
+            }
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let prefix = if dir.file_name().map(|n| n.eq_ignore_ascii_case("RegBack")).unwrap_or(false) {
+                "RegBack\\"
+            } else {
+                ""
+            };
+            candidates.push((format!("{}{}", prefix, name), path));
+        }
+    }
+
+    Ok(candidates.into_iter()
+        .filter(|(_, path)| path.is_file())
+        .map(|(name, path)| DiscoveredHive {
+            logs: find_logs(&path),
+            name,
+            path,
+        })
+        .collect())
+}
+
+/// Runs `check_registry_file` on every hive using one worker per CPU.
+/// Results are returned in discovery order.
+pub fn scan_hives(hives: Vec<DiscoveredHive>, audit_log: Option<&AuditLog>) -> Vec<ScanEntry> {
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+        .min(hives.len().max(1));
+    let queue = Mutex::new(hives.into_iter().enumerate());
+    let results = Mutex::new(Vec::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let Some((index, hive)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let result = audit::analyze(&hive.path.to_string_lossy(), audit_log)
+                    .map(|(analysis, _)| analysis);
+                results.lock().unwrap().push((index, ScanEntry { hive, result }));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, entry)| entry).collect()
+}
+
+pub fn print_summary(entries: &[ScanEntry]) {
+    let mut rows = vec![[
+        "Hive".to_string(),
+        "Status".to_string(),
+        "Size".to_string(),
+        "Seq (P/S)".to_string(),
+        "Checksum".to_string(),
+        "Critical".to_string(),
+        "Warnings".to_string(),
+        "Logs".to_string(),
+        "Path".to_string(),
+    ]];
+
+    for entry in entries {
+        let logs = if entry.hive.logs.is_empty() {
+            "-".to_string()
+        } else {
+            entry.hive.logs.iter()
+                .filter_map(|log| log.extension().map(|ext| ext.to_string_lossy().to_string()))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let path = entry.hive.path.display().to_string();
+
+        rows.push(match &entry.result {
+            Ok(result) => {
+                let info = &result.file_info;
+                let critical = result.issues.iter().filter(|i| i.severity == IssueSeverity::Critical).count();
+                let warnings = result.issues.len() - critical;
+                [
+                    entry.hive.name.clone(),
+                    if result.issues.is_empty() { "OK" } else { "ISSUES" }.to_string(),
+                    info.size.to_string(),
+                    format!("{}/{}", info.primary_seq_num, info.secondary_seq_num),
+                    if info.stored_checksum == info.calculated_checksum { "OK" } else { "BAD" }.to_string(),
+                    critical.to_string(),
+                    warnings.to_string(),
+                    logs,
+                    path,
+                ]
+            }
+            Err(_) => [
+                entry.hive.name.clone(),
+                "ERROR".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                logs,
+                path,
+            ],
+        });
+    }
+
+    let mut widths = [0usize; 9];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for (i, row) in rows.iter().enumerate() {
+        let line: Vec<String> = row.iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+        if i == 0 {
+            println!("{}", widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("  "));
+        }
+    }
+
+    for entry in entries {
+        if let Err(e) = &entry.result {
+            println!("\n{}: {}", entry.hive.name, e);
+        }
+    }
+
+    let failed = entries.iter()
+        .filter(|e| e.result.as_ref().map(|r| !r.issues.is_empty()).unwrap_or(true))
+        .count();
+    println!("\n{} hive(s) scanned, {} with issues or errors.", entries.len(), failed);
+}
@@ -0,0 +1,550 @@
+use crate::audit::{self, AuditLog, FileHashes};
+use crate::browse::{HiveBrowser, KeyEntry, KeyHealth};
+use crate::guide;
+use crate::hive;
+use crate::registry;
+use crate::types::{AnalysisResult, FileInfo, FixRisk, IssueSeverity};
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Flex, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Clear, List, ListItem, ListState, Paragraph, Row, Table, Tabs, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashSet;
+
+/// Rows of the file information table, borders included
+const INFO_HEIGHT: u16 = 9;
+/// Rows of the box describing the highlighted issue, borders included
+const DETAILS_HEIGHT: u16 = 6;
+/// Share of the screen width and height taken by a dialog
+const DIALOG_PERCENT: u16 = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum View {
+    Issues,
+    Keys,
+}
+
+/// Window shown over the views until it is answered or closed
+enum Dialog {
+    /// Indices of the issues whose fixes are about to be written
+    Confirm(Vec<usize>),
+    /// Outcome of a repair, the validation of its result and the next steps
+    Outcome(Vec<Line<'static>>),
+}
+
+struct App {
+    path: String,
+    output_path: Option<String>,
+    audit_log: Option<AuditLog>,
+    analysis: Option<AnalysisResult>,
+    /// Whether each analysed issue is ticked for fixing
+    selections: Vec<bool>,
+    issues: ListState,
+    browser: Result<HiveBrowser, String>,
+    /// Offsets of the keys whose subkeys are listed
+    expanded: HashSet<u32>,
+    keys: ListState,
+    view: View,
+    dialog: Option<Dialog>,
+    dialog_scroll: u16,
+    /// Fixes confirmed but not yet written, applied after the next redraw
+    pending: Option<Vec<usize>>,
+    status: String,
+    quit: bool,
+}
+
+/// Runs the terminal front end on `file_path` until the user quits. Fixes
+/// go through the same `registry::apply_fixes` as the GUI and the command
+/// line, to `output_path` if given and otherwise in place after a `.backup`.
+pub fn run(file_path: &str, output_path: Option<&str>, audit_log: Option<&AuditLog>) -> Result<()> {
+    let mut app = App {
+        path: file_path.to_string(),
+        output_path: output_path.map(str::to_string),
+        audit_log: audit_log.cloned(),
+        analysis: None,
+        selections: Vec::new(),
+        issues: ListState::default(),
+        browser: Err("Not read yet".to_string()),
+        expanded: HashSet::new(),
+        keys: ListState::default(),
+        view: View::Issues,
+        dialog: None,
+        dialog_scroll: 0,
+        pending: None,
+        status: format!("Analyzing {}...", file_path),
+        quit: false,
+    };
+
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> Result<()> {
+    terminal.draw(|frame| draw(frame, app))?;
+    app.load();
+    if app.analysis.is_some() {
+        app.status = "Analysis complete.".to_string();
+    }
+
+    while !app.quit {
+        terminal.draw(|frame| draw(frame, app))?;
+        if let Some(selected) = app.pending.take() {
+            app.apply(&selected);
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            // Windows also reports key releases
+            if key.kind == KeyEventKind::Press {
+                app.handle_key(key.code);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl App {
+    /// Analyses the hive and reopens its key tree, as after every repair in the GUI
+    fn load(&mut self) {
+        match audit::analyze(&self.path, self.audit_log.as_ref()) {
+            Ok((analysis, _)) => {
+                self.selections = vec![false; analysis.issues.len()];
+                self.issues.select((!analysis.issues.is_empty()).then_some(0));
+                self.analysis = Some(analysis);
+            }
+            Err(e) => {
+                self.analysis = None;
+                self.selections.clear();
+                self.issues.select(None);
+                self.status = format!("Analysis failed: {}", e);
+            }
+        }
+        self.browser = HiveBrowser::open(&self.path).map_err(|e| e.to_string());
+        self.expanded.clear();
+        if let Ok(browser) = &self.browser {
+            self.expanded.insert(browser.root.offset);
+        }
+        self.keys.select(Some(0));
+    }
+
+    fn handle_key(&mut self, code: KeyCode) {
+        match self.dialog.take() {
+            Some(Dialog::Confirm(selected)) => match code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => {
+                    self.pending = Some(selected);
+                    self.status = "Applying fixes...".to_string();
+                }
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                    self.status = "Nothing was written.".to_string();
+                }
+                _ => self.dialog = Some(Dialog::Confirm(selected)),
+            },
+            Some(Dialog::Outcome(lines)) => match code {
+                KeyCode::Enter | KeyCode::Esc => {}
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.dialog_scroll = self.dialog_scroll.saturating_sub(1);
+                    self.dialog = Some(Dialog::Outcome(lines));
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.dialog_scroll = self.dialog_scroll.saturating_add(1);
+                    self.dialog = Some(Dialog::Outcome(lines));
+                }
+                _ => self.dialog = Some(Dialog::Outcome(lines)),
+            },
+            None => match code {
+                KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                KeyCode::Tab | KeyCode::BackTab => {
+                    self.view = if self.view == View::Issues { View::Keys } else { View::Issues };
+                }
+                _ if self.view == View::Issues => self.handle_issue_key(code),
+                _ => self.handle_browser_key(code),
+            },
+        }
+    }
+
+    fn handle_issue_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => self.issues.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.issues.select_next(),
+            KeyCode::Char(' ') => {
+                if let Some(i) = self.issues.selected().filter(|&i| self.is_fixable(i)) {
+                    self.selections[i] = !self.selections[i];
+                }
+            }
+            KeyCode::Char('a') => {
+                for i in 0..self.selections.len() {
+                    self.selections[i] = self.is_fixable(i);
+                }
+            }
+            KeyCode::Char('n') => self.selections.fill(false),
+            KeyCode::Enter | KeyCode::Char('f') => self.confirm(),
+            _ => {}
+        }
+    }
+
+    fn handle_browser_key(&mut self, code: KeyCode) {
+        let rows = self.key_rows();
+        let Some(current) = self.keys.selected().and_then(|i| rows.get(i)) else {
+            return;
+        };
+        let (depth, entry) = current.clone();
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => self.keys.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') if self.keys.selected().is_some_and(|i| i + 1 < rows.len()) => {
+                self.keys.select_next();
+            }
+            KeyCode::Right | KeyCode::Enter | KeyCode::Char('l') if entry.has_subkeys => {
+                self.expanded.insert(entry.offset);
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                if self.expanded.remove(&entry.offset) {
+                    return;
+                }
+                // Jump to the parent, the nearest shallower row above
+                let index = self.keys.selected().unwrap_or(0);
+                if let Some(parent) = rows[..index].iter().rposition(|(d, _)| *d < depth) {
+                    self.keys.select(Some(parent));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn is_fixable(&self, index: usize) -> bool {
+        self.analysis.as_ref().and_then(|a| a.issues.get(index)).is_some_and(|i| i.fix_type.is_some())
+    }
+
+    fn confirm(&mut self) {
+        let selected: Vec<usize> = (0..self.selections.len()).filter(|&i| self.selections[i]).collect();
+        if registry::is_read_only() {
+            self.status = "Read-only mode: fixes cannot be applied.".to_string();
+        } else if selected.is_empty() {
+            self.status = "Tick the fixes to apply with Space, or all of them with a.".to_string();
+        } else {
+            self.dialog = Some(Dialog::Confirm(selected));
+        }
+    }
+
+    /// Writes the selected fixes the way the GUI's fix dialog does, records
+    /// them in the audit log and shows the outcome with the next steps
+    fn apply(&mut self, selected: &[usize]) {
+        let Some(analysis) = &self.analysis else {
+            return;
+        };
+        let analysis = registry::select_issues(analysis, selected);
+        let fixes = registry::fix_types(&analysis);
+        let output_path = self.output_path.as_deref();
+        let target = output_path.unwrap_or(&self.path).to_string();
+
+        let before = self.audit_log.as_ref().map(|_| audit::hash_file(&self.path));
+        let result = registry::apply_fixes(&self.path, output_path, &analysis, &fixes);
+        let mut outcome = match &result {
+            Ok(_) if output_path.is_some() => {
+                format!("Fixes for {} issue(s) applied to repaired copy {}.", selected.len(), target)
+            }
+            Ok(_) => format!("Fixes for {} issue(s) applied successfully.", selected.len()),
+            Err(e) => e.to_string(),
+        };
+        if let (Some(log), Some(before)) = (&self.audit_log, before) {
+            let hashes = before.map(|before| FileHashes { before, after: audit::hash_file(&target).ok() });
+            let logged = hashes.and_then(|hashes| log.record_fix(&self.path, output_path, &fixes, &hashes, &outcome));
+            if let Err(e) = logged {
+                outcome = format!("{} (audit log failed: {})", outcome, e);
+            }
+        }
+
+        let mut lines = vec![Line::from(outcome.clone())];
+        if let Ok(repaired) = &result {
+            lines.push(Line::default());
+            lines.push(Line::styled(
+                format!("Validation of {}: {} issue(s) remaining", target, repaired.issues.len()),
+                Style::new().add_modifier(Modifier::BOLD),
+            ));
+            for issue in &repaired.issues {
+                lines.push(Line::from(format!("  {}: {} [{}]", issue.severity, issue.message, issue.code)));
+            }
+            lines.push(Line::default());
+            lines.push(Line::styled("Next steps:", Style::new().add_modifier(Modifier::BOLD)));
+            for (i, step) in guide::next_steps(&self.path, repaired, true).iter().enumerate() {
+                lines.push(Line::from(format!("  {}. {}", i + 1, step)));
+            }
+        }
+        self.dialog = Some(Dialog::Outcome(lines));
+        self.dialog_scroll = 0;
+
+        self.load();
+        self.status = outcome;
+    }
+
+    /// Keys shown in the browser, depth first, each with its depth
+    fn key_rows(&mut self) -> Vec<(usize, KeyEntry)> {
+        let Ok(browser) = &mut self.browser else {
+            return Vec::new();
+        };
+        let mut rows = Vec::new();
+        let mut stack = vec![(0, browser.root.clone())];
+        while let Some((depth, entry)) = stack.pop() {
+            if self.expanded.contains(&entry.offset) && depth < hive::MAX_DEPTH {
+                for child in browser.contents(&entry).subkeys.iter().rev() {
+                    stack.push((depth + 1, child.clone()));
+                }
+            }
+            rows.push((depth, entry));
+        }
+        rows
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let [info, tabs, main, footer] = Layout::vertical([
+        Constraint::Length(INFO_HEIGHT),
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(2),
+    ])
+    .areas(frame.area());
+
+    let title = format!(" MDC RegFix: {} ", app.path);
+    match &app.analysis {
+        Some(analysis) => draw_file_info(frame, info, &title, &analysis.file_info),
+        None => frame.render_widget(Paragraph::new(app.status.as_str()).block(Block::bordered().title(title)), info),
+    }
+
+    let issue_count = app.analysis.as_ref().map_or(0, |a| a.issues.len());
+    let titles = [format!(" Issues ({}) ", issue_count), " Keys ".to_string()];
+    let selected_tab = if app.view == View::Issues { 0 } else { 1 };
+    let highlight = Style::new().fg(Color::Black).bg(Color::Cyan);
+    frame.render_widget(Tabs::new(titles).select(selected_tab).highlight_style(highlight), tabs);
+
+    match app.view {
+        View::Issues => draw_issues(frame, main, app),
+        View::Keys => draw_keys(frame, main, app),
+    }
+
+    let help = match (&app.dialog, app.view) {
+        (Some(Dialog::Confirm(_)), _) => "y apply  n go back",
+        (Some(Dialog::Outcome(_)), _) => "↑↓ scroll  Enter close",
+        (None, View::Issues) => "↑↓ move  Space tick  a all  n none  Enter apply ticked fixes  Tab keys  q quit",
+        (None, View::Keys) => "↑↓ move  → expand  ← collapse  Tab issues  q quit",
+    };
+    let footer_text = vec![Line::from(app.status.as_str()), Line::styled(help, Style::new().fg(Color::DarkGray))];
+    frame.render_widget(Paragraph::new(footer_text), footer);
+
+    match &app.dialog {
+        Some(Dialog::Confirm(selected)) => draw_confirm(frame, app, selected),
+        Some(Dialog::Outcome(lines)) => draw_dialog(frame, " Repair ", lines.clone(), app.dialog_scroll),
+        None => {}
+    }
+}
+
+fn draw_file_info(frame: &mut Frame, area: Rect, title: &str, info: &FileInfo) {
+    let mismatch = |differs: bool| if differs { Style::new().fg(Color::Red) } else { Style::new() };
+    let rows = [
+        ("Size", format!("{} bytes", info.size), Style::new()),
+        ("Signature", info.signature.clone(), mismatch(info.signature != "regf")),
+        (
+            "Sequence numbers",
+            format!("{} (primary) vs {} (secondary)", info.primary_seq_num, info.secondary_seq_num),
+            mismatch(info.primary_seq_num != info.secondary_seq_num),
+        ),
+        ("Last written", hive::format_filetime(info.last_written), Style::new()),
+        ("Version", format!("{}.{}", info.major_version, info.minor_version), Style::new()),
+        (
+            "Hive bins size",
+            format!("{} bytes (stored) vs {} bytes (measured)", info.hive_bins_size, info.measured_hive_bins_size),
+            mismatch(info.hive_bins_size != info.measured_hive_bins_size),
+        ),
+        (
+            "Checksum",
+            format!("0x{:08X} (stored) vs 0x{:08X} (calculated)", info.stored_checksum, info.calculated_checksum),
+            mismatch(info.stored_checksum != info.calculated_checksum),
+        ),
+    ];
+    let rows = rows.into_iter().map(|(field, value, style)| Row::new([Span::raw(field), Span::styled(value, style)]));
+    let table = Table::new(rows, [Constraint::Length(18), Constraint::Min(0)]).block(Block::bordered().title(title));
+    frame.render_widget(table, area);
+}
+
+fn risk_color(risk: FixRisk) -> Color {
+    match risk {
+        FixRisk::Low => Color::Green,
+        FixRisk::Medium => Color::Yellow,
+        FixRisk::High => Color::Red,
+    }
+}
+
+fn draw_issues(frame: &mut Frame, area: Rect, app: &mut App) {
+    let Some(analysis) = &app.analysis else {
+        frame.render_widget(Paragraph::new("The hive could not be analyzed.").block(Block::bordered()), area);
+        return;
+    };
+    if analysis.issues.is_empty() {
+        frame.render_widget(Paragraph::new("No issues found.").block(Block::bordered()), area);
+        return;
+    }
+    let [list_area, details_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(DETAILS_HEIGHT)]).areas(area);
+
+    let items: Vec<ListItem> = analysis.issues.iter().enumerate()
+        .map(|(i, issue)| {
+            let tick = match (&issue.fix_type, app.selections[i]) {
+                (Some(_), true) => "[x] ",
+                (Some(_), false) => "[ ] ",
+                (None, _) => "    ",
+            };
+            let severity_color = match issue.severity {
+                IssueSeverity::Critical => Color::Red,
+                IssueSeverity::Warning => Color::Yellow,
+            };
+            let fix = match &issue.fix_type {
+                Some(fix) => Span::styled(format!("  {}", fix.risk().label()), Style::new().fg(risk_color(fix.risk()))),
+                None => Span::styled("  manual repair", Style::new().fg(Color::DarkGray)),
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw(tick),
+                Span::styled(format!("{:<9}", issue.severity.to_string()), Style::new().fg(severity_color)),
+                Span::raw(format!("{} [{}]", issue.message, issue.code)),
+                fix,
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::bordered())
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, list_area, &mut app.issues);
+
+    let mut details = Vec::new();
+    if let Some(issue) = app.issues.selected().and_then(|i| analysis.issues.get(i)) {
+        if let Some(location) = &issue.location {
+            details.push(Line::from(format!("At {}", location)));
+        }
+        if let Some(text) = &issue.details {
+            details.push(Line::from(text.clone()));
+        }
+        match &issue.fix_type {
+            Some(fix) => details.push(Line::from(fix.risk().description())),
+            None => details.push(Line::from(format!("No automatic fix: {}", issue.code.guidance()))),
+        }
+    }
+    let details = Paragraph::new(details).wrap(Wrap { trim: true }).block(Block::bordered().title(" Details "));
+    frame.render_widget(details, details_area);
+}
+
+fn health_style(health: KeyHealth) -> Style {
+    match health {
+        KeyHealth::Healthy => Style::new(),
+        KeyHealth::Damaged => Style::new().fg(Color::Red),
+        KeyHealth::DamagedBelow => Style::new().fg(Color::Yellow),
+    }
+}
+
+fn draw_keys(frame: &mut Frame, area: Rect, app: &mut App) {
+    let rows = app.key_rows();
+    let browser = match &mut app.browser {
+        Ok(browser) => browser,
+        Err(e) => {
+            let text = format!("The key tree cannot be read: {}", e);
+            frame.render_widget(Paragraph::new(text).wrap(Wrap { trim: true }).block(Block::bordered()), area);
+            return;
+        }
+    };
+    let [tree_area, values_area] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(area);
+
+    let items: Vec<ListItem> = rows.iter()
+        .map(|(depth, entry)| {
+            let marker = match (entry.has_subkeys, app.expanded.contains(&entry.offset)) {
+                (false, _) => "  ",
+                (true, true) => "▾ ",
+                (true, false) => "▸ ",
+            };
+            let name = if entry.path.is_empty() { format!("{} (root)", entry.name) } else { entry.name.clone() };
+            let line = format!("{}{}{}", "  ".repeat(*depth), marker, name);
+            ListItem::new(Line::styled(line, health_style(browser.health(entry.offset))))
+        })
+        .collect();
+    let title = format!(" Keys ({} damaged) ", browser.damaged_count());
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, tree_area, &mut app.keys);
+
+    let Some((_, entry)) = app.keys.selected().and_then(|i| rows.get(i)) else {
+        frame.render_widget(Block::bordered(), values_area);
+        return;
+    };
+    let contents = browser.contents(entry);
+    let [problems_area, table_area] = Layout::vertical([
+        Constraint::Length(contents.problems.len().min(u16::MAX as usize) as u16),
+        Constraint::Min(0),
+    ])
+    .areas(values_area.inner(ratatui::layout::Margin::new(1, 1)));
+
+    let title = format!(" {} (written {}) ", entry.path, hive::format_filetime(contents.last_written));
+    frame.render_widget(Block::bordered().title(title), values_area);
+    let problems: Vec<Line> = contents.problems.iter().map(|p| Line::styled(p.clone(), Style::new().fg(Color::Red))).collect();
+    frame.render_widget(Paragraph::new(problems), problems_area);
+
+    let values = contents.values.iter().map(|value| {
+        let style = if value.problem.is_some() { Style::new().fg(Color::Red) } else { Style::new() };
+        Row::new([
+            value.display_name().to_string(),
+            hive::value_type_name(value.data_type),
+            value.problem.clone().unwrap_or_else(|| value.display_data()),
+        ])
+        .style(style)
+    });
+    let header = Row::new(["Name", "Type", "Data"]).style(Style::new().add_modifier(Modifier::BOLD));
+    let widths = [Constraint::Percentage(30), Constraint::Length(22), Constraint::Min(0)];
+    frame.render_widget(Table::new(values, widths).header(header), table_area);
+}
+
+fn draw_confirm(frame: &mut Frame, app: &App, selected: &[usize]) {
+    let Some(analysis) = &app.analysis else {
+        return;
+    };
+    let target = app.output_path.as_deref().unwrap_or(&app.path);
+    let mut lines = vec![
+        Line::from(format!("Write fixes for {} issue(s) to {}?", selected.len(), target)),
+        match &app.output_path {
+            Some(_) => Line::from(format!("{} is copied there first and not changed.", app.path)),
+            None => Line::from(format!("A backup is made first at {}.backup.", app.path)),
+        },
+        Line::default(),
+    ];
+    for issue in selected.iter().filter_map(|&i| analysis.issues.get(i)) {
+        let Some(fix) = &issue.fix_type else {
+            continue;
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:<12}", fix.risk().label()), Style::new().fg(risk_color(fix.risk()))),
+            Span::raw(issue.message.clone()),
+        ]));
+    }
+    let narrowed = registry::select_issues(analysis, selected);
+    for (a, b) in registry::conflicting_fixes(&narrowed.issues, &registry::fix_types(&narrowed)) {
+        lines.push(Line::default());
+        lines.push(Line::styled(
+            format!(
+                "'{}' and '{}' touch the same bytes and will be refused together.",
+                narrowed.issues[a].message, narrowed.issues[b].message
+            ),
+            Style::new().fg(Color::Red),
+        ));
+    }
+    draw_dialog(frame, " Apply fixes ", lines, 0);
+}
+
+fn draw_dialog(frame: &mut Frame, title: &str, lines: Vec<Line>, scroll: u16) {
+    let [area] = Layout::vertical([Constraint::Percentage(DIALOG_PERCENT)]).flex(Flex::Center).areas(frame.area());
+    let [area] = Layout::horizontal([Constraint::Percentage(DIALOG_PERCENT)]).flex(Flex::Center).areas(area);
+    frame.render_widget(Clear, area);
+    let dialog = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll, 0))
+        .block(Block::bordered().title(title));
+    frame.render_widget(dialog, area);
+}
@@ -1,19 +1,133 @@
 use std::path::PathBuf;
+use crate::browse::HiveBrowser;
+use crate::compare::Comparison;
+use crate::diff::HiveDiff;
+use crate::header::FieldChange;
+use crate::hive::BASE_BLOCK_SIZE;
+use crate::pagemap::PageMap;
+use crate::preview::ByteChange;
+use crate::progress::{Monitor, Progress};
 
 #[derive(Debug, Clone)]
 pub struct ValidationIssue {
+    pub code: IssueCode,
     pub severity: IssueSeverity,
     pub message: String,
     pub details: Option<String>,
+    /// Where the problem is, when it can be pinned down
+    pub location: Option<IssueLocation>,
     pub fix_type: Option<FixType>,
     pub fix_data: Option<FixData>,
 }
 
+/// Machine-readable kind of an issue, stable across releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IssueCode {
+    InvalidSignature,
+    ChecksumMismatch,
+    HiveBinsSizeMismatch,
+    SequenceMismatch,
+    DamagedBinChain,
+    BrokenReference,
+    OrphanedKey,
+    OrphanedValue,
+}
+
+impl IssueCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueCode::InvalidSignature => "invalid_signature",
+            IssueCode::ChecksumMismatch => "checksum_mismatch",
+            IssueCode::HiveBinsSizeMismatch => "hive_bins_size_mismatch",
+            IssueCode::SequenceMismatch => "sequence_mismatch",
+            IssueCode::DamagedBinChain => "damaged_bin_chain",
+            IssueCode::BrokenReference => "broken_reference",
+            IssueCode::OrphanedKey => "orphaned_key",
+            IssueCode::OrphanedValue => "orphaned_value",
+        }
+    }
+
+    /// What to try when an issue has no automatic fix
+    pub fn guidance(&self) -> &'static str {
+        match self {
+            IssueCode::InvalidSignature => "The base block is unusable. Restore the hive from a backup or a RegBack copy, or use `merge` to combine several damaged copies.",
+            IssueCode::DamagedBinChain => "Cells after the damaged bin cannot be reached. Use `merge` with other copies of this hive, or `rebuild` to write a new hive from everything readable.",
+            IssueCode::BrokenReference => "Use `graft` to copy the affected key from a healthy copy of the same hive, or `rebuild` to write a new hive from the keys that can still be read.",
+            IssueCode::ChecksumMismatch
+            | IssueCode::HiveBinsSizeMismatch
+            | IssueCode::SequenceMismatch => "Apply the header fix offered for this issue.",
+            IssueCode::OrphanedKey | IssueCode::OrphanedValue => "Apply the recovery fix, or `carve` the hive to review the data first.",
+        }
+    }
+}
+
+impl std::fmt::Display for IssueCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The bytes an issue concerns. Offsets are file offsets; cell offsets are
+/// relative to the start of the hive bins, as in the regf format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssueLocation {
+    pub offset: u64,
+    pub length: u64,
+    pub cell: Option<u32>,
+    /// Path of the key concerned, relative to the root
+    pub key_path: Option<String>,
+}
+
+impl IssueLocation {
+    /// Bytes of the base block
+    pub fn header(offset: u64, length: u64) -> Self {
+        Self { offset, length, cell: None, key_path: None }
+    }
+
+    /// A whole cell, size field included
+    pub fn cell(cell: u32, size: u32, key_path: Option<String>) -> Self {
+        Self { offset: (BASE_BLOCK_SIZE as u32 + cell) as u64, length: size as u64, cell: Some(cell), key_path }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+
+    pub fn overlaps(&self, other: &IssueLocation) -> bool {
+        self.offset < other.end() && other.offset < self.end()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "offset": self.offset,
+            "length": self.length,
+            "cell": self.cell,
+            "key_path": self.key_path,
+        })
+    }
+}
+
+impl std::fmt::Display for IssueLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bytes 0x{:X}-0x{:X}", self.offset, self.end().saturating_sub(1))?;
+        if let Some(cell) = self.cell {
+            write!(f, ", cell 0x{:X}", cell)?;
+        }
+        if let Some(path) = &self.key_path {
+            write!(f, ", key {}", if path.is_empty() { "(root)" } else { path })?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum FixData {
     HiveBinsSize(u32),
     Checksum(u32),
     SequenceNumbers(u32, u32),
+    OrphanedCell(u32),
+    // Orphans summarised in a single issue
+    OrphanedCells(Vec<u32>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -21,6 +135,45 @@ pub enum FixType {
     HiveBinsSize,
     Checksum,
     SequenceNumbers,
+    RecoverOrphans,
+}
+
+impl FixType {
+    pub fn risk(&self) -> FixRisk {
+        match self {
+            FixType::Checksum | FixType::SequenceNumbers => FixRisk::Low,
+            FixType::HiveBinsSize => FixRisk::Medium,
+            FixType::RecoverOrphans => FixRisk::High,
+        }
+    }
+}
+
+/// How far a fix reaches into the hive, used to group fixes in the repair wizard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FixRisk {
+    Low,
+    Medium,
+    High,
+}
+
+impl FixRisk {
+    pub const ALL: [FixRisk; 3] = [FixRisk::Low, FixRisk::Medium, FixRisk::High];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FixRisk::Low => "Low risk",
+            FixRisk::Medium => "Medium risk",
+            FixRisk::High => "High risk",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FixRisk::Low => "Rewrites header fields that can be worked out from the rest of the file.",
+            FixRisk::Medium => "Changes how much of the file Windows treats as hive data.",
+            FixRisk::High => "Allocates new cells and links orphaned data back into the key tree.",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -38,12 +191,35 @@ impl std::fmt::Display for IssueSeverity {
     }
 }
 
+/// Identifies a GUI tab, so the results of background work reach the tab
+/// that started it even after the user has switched to another
+pub type TabId = u64;
+
 #[derive(Debug)]
 pub enum Message {
+    /// Opens the hive in a tab, or switches to the tab already showing it
     FileSelected(PathBuf),
-    AnalysisComplete(AnalysisResult),
-    FixSelected(Vec<FixType>),
-    FixComplete(String),
+    AnalysisComplete(TabId, AnalysisResult),
+    /// Indices into the analysed issues whose fixes should be applied
+    FixSelected(TabId, Vec<usize>, Option<PathBuf>),
+    FixComplete(TabId, String),
+    CompareSelected(Vec<PathBuf>),
+    ComparisonComplete(Comparison),
+    DiffSelected(PathBuf, PathBuf),
+    DiffComplete(Result<HiveDiff, String>),
+    BrowserReady(TabId, Result<HiveBrowser, String>),
+    FixPreviewReady(TabId, usize, Result<Vec<ByteChange>, String>),
+    /// Base block fields to write, with an optional output copy
+    HeaderEditSelected(TabId, Vec<FieldChange>, Option<PathBuf>),
+    PageMapReady(TabId, Result<PageMap, String>),
+    Progress(TabId, Progress),
+    /// An operation ended without a result, such as a cancelled analysis,
+    /// with the status to show if any
+    OperationStopped(TabId, Monitor, Option<String>),
+    /// Path of a verified backup made by the repair wizard
+    BackupComplete(TabId, Result<String, String>),
+    /// Analysis of the repaired file or copy, sent just before its FixComplete
+    RepairValidated(TabId, AnalysisResult),
 }
 
 #[derive(Debug, Clone)]